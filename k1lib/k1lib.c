@@ -34,25 +34,38 @@ void _k1_free(void* ptr) {
 
 // Passing struct; not guaranteed ABI
 // One day pass by reference OR pass each field
+// Returns a string with NULL data if the file can't be opened or read
 K1String _k1_readFileToString(K1String filename) {
+    K1String failed = { .len = 0, .data = NULL };
     char* as_cstring = malloc(filename.len + 1);
     // snprintf(as_cstring, filename.len, "%s", filename.data);
     memcpy(as_cstring, filename.data, filename.len);
     as_cstring[filename.len] = '\0';
 
     FILE* file = fopen(as_cstring, "r");
+    free(as_cstring);
+    if (file == NULL) {
+        return failed;
+    }
     fseek(file, 0, SEEK_END);
     long fsize = ftell(file);
+    if (fsize < 0) {
+        fclose(file);
+        return failed;
+    }
     char* buf = malloc(fsize);
 
     fseek(file, 0, SEEK_SET);
-    fread(buf, fsize, 1, file);
+    size_t read = fread(buf, 1, fsize, file);
+    fclose(file);
+    if (read != (size_t)fsize) {
+        free(buf);
+        return failed;
+    }
     K1String string = {
         .len = fsize,
         .data = buf
     };
-    fclose(file);
-    free(as_cstring);
     return string;
 }
 
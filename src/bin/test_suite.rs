@@ -11,6 +11,7 @@ use clap::Parser;
 use colored::Colorize;
 use inkwell::context::Context;
 use k1::compiler;
+use k1::typer::TypedModule;
 use std::os::unix::prelude::ExitStatusExt;
 
 #[derive(Parser, Debug, Clone)]
@@ -31,8 +32,10 @@ pub struct TestSuiteClapArgs {
 #[derive(Debug)]
 enum TestExpectation {
     ExitCode(i32),
-    CompileErrorMessage {
-        message: String,
+    /// A single `//errmsg:` line must match the first error. With several, there must be
+    /// exactly that many errors, each matching its line in order
+    CompileErrorMessages {
+        messages: Vec<String>,
     },
     AbortErrorMessage {
         message: String,
@@ -68,8 +71,15 @@ fn get_test_expectation(test_file: &Path) -> TestExpectation {
     let exit_code_prefix = "//exitcode: ";
     let abort_msg_prefix = "//abortmsg: ";
     if last_line.starts_with(error_message_prefix) {
-        let expected_error: String = last_line.chars().skip(error_message_prefix.len()).collect();
-        TestExpectation::CompileErrorMessage { message: expected_error }
+        let mut expected_errors: Vec<String> = src
+            .lines()
+            .rev()
+            .filter(|l| !l.is_empty())
+            .take_while(|l| l.starts_with(error_message_prefix))
+            .map(|l| l.chars().skip(error_message_prefix.len()).collect())
+            .collect();
+        expected_errors.reverse();
+        TestExpectation::CompileErrorMessages { messages: expected_errors }
     } else if last_line.starts_with(exit_code_prefix) {
         let s: String = last_line.chars().skip(exit_code_prefix.len()).collect();
         let as_i32: i32 = s.parse().unwrap();
@@ -82,6 +92,44 @@ fn get_test_expectation(test_file: &Path) -> TestExpectation {
     }
 }
 
+/// Warnings are expected with `//warnmsg:` lines, which can come before the final expectation
+fn get_expected_warnings(test_file: &Path) -> Vec<String> {
+    let src = std::fs::read_to_string(test_file).expect("could not read source file for test {}");
+    let warning_message_prefix = "//warnmsg: ";
+    src.lines()
+        .filter(|l| l.starts_with(warning_message_prefix))
+        .map(|l| l.chars().skip(warning_message_prefix.len()).collect())
+        .collect()
+}
+
+/// A `//deny-warnings` line compiles the test with `--deny-warnings`
+fn is_deny_warnings_test(test_file: &Path) -> bool {
+    let src = std::fs::read_to_string(test_file).expect("could not read source file for test {}");
+    src.lines().any(|l| l == "//deny-warnings")
+}
+
+/// A test with any `//warnmsg:` lines must report exactly those warnings, in order
+fn check_warnings(filename: &str, module: &TypedModule, expected: &[String]) -> Result<()> {
+    if expected.is_empty() {
+        return Ok(());
+    }
+    let warnings: Vec<&str> = module.warnings.iter().map(|w| w.message.as_str()).collect();
+    if warnings.len() != expected.len() {
+        bail!(
+            "{filename}: Expected {} warnings but got {}: {:?}",
+            expected.len(),
+            warnings.len(),
+            warnings
+        )
+    }
+    for (warning, message) in warnings.iter().zip(expected.iter()) {
+        if !warning.contains(message) {
+            bail!("{filename}: Warned with unexpected message: {}", warning)
+        }
+    }
+    Ok(())
+}
+
 fn test_file<P: AsRef<Path>>(ctx: &Context, path: P, interpret: bool) -> Result<()> {
     let out_dir = ".k1-out/test_suite";
     let filename = path.as_ref().file_name().unwrap().to_str().unwrap();
@@ -94,33 +142,64 @@ fn test_file<P: AsRef<Path>>(ctx: &Context, path: P, interpret: bool) -> Result<
         run: false,
         file: path.as_ref().to_owned(),
         gui: false,
+        message_format: compiler::MessageFormat::Human,
+        warn: vec![],
+        allow: vec![],
+        deny_warnings: is_deny_warnings_test(path.as_ref()),
     };
     let compile_result = compiler::compile_module(&args);
     let expectation = get_test_expectation(path.as_ref());
+    let expected_warnings = get_expected_warnings(path.as_ref());
     match compile_result {
         Err(err) => match err.module.as_ref() {
             Some(module) => {
-                let err = &module.errors[0];
+                check_warnings(filename, module, &expected_warnings)?;
+                // Lex and syntax errors come first; the typer still runs over the partial module
+                let mut all_errors: Vec<String> = module
+                    .ast
+                    .lex_errors
+                    .iter()
+                    .map(|e| e.to_string())
+                    .chain(module.ast.errors.iter().map(|e| e.to_string()))
+                    .chain(module.errors.iter().map(|e| e.to_string()))
+                    .collect();
+                // Denied warnings fail compilation on their own, so they stand in for errors
+                if all_errors.is_empty() && args.deny_warnings {
+                    all_errors.extend(module.warnings.iter().map(|w| w.message.clone()));
+                }
+                let err = &all_errors[0];
                 match expectation {
-                    TestExpectation::CompileErrorMessage { message } => {
+                    TestExpectation::CompileErrorMessages { messages } if messages.len() == 1 => {
                         // Check for message!
-                        if !err.to_string().contains(&message) {
-                            bail!("{filename}: Failed with unexpected message: {}", err.to_string())
+                        if !err.contains(&messages[0]) {
+                            bail!("{filename}: Failed with unexpected message: {}", err)
+                        }
+                    }
+                    TestExpectation::CompileErrorMessages { messages } => {
+                        if all_errors.len() != messages.len() {
+                            bail!(
+                                "{filename}: Expected {} errors but got {}: {:?}",
+                                messages.len(),
+                                all_errors.len(),
+                                all_errors
+                            )
+                        }
+                        for (err, message) in all_errors.iter().zip(messages.iter()) {
+                            if !err.contains(message) {
+                                bail!("{filename}: Failed with unexpected message: {}", err)
+                            }
                         }
                     }
                     TestExpectation::CompileErrorLine { .. } => {
                         unimplemented!("error line test")
                     }
                     TestExpectation::AbortErrorMessage { .. } => {
-                        bail!(
-                            "{filename}: Expected abort but got compile error: {}",
-                            err.to_string()
-                        )
+                        bail!("{filename}: Expected abort but got compile error: {}", err)
                     }
                     TestExpectation::ExitCode(expected_code) => bail!(
                         "{filename}: Expected exit code {} but got compile error: {}",
                         expected_code,
-                        err.to_string()
+                        err
                     ),
                 }
             }
@@ -129,6 +208,7 @@ fn test_file<P: AsRef<Path>>(ctx: &Context, path: P, interpret: bool) -> Result<
             }
         },
         Ok(typed_module) => {
+            check_warnings(filename, &typed_module, &expected_warnings)?;
             let name = typed_module.name();
             let expect_exit = matches!(
                 expectation,
@@ -18,14 +18,14 @@ use inkwell::module::{Linkage as LlvmLinkage, Module as LlvmModule};
 use inkwell::passes::PassManager;
 use inkwell::targets::{InitializationConfig, Target, TargetData, TargetMachine};
 use inkwell::types::{
-    AnyType, AnyTypeEnum, BasicMetadataTypeEnum, BasicType, BasicTypeEnum, IntType, PointerType,
-    StructType, VoidType,
+    AnyType, AnyTypeEnum, BasicMetadataTypeEnum, BasicType, BasicTypeEnum,
+    FunctionType as LlvmFunctionType, IntType, PointerType, StructType, VoidType,
 };
 use inkwell::values::{
-    ArrayValue, BasicMetadataValueEnum, BasicValue, BasicValueEnum, FunctionValue, GlobalValue,
-    InstructionValue, IntValue, PointerValue, StructValue,
+    ArrayValue, BasicMetadataValueEnum, BasicValue, BasicValueEnum, CallSiteValue, FunctionValue,
+    GlobalValue, InstructionValue, IntValue, PointerValue, StructValue,
 };
-use inkwell::{AddressSpace, IntPredicate, OptimizationLevel};
+use inkwell::{AddressSpace, FloatPredicate, IntPredicate, OptimizationLevel};
 use log::{debug, info, trace};
 
 use crate::lex::SpanId;
@@ -374,6 +374,15 @@ impl<'ctx> BuiltinTypes<'ctx> {
     }
 }
 
+/// Where `continue` and `break` jump to, for a loop being generated
+#[derive(Debug, Clone, Copy)]
+struct LoopBlocks<'ctx> {
+    cond_block: BasicBlock<'ctx>,
+    end_block: BasicBlock<'ctx>,
+    /// How many blocks' defers were pending outside of the loop; leaving the loop runs the rest
+    defer_depth: usize,
+}
+
 struct LibcFunctions<'ctx> {
     printf: FunctionValue<'ctx>,
     exit: FunctionValue<'ctx>,
@@ -387,12 +396,20 @@ pub struct Codegen<'ctx, 'module> {
     llvm_machine: TargetMachine,
     builder: Builder<'ctx>,
     llvm_functions: HashMap<FunctionId, FunctionValue<'ctx>>,
+    /// Wrappers that give plain functions the closure calling convention, so that they can be
+    /// used as function values
+    llvm_env_thunks: HashMap<FunctionId, FunctionValue<'ctx>>,
     llvm_types: RefCell<HashMap<TypeId, LlvmType<'ctx>>>,
     /// The type of stored pointers here should be one level higher than the type of the
     /// value pointed to. This is so that it can be used reliably, without matching or
     /// checking, as a Pointer to the actual type
     variables: HashMap<VariableId, Pointer<'ctx>>,
     globals: HashMap<VariableId, GlobalValue<'ctx>>,
+    /// Keyed by the scope of the loop's body
+    loops: HashMap<ScopeId, LoopBlocks<'ctx>>,
+    /// The deferred expressions of each block being generated in the current function,
+    /// innermost block last
+    defer_frames: Vec<Vec<TypedExpr>>,
     libc_functions: LibcFunctions<'ctx>,
     builtin_globals: HashMap<String, GlobalValue<'ctx>>,
     builtin_types: BuiltinTypes<'ctx>,
@@ -637,7 +654,10 @@ impl<'ctx, 'module> Codegen<'ctx, 'module> {
             builder,
             variables: pointers,
             globals,
+            loops: HashMap::new(),
+            defer_frames: Vec::new(),
             llvm_functions: HashMap::new(),
+            llvm_env_thunks: HashMap::new(),
             llvm_types: RefCell::new(HashMap::new()),
             libc_functions: LibcFunctions { printf, exit, memcmp },
             builtin_globals,
@@ -1184,6 +1204,7 @@ impl<'ctx, 'module> Codegen<'ctx, 'module> {
                 Ok(LlvmVoidType { void_type: self.ctx.void_type(), di_type }.into())
             }
             Type::OpaqueAlias(opaque) => self.codegen_type_inner(opaque.aliasee, depth + 1),
+            Type::Error => panic!("Cannot codegen a module with type errors"),
             Type::RecursiveReference(rr) => {
                 if depth == 0 {
                     // If this is not a recursive call, we already built this type
@@ -1219,7 +1240,41 @@ impl<'ctx, 'module> Codegen<'ctx, 'module> {
             Type::Generic(_) => {
                 panic!("Cannot codegen a Generic; something went wrong in typecheck")
             }
-            Type::Function(_) => todo!("Cannot yet codegen a physical Function type"),
+            Type::Function(_) => {
+                // Function values are a pointer to the code, and a pointer to the closure's
+                // environment, which is null for functions that do not capture anything
+                let ptr_type = self.builtin_types.ptr.as_basic_type_enum();
+                let closure_struct_type = self.ctx.struct_type(&[ptr_type, ptr_type], false);
+                let placeholder_pointee = self.codegen_type(U64_TYPE_ID)?.debug_type();
+                let pointer_di_type = |name: &str| {
+                    self.debug
+                        .debug_builder
+                        .create_pointer_type(
+                            name,
+                            placeholder_pointee,
+                            self.builtin_types.ptr_sized_int.get_bit_width() as u64,
+                            WORD_SIZE_BITS as u32,
+                            AddressSpace::default(),
+                        )
+                        .as_type()
+                };
+                let di_type = self.make_debug_struct_type(
+                    "closure",
+                    &closure_struct_type,
+                    span,
+                    &[
+                        StructDebugMember { name: "fn", di_type: pointer_di_type("fn") },
+                        StructDebugMember { name: "env", di_type: pointer_di_type("env") },
+                    ],
+                );
+                Ok(LlvmValueType {
+                    type_id,
+                    basic_type: closure_struct_type.as_basic_type_enum(),
+                    size: self.size_info(&closure_struct_type),
+                    di_type,
+                }
+                .into())
+            }
         }?;
         if !no_cache {
             self.llvm_types.borrow_mut().insert(type_id, codegened_type.clone());
@@ -1549,6 +1604,11 @@ impl<'ctx, 'module> Codegen<'ctx, 'module> {
                         let promoted = self.i1_to_bool(negated, "negated");
                         Ok(promoted.as_basic_value_enum().into())
                     }
+                    UnaryOpKind::FloatNegation => {
+                        let negated =
+                            self.builder.build_float_neg(value.into_float_value(), "fneg");
+                        Ok(negated.as_basic_value_enum().into())
+                    }
                     UnaryOpKind::ReferenceToInt => {
                         let as_int = self.builder.build_ptr_to_int(
                             value.into_pointer_value(),
@@ -1700,13 +1760,99 @@ impl<'ctx, 'module> Codegen<'ctx, 'module> {
                         );
                         Ok(as_ptr.as_basic_value_enum().into())
                     }
+                    CastType::FloatExtend | CastType::FloatTruncate => {
+                        let float_value =
+                            self.codegen_expr_basic_value(&cast.base_expr)?.into_float_value();
+                        let float_type = self.codegen_type(cast.target_type_id)?.value_basic_type();
+                        let value = self.builder.build_float_cast(
+                            float_value,
+                            float_type.into_float_type(),
+                            "float_cast",
+                        );
+                        Ok(value.as_basic_value_enum().into())
+                    }
+                    CastType::IntegerToFloat => {
+                        let int_value =
+                            self.codegen_expr_basic_value(&cast.base_expr)?.into_int_value();
+                        let float_type = self
+                            .codegen_type(cast.target_type_id)?
+                            .value_basic_type()
+                            .into_float_type();
+                        let from_integer_type =
+                            self.module.types.get(cast.base_expr.get_type()).expect_integer();
+                        let value = if from_integer_type.is_signed() {
+                            self.builder.build_signed_int_to_float(int_value, float_type, "itof")
+                        } else {
+                            self.builder.build_unsigned_int_to_float(int_value, float_type, "itof")
+                        };
+                        Ok(value.as_basic_value_enum().into())
+                    }
+                    CastType::FloatToInteger => {
+                        let float_value =
+                            self.codegen_expr_basic_value(&cast.base_expr)?.into_float_value();
+                        let int_type = self
+                            .codegen_type(cast.target_type_id)?
+                            .value_basic_type()
+                            .into_int_type();
+                        let to_integer_type =
+                            self.module.types.get(cast.target_type_id).expect_integer();
+                        let value = if to_integer_type.is_signed() {
+                            self.builder.build_float_to_signed_int(float_value, int_type, "ftoi")
+                        } else {
+                            self.builder.build_float_to_unsigned_int(float_value, int_type, "ftoi")
+                        };
+                        Ok(value.as_basic_value_enum().into())
+                    }
                 }
             }
             TypedExpr::Return(ret) => {
                 let return_value = self.codegen_expr_basic_value(&ret.value)?;
+                self.codegen_defers(0)?;
                 let ret_inst = self.builder.build_return(Some(&return_value));
                 Ok(LlvmValue::Never(ret_inst))
             }
+            TypedExpr::Break(brk) => {
+                if brk.value.is_some() {
+                    return err!(brk.span, "break values should have been lowered by the typer");
+                }
+                let loop_blocks = *self.loops.get(&brk.loop_scope_id).expect("break target loop");
+                self.codegen_defers(loop_blocks.defer_depth)?;
+                let branch = self.builder.build_unconditional_branch(loop_blocks.end_block);
+                Ok(LlvmValue::Never(branch))
+            }
+            TypedExpr::Continue(cont) => {
+                let loop_blocks =
+                    *self.loops.get(&cont.loop_scope_id).expect("continue target loop");
+                self.codegen_defers(loop_blocks.defer_depth)?;
+                let branch = self.builder.build_unconditional_branch(loop_blocks.cond_block);
+                Ok(LlvmValue::Never(branch))
+            }
+            TypedExpr::Error(_) => panic!("Cannot codegen a module with type errors"),
+            TypedExpr::FunctionReference(fun_ref) => {
+                let function = self.module.get_function(fun_ref.function_id);
+                let function_value = self.codegen_function(fun_ref.function_id, function)?;
+                let (function_value, env_ptr) = match &function.closure_env {
+                    None => (
+                        self.codegen_env_thunk(fun_ref.function_id, function_value)?,
+                        self.builtin_types.ptr.const_null(),
+                    ),
+                    Some(closure_env) => (function_value, self.codegen_closure_env(closure_env)?),
+                };
+                let function_ptr = function_value.as_global_value().as_pointer_value();
+                let closure_type =
+                    self.codegen_type(fun_ref.type_id)?.value_basic_type().into_struct_type();
+                let closure = self
+                    .builder
+                    .build_insert_value(closure_type.get_undef(), function_ptr, 0, "closure_fn")
+                    .unwrap()
+                    .into_struct_value();
+                let closure = self
+                    .builder
+                    .build_insert_value(closure, env_ptr, 1, "closure_env")
+                    .unwrap()
+                    .into_struct_value();
+                Ok(closure.as_basic_value_enum().into())
+            }
         }
     }
 
@@ -1749,6 +1895,53 @@ impl<'ctx, 'module> Codegen<'ctx, 'module> {
                 };
                 Ok(op_res.as_basic_value_enum().into())
             }
+            Type::Float(_) => {
+                let lhs_value = self.codegen_expr_basic_value(&bin_op.lhs)?.into_float_value();
+                let rhs_value = self.codegen_expr_basic_value(&bin_op.rhs)?.into_float_value();
+                let op_res = match bin_op.kind {
+                    BinaryOpKind::Add => self.builder.build_float_add(lhs_value, rhs_value, "fadd"),
+                    BinaryOpKind::Subtract => {
+                        self.builder.build_float_sub(lhs_value, rhs_value, "fsub")
+                    }
+                    BinaryOpKind::Multiply => {
+                        self.builder.build_float_mul(lhs_value, rhs_value, "fmul")
+                    }
+                    BinaryOpKind::Divide => {
+                        self.builder.build_float_div(lhs_value, rhs_value, "fdiv")
+                    }
+                    BinaryOpKind::Rem => self.builder.build_float_rem(lhs_value, rhs_value, "frem"),
+                    _ => {
+                        panic!("Unsupported bin op kind returning float: {}", bin_op.kind)
+                    }
+                };
+                Ok(op_res.as_basic_value_enum().into())
+            }
+            Type::Bool(_)
+                if matches!(self.module.types.get(bin_op.lhs.get_type()), Type::Float(_)) =>
+            {
+                let lhs_value = self.codegen_expr_basic_value(&bin_op.lhs)?.into_float_value();
+                let rhs_value = self.codegen_expr_basic_value(&bin_op.rhs)?.into_float_value();
+                // Ordered comparisons are false if either side is NaN, except for !=
+                let pred = match bin_op.kind {
+                    BinaryOpKind::Equals => FloatPredicate::OEQ,
+                    BinaryOpKind::NotEquals => FloatPredicate::UNE,
+                    BinaryOpKind::Less => FloatPredicate::OLT,
+                    BinaryOpKind::LessEqual => FloatPredicate::OLE,
+                    BinaryOpKind::Greater => FloatPredicate::OGT,
+                    BinaryOpKind::GreaterEqual => FloatPredicate::OGE,
+                    other => panic!("Unsupported binary operation {other:?} on floats"),
+                };
+                let i1_compare = self.builder.build_float_compare(
+                    pred,
+                    lhs_value,
+                    rhs_value,
+                    &format!("{}_i1", bin_op.kind),
+                );
+                Ok(self
+                    .i1_to_bool(i1_compare, &format!("{}_res", bin_op.kind))
+                    .as_basic_value_enum()
+                    .into())
+            }
             Type::Bool(_) => match bin_op.kind {
                 BinaryOpKind::And | BinaryOpKind::Or => {
                     let lhs = self.codegen_expr_basic_value(&bin_op.lhs)?.into_int_value();
@@ -1943,7 +2136,13 @@ impl<'ctx, 'module> Codegen<'ctx, 'module> {
     }
 
     fn codegen_function_call(&mut self, call: &Call) -> CodegenResult<LlvmValue<'ctx>> {
-        let callee = self.module.get_function(call.callee_function_id);
+        let callee_function_id = match call.callee {
+            Callee::StaticFunction(function_id) => function_id,
+            Callee::DynamicFunction(variable_id) => {
+                return self.codegen_dynamic_function_call(variable_id, call);
+            }
+        };
+        let callee = self.module.get_function(callee_function_id);
 
         if let Some(intrinsic_type) = callee.intrinsic_type {
             if intrinsic_type.is_inlined() {
@@ -1951,7 +2150,7 @@ impl<'ctx, 'module> Codegen<'ctx, 'module> {
             }
         }
 
-        let function_value = self.codegen_function(call.callee_function_id, callee)?;
+        let function_value = self.codegen_function(callee_function_id, callee)?;
 
         let args: CodegenResult<Vec<BasicMetadataValueEnum<'ctx>>> = call
             .args
@@ -1970,6 +2169,151 @@ impl<'ctx, 'module> Codegen<'ctx, 'module> {
             })
             .collect();
         let callsite_value = self.builder.build_call(function_value, &args?, "call_ret");
+        self.call_site_result(callsite_value, call)
+    }
+
+    /// Copies the captured values, or pointers to the captured variables, into a fresh
+    /// environment.
+    ///
+    /// A closure that captures anything by reference can't outlive its defining function (the
+    /// typer rejects it), so its environment lives on that function's stack. Otherwise the
+    /// closure may escape, and its environment comes from `_k1_malloc` like any other heap value
+    fn codegen_closure_env(
+        &mut self,
+        closure_env: &ClosureEnvironment,
+    ) -> CodegenResult<PointerValue<'ctx>> {
+        let env_struct_type =
+            self.codegen_type(closure_env.env_type)?.value_basic_type().into_struct_type();
+        let env_ptr = if closure_env.find_reference_capture().is_some() {
+            self.build_entry_block_alloca(env_struct_type, "closure_env")
+        } else {
+            let env_size_bytes = self.size_info(&env_struct_type).size_bits / 8;
+            let env_size_value = self.ctx.i64_type().const_int(env_size_bytes as u64, false);
+            let k1_malloc = self.get_k1_malloc()?;
+            self.builder
+                .build_call(k1_malloc, &[env_size_value.into()], "closure_env")
+                .try_as_basic_value()
+                .left()
+                .unwrap()
+                .into_pointer_value()
+        };
+        for (index, capture) in closure_env.captures.iter().enumerate() {
+            let variable = *self.variables.get(&capture.variable_id).unwrap();
+            let field_value = match capture.mode {
+                CaptureMode::ByValue => variable.loaded_value(&self.builder),
+                CaptureMode::ByReference => variable.pointer.as_basic_value_enum(),
+            };
+            let field_ptr = self
+                .builder
+                .build_struct_gep(env_struct_type, env_ptr, index as u32, "capture")
+                .unwrap();
+            self.builder.build_store(field_ptr, field_value);
+        }
+        Ok(env_ptr)
+    }
+
+    /// Allocates in the current function's entry block rather than at the insertion point, so
+    /// that one reached on every iteration of a loop doesn't grow the stack each time around
+    fn build_entry_block_alloca(&self, ty: StructType<'ctx>, name: &str) -> PointerValue<'ctx> {
+        let current_fn = self.builder.get_insert_block().unwrap().get_parent().unwrap();
+        let entry_block = current_fn.get_first_basic_block().unwrap();
+        let entry_builder = self.ctx.create_builder();
+        match entry_block.get_first_instruction() {
+            Some(first_instruction) => entry_builder.position_before(&first_instruction),
+            None => entry_builder.position_at_end(entry_block),
+        }
+        entry_builder.build_alloca(ty, name)
+    }
+
+    fn get_k1_malloc(&mut self) -> CodegenResult<FunctionValue<'ctx>> {
+        let root_scope_id = self.module.scopes.get_root_scope_id();
+        let k1_malloc_id = self
+            .module
+            .ast
+            .identifiers
+            .get("_k1_malloc")
+            .and_then(|ident| self.module.scopes.find_function(root_scope_id, ident))
+            .expect("_k1_malloc is declared in core");
+        self.codegen_function(k1_malloc_id, self.module.get_function(k1_malloc_id))
+    }
+
+    /// Every function value is called with an environment, so a plain function gets a thunk
+    /// that drops it
+    fn codegen_env_thunk(
+        &mut self,
+        function_id: FunctionId,
+        function_value: FunctionValue<'ctx>,
+    ) -> CodegenResult<FunctionValue<'ctx>> {
+        if let Some(thunk) = self.llvm_env_thunks.get(&function_id) {
+            return Ok(*thunk);
+        }
+        let fn_ty = function_value.get_type();
+        let mut thunk_param_types: Vec<BasicMetadataTypeEnum<'ctx>> =
+            Vec::with_capacity(fn_ty.count_param_types() as usize + 1);
+        thunk_param_types.push(self.builtin_types.ptr.into());
+        thunk_param_types.extend(fn_ty.get_param_types().into_iter().map(|t| t.into()));
+        let thunk_ty = match fn_ty.get_return_type() {
+            None => self.ctx.void_type().fn_type(&thunk_param_types, false),
+            Some(ret_type) => ret_type.fn_type(&thunk_param_types, false),
+        };
+        let thunk_name = format!("{}__env_thunk", function_value.get_name().to_str().unwrap());
+        let thunk =
+            self.llvm_module.add_function(&thunk_name, thunk_ty, Some(LlvmLinkage::Private));
+        self.llvm_env_thunks.insert(function_id, thunk);
+
+        let maybe_starting_block = self.builder.get_insert_block();
+        let entry_block = self.ctx.append_basic_block(thunk, "entry");
+        self.builder.position_at_end(entry_block);
+        // The thunk has no debug info of its own
+        self.builder.unset_current_debug_location();
+        let args: Vec<BasicMetadataValueEnum<'ctx>> =
+            thunk.get_param_iter().skip(1).map(|param| param.into()).collect();
+        let ret = self.builder.build_call(function_value, &args, "call_ret");
+        match ret.try_as_basic_value().left() {
+            None => self.builder.build_return(None),
+            Some(value) => self.builder.build_return(Some(&value)),
+        };
+        if let Some(start_block) = maybe_starting_block {
+            self.builder.position_at_end(start_block);
+        }
+        Ok(thunk)
+    }
+
+    fn codegen_dynamic_function_call(
+        &mut self,
+        variable_id: VariableId,
+        call: &Call,
+    ) -> CodegenResult<LlvmValue<'ctx>> {
+        let closure = self.get_loaded_variable(variable_id).into_struct_value();
+        let variable_type = self.module.variables.get_variable(variable_id).type_id;
+        let Type::Function(function_type) = self.module.types.get(variable_type) else {
+            return err!(call.span, "Dynamic call through a non-function variable");
+        };
+        let param_types: CodegenResult<Vec<LlvmType<'ctx>>> =
+            function_type.params.iter().map(|param| self.codegen_type(param.type_id)).collect();
+        let param_types = param_types?;
+        let ret_type = self.codegen_type(function_type.return_type)?;
+        let closure_fn_ty = self.make_llvm_function_type(&param_types, &ret_type, true);
+
+        let function_ptr =
+            self.builder.build_extract_value(closure, 0, "fn").unwrap().into_pointer_value();
+        let env_ptr =
+            self.builder.build_extract_value(closure, 1, "env").unwrap().into_pointer_value();
+        let mut args: Vec<BasicMetadataValueEnum<'ctx>> = Vec::with_capacity(call.args.len() + 1);
+        args.push(env_ptr.into());
+        for arg_expr in call.args.iter() {
+            args.push(self.codegen_expr_basic_value(arg_expr)?.into());
+        }
+        let callsite_value =
+            self.builder.build_indirect_call(closure_fn_ty, function_ptr, &args, "call_ret");
+        self.call_site_result(callsite_value, call)
+    }
+
+    fn call_site_result(
+        &mut self,
+        callsite_value: CallSiteValue<'ctx>,
+        call: &Call,
+    ) -> CodegenResult<LlvmValue<'ctx>> {
         match callsite_value.try_as_basic_value() {
             either::Either::Left(value) => Ok(LlvmValue::BasicValue(value)),
             either::Either::Right(_instr) => {
@@ -2252,9 +2596,14 @@ impl<'ctx, 'module> Codegen<'ctx, 'module> {
         let unit_value = self.builtin_types.unit_value.as_basic_value_enum().into();
         let mut last: LlvmValue<'ctx> = unit_value;
         self.set_debug_location(block.span);
+        self.defer_frames.push(Vec::new());
         for stmt in &block.statements {
             match stmt {
                 TypedStmt::Expr(expr) => last = self.codegen_expr(expr)?,
+                TypedStmt::Defer(defer) => {
+                    self.defer_frames.last_mut().unwrap().push(defer.expr.clone());
+                    last = unit_value;
+                }
                 TypedStmt::ValDef(val_def) => {
                     let _value = self.codegen_val(val_def)?;
                     last = unit_value;
@@ -2300,16 +2649,56 @@ impl<'ctx, 'module> Codegen<'ctx, 'module> {
                     self.builder.build_conditional_branch(cond_i1, loop_body_block, loop_end_block);
 
                     self.builder.position_at_end(loop_body_block);
+                    self.loops.insert(
+                        while_stmt.block.scope_id,
+                        LoopBlocks {
+                            cond_block: loop_entry_block,
+                            end_block: loop_end_block,
+                            defer_depth: self.defer_frames.len(),
+                        },
+                    );
                     self.codegen_block_statements(&while_stmt.block)?;
-                    self.builder.build_unconditional_branch(loop_entry_block);
+                    self.loops.remove(&while_stmt.block.scope_id);
+                    // The body may already have jumped away, with a break or continue
+                    let body_end_block = self.builder.get_insert_block().unwrap();
+                    if body_end_block.get_terminator().is_none() {
+                        self.builder.build_unconditional_branch(loop_entry_block);
+                    }
 
                     self.builder.position_at_end(loop_end_block);
                 }
             }
         }
+        // Falling off the end of the block runs its own defers; every other exit has already
+        // run them, and terminated the current basic block
+        let has_fallthrough = self.builder.get_insert_block().unwrap().get_terminator().is_none();
+        if has_fallthrough {
+            self.codegen_defers(self.defer_frames.len() - 1)?;
+        }
+        self.defer_frames.pop();
         Ok(last)
     }
 
+    /// Emits a cleanup block that runs the deferred expressions of every block from the innermost
+    /// out to `depth`, each in reverse order. Called wherever control leaves those blocks
+    fn codegen_defers(&mut self, depth: usize) -> CodegenResult<()> {
+        let deferred: Vec<TypedExpr> = self.defer_frames[depth..]
+            .iter()
+            .rev()
+            .flat_map(|frame| frame.iter().rev().cloned())
+            .collect();
+        if deferred.is_empty() {
+            return Ok(());
+        }
+        let cleanup_block = self.append_basic_block("defer_cleanup");
+        self.builder.build_unconditional_branch(cleanup_block);
+        self.builder.position_at_end(cleanup_block);
+        for expr in deferred.iter() {
+            self.codegen_expr(expr)?;
+        }
+        Ok(())
+    }
+
     fn push_function_debug_info(
         &mut self,
         function: &TypedFunction,
@@ -2348,6 +2737,26 @@ impl<'ctx, 'module> Codegen<'ctx, 'module> {
         Ok(di_subprogram)
     }
 
+    /// Closures take a pointer to their environment before their declared parameters
+    fn make_llvm_function_type(
+        &self,
+        param_types: &[LlvmType<'ctx>],
+        ret_type: &LlvmType<'ctx>,
+        has_env: bool,
+    ) -> LlvmFunctionType<'ctx> {
+        let mut param_metadata_types: Vec<BasicMetadataTypeEnum<'ctx>> =
+            Vec::with_capacity(param_types.len() + 1);
+        if has_env {
+            param_metadata_types.push(self.builtin_types.ptr.into());
+        }
+        param_metadata_types
+            .extend(param_types.iter().map(|llvm_type| llvm_type.value_basic_type().into()));
+        match ret_type {
+            LlvmType::Void(v) => v.void_type.fn_type(&param_metadata_types, false),
+            _ => ret_type.value_basic_type().fn_type(&param_metadata_types, false),
+        }
+    }
+
     fn codegen_function(
         &mut self,
         function_id: FunctionId,
@@ -2372,14 +2781,10 @@ impl<'ctx, 'module> Codegen<'ctx, 'module> {
         let param_types: CodegenResult<Vec<LlvmType<'ctx>>> =
             function.params.iter().map(|fn_arg| self.codegen_type(fn_arg.type_id)).collect();
         let param_types = param_types?;
-        let param_metadata_types: Vec<BasicMetadataTypeEnum<'ctx>> =
-            param_types.iter().map(|llvm_type| llvm_type.value_basic_type().into()).collect();
         let ret_type = self.codegen_type(function.ret_type)?;
 
-        let fn_ty = match &ret_type {
-            LlvmType::Void(v) => v.void_type.fn_type(&param_metadata_types, false),
-            _ => ret_type.value_basic_type().fn_type(&param_metadata_types, false),
-        };
+        let fn_ty =
+            self.make_llvm_function_type(&param_types, &ret_type, function.closure_env.is_some());
         let llvm_linkage = match function.linkage {
             TyperLinkage::Standard => None,
             TyperLinkage::External => Some(LlvmLinkage::External),
@@ -2396,10 +2801,14 @@ impl<'ctx, 'module> Codegen<'ctx, 'module> {
         }
 
         let di_subprogram = self.push_function_debug_info(function, &ret_type, &param_types)?;
+        // Lambdas are generated in the middle of their enclosing function, whose defers are not
+        // theirs to run
+        let enclosing_defer_frames = std::mem::take(&mut self.defer_frames);
 
         let entry_block = self.ctx.append_basic_block(fn_val, "entry");
         self.builder.position_at_end(entry_block);
-        for (i, param) in fn_val.get_param_iter().enumerate() {
+        let env_param_count = if function.closure_env.is_some() { 1 } else { 0 };
+        for (i, param) in fn_val.get_param_iter().skip(env_param_count).enumerate() {
             let typed_param = &function.params[i];
             let ty = self.codegen_type(typed_param.type_id)?;
             let param_name = self.module.ast.identifiers.get_name(typed_param.name);
@@ -2440,6 +2849,39 @@ impl<'ctx, 'module> Codegen<'ctx, 'module> {
                 },
             );
         }
+        // Inside a closure, captured variables are found through the environment. The
+        // enclosing function's pointers for them are put back once we're done
+        let mut shadowed_variables = Vec::new();
+        if let Some(closure_env) = &function.closure_env {
+            let env_ptr = fn_val.get_first_param().unwrap().into_pointer_value();
+            env_ptr.set_name("env");
+            let env_struct_type =
+                self.codegen_type(closure_env.env_type)?.value_basic_type().into_struct_type();
+            for (index, capture) in closure_env.captures.iter().enumerate() {
+                let variable = self.module.variables.get_variable(capture.variable_id);
+                let variable_llvm_type = self.codegen_type(variable.type_id)?.value_basic_type();
+                let field_ptr = self
+                    .builder
+                    .build_struct_gep(env_struct_type, env_ptr, index as u32, "capture")
+                    .unwrap();
+                let pointer = match capture.mode {
+                    CaptureMode::ByValue => field_ptr,
+                    CaptureMode::ByReference => self
+                        .builder
+                        .build_load(self.builtin_types.ptr, field_ptr, "captured_ref")
+                        .into_pointer_value(),
+                };
+                let previous = self.variables.insert(
+                    capture.variable_id,
+                    Pointer {
+                        pointer,
+                        pointee_type_id: variable.type_id,
+                        pointee_llvm_type: variable_llvm_type,
+                    },
+                );
+                shadowed_variables.push((capture.variable_id, previous));
+            }
+        }
         match function.intrinsic_type {
             Some(intrinsic_type) => {
                 trace!("codegen intrinsic {:?} fn {:?}", intrinsic_type, function);
@@ -2471,6 +2913,13 @@ impl<'ctx, 'module> Codegen<'ctx, 'module> {
                 }
             }
         };
+        self.defer_frames = enclosing_defer_frames;
+        for (variable_id, previous) in shadowed_variables {
+            match previous {
+                Some(pointer) => self.variables.insert(variable_id, pointer),
+                None => self.variables.remove(&variable_id),
+            };
+        }
         if let Some(start_block) = maybe_starting_block {
             self.builder.position_at_end(start_block);
         }
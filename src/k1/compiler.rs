@@ -5,8 +5,9 @@ use std::os::unix::prelude::ExitStatusExt;
 use std::path::Path;
 use std::time::Instant;
 
-use crate::parse;
-use crate::parse::print_error_location;
+use crate::diagnostics::{Diagnostic, Lint};
+use crate::lex::Spans;
+use crate::parse::{self, Sources};
 use crate::typer::TypedModule;
 use anyhow::{bail, Result};
 use inkwell::context::Context;
@@ -17,7 +18,16 @@ use parse::{lex_text, ParsedModule, Source};
 
 use std::path::PathBuf;
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MessageFormat {
+    /// Colored source snippets
+    #[default]
+    Human,
+    /// One json object per line
+    Json,
+}
 
 #[derive(Parser, Debug, Clone)]
 #[command(author, version, about, long_about = None)]
@@ -50,10 +60,59 @@ pub struct Args {
     #[arg(long, default_value_t = false)]
     pub gui: bool,
 
+    /// How errors and warnings are printed
+    #[arg(long, value_enum, default_value_t = MessageFormat::Human)]
+    pub message_format: MessageFormat,
+
+    /// Enable a lint, by name, or all of them with 'warnings'. Overrides a blanket -A
+    #[arg(short = 'W', value_parser = LintSelector::parse)]
+    pub warn: Vec<LintSelector>,
+
+    /// Allow (silence) a lint, by name, or all of them with 'warnings'
+    #[arg(short = 'A', value_parser = LintSelector::parse)]
+    pub allow: Vec<LintSelector>,
+
+    /// Fail compilation if any warnings are reported
+    #[arg(long, default_value_t = false)]
+    pub deny_warnings: bool,
+
     /// File
     pub file: PathBuf,
 }
 
+impl Args {
+    /// Every lint is on by default. A flag naming a specific lint beats a blanket 'warnings', so
+    /// `-A warnings -W unused-mut` reports only unused-mut
+    pub fn is_lint_enabled(&self, lint: Lint) -> bool {
+        let specific = LintSelector::Lint(lint);
+        if self.allow.contains(&specific) {
+            false
+        } else if self.warn.contains(&specific) {
+            true
+        } else {
+            !self.allow.contains(&LintSelector::All) || self.warn.contains(&LintSelector::All)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintSelector {
+    All,
+    Lint(Lint),
+}
+
+impl LintSelector {
+    fn parse(s: &str) -> std::result::Result<LintSelector, String> {
+        if s == "warnings" {
+            return Ok(LintSelector::All);
+        }
+        Lint::from_name(s).map(LintSelector::Lint).ok_or_else(|| {
+            let names: Vec<&str> = Lint::ALL.iter().map(|lint| lint.name()).collect();
+            format!("Unknown lint '{}'; expected 'warnings' or one of: {}", s, names.join(", "))
+        })
+    }
+}
+
 /// Type size assertion. The first argument is a type and the second argument is its expected size.
 /// Cool trick from rustc.
 #[macro_export]
@@ -63,6 +122,27 @@ macro_rules! static_assert_size {
     };
 }
 
+/// All diagnostics go to stderr, so that stdout is left to the program being compiled
+pub fn emit_diagnostic(
+    format: MessageFormat,
+    spans: &Spans,
+    sources: &Sources,
+    diagnostic: &Diagnostic,
+) {
+    match format {
+        MessageFormat::Human => eprintln!("{}", diagnostic.render(spans, sources)),
+        MessageFormat::Json => eprintln!("{}", diagnostic.render_json(spans, sources)),
+    }
+}
+
+/// A line for people reading along; json output is nothing but diagnostics, one per line
+fn emit_summary(format: MessageFormat, summary: std::fmt::Arguments) {
+    match format {
+        MessageFormat::Human => eprintln!("{}", summary),
+        MessageFormat::Json => info!("{}", summary),
+    }
+}
+
 pub struct CompileModuleError {
     pub parsed_module: Option<ParsedModule>,
     pub module: Option<TypedModule>,
@@ -103,60 +183,100 @@ pub fn compile_module(args: &Args) -> std::result::Result<TypedModule, CompileMo
         ents.sort_by_key(|ent1| ent1.file_name());
         ents
     };
-    let mut parse_errors = Vec::new();
-
-    let mut parse_file = |path: &Path| {
+    let mut parse_file = |path: &Path, is_library: bool| {
         let content = fs::read_to_string(path).unwrap();
         let name = path.file_name().unwrap();
         info!("Parsing {}", name.to_string_lossy());
         let file_id = parsed_module.sources.next_file_id();
-        let source = Source::make(
+        let mut source = Source::make(
             file_id,
             path.canonicalize().unwrap().parent().unwrap().to_str().unwrap().to_string(),
             name.to_str().unwrap().to_string(),
             content,
         );
+        source.is_library = is_library;
         let token_vec = match lex_text(&mut parsed_module, source) {
             Ok(token_vec) => token_vec,
             Err(e) => {
-                // If lexing fails, we panic here because the source isn't in sources
-                print_error_location(&parsed_module.spans, &parsed_module.sources, e.span());
-                parse_errors.push(e);
+                emit_diagnostic(
+                    args.message_format,
+                    &parsed_module.spans,
+                    &parsed_module.sources,
+                    &e.to_diagnostic(),
+                );
+                parsed_module.lex_errors.push(e);
                 return ();
             }
         };
         let mut parser = parse::Parser::make(&token_vec, file_id, &mut parsed_module);
 
-        let result = parser.parse_module();
-        if let Err(e) = result {
-            parser.print_error(&e);
-            parse_errors.push(e);
+        // Errors are collected on the module; we report all of the ones from this file
+        let errors_before = parser.module.errors.len();
+        let _ = parser.parse_module();
+        let module = &parser.module;
+        for e in module.errors[errors_before..].iter() {
+            let diagnostic = e.to_diagnostic(&module.spans, &module.sources);
+            emit_diagnostic(args.message_format, &module.spans, &module.sources, &diagnostic);
         }
         ()
     };
 
     if use_core {
         let core_path: &Path = Path::new("builtins/core.k1");
-        parse_file(core_path);
+        parse_file(core_path, true);
 
         let bitwise_path: &Path = Path::new("builtins/bitwise.k1");
-        parse_file(bitwise_path);
+        parse_file(bitwise_path, true);
     }
 
     for f in dir_entries.iter() {
-        parse_file(&f.path());
+        parse_file(&f.path(), false);
     }
 
-    if !parse_errors.is_empty() {
-        return Err(CompileModuleError { parsed_module: Some(parsed_module), module: None });
+    let parse_error_count = parsed_module.lex_errors.len() + parsed_module.errors.len();
+    if parse_error_count > 0 {
+        emit_summary(
+            args.message_format,
+            format_args!("{} failed parsing with {} errors", module_name, parse_error_count),
+        );
     }
 
     let type_start = Instant::now();
     let parsing_elapsed = type_start.duration_since(start_parse);
     info!("parsing took {}ms", parsing_elapsed.as_millis());
 
+    // We typecheck even if parsing failed, over whatever we managed to parse,
+    // so that a single run reports as many errors as possible
     let mut typed_module = TypedModule::new(parsed_module);
-    if let Err(_) = typed_module.run() {
+    let typing_result = typed_module.run();
+    for e in typed_module.errors.iter() {
+        emit_diagnostic(
+            args.message_format,
+            &typed_module.ast.spans,
+            &typed_module.ast.sources,
+            &e.to_diagnostic(),
+        );
+    }
+    let mut warning_count = 0;
+    for warning in typed_module.warnings.iter() {
+        if warning.lint.map_or(true, |lint| args.is_lint_enabled(lint)) {
+            warning_count += 1;
+            emit_diagnostic(
+                args.message_format,
+                &typed_module.ast.spans,
+                &typed_module.ast.sources,
+                warning,
+            );
+        }
+    }
+    let denied_warnings = args.deny_warnings && warning_count > 0;
+    if denied_warnings {
+        emit_summary(
+            args.message_format,
+            format_args!("{} failed with {} denied warnings", module_name, warning_count),
+        );
+    }
+    if typing_result.is_err() || parse_error_count > 0 || denied_warnings {
         if args.dump_module {
             println!("{}", typed_module);
         }
@@ -173,6 +293,7 @@ pub fn compile_module(args: &Args) -> std::result::Result<TypedModule, CompileMo
 
 pub fn write_executable<'ctx, 'module>(
     debug: bool,
+    message_format: MessageFormat,
     out_dir: &str,
     module_name: &str,
 ) -> Result<()> {
@@ -200,7 +321,7 @@ pub fn write_executable<'ctx, 'module>(
     let build_status = build_cmd.status()?;
 
     if !build_status.success() {
-        eprintln!("Build failed!");
+        emit_summary(message_format, format_args!("Build failed!"));
         bail!("build_executable with clang failed");
     }
 
@@ -222,12 +343,13 @@ pub fn codegen_module<'ctx, 'module>(
     let mut codegen = Codegen::create(ctx, &typed_module, args.debug, llvm_optimize);
     let module_name = codegen.name().to_string();
     if let Err(e) = codegen.codegen_module() {
-        print_error_location(&codegen.module.ast.spans, &codegen.module.ast.sources, e.span);
-        eprintln!("Codegen error: {}", e.message);
+        let diagnostic = Diagnostic::error(format!("Codegen error: {}", e.message), e.span);
+        let ast = &codegen.module.ast;
+        emit_diagnostic(args.message_format, &ast.spans, &ast.sources, &diagnostic);
         anyhow::bail!(e)
     }
     if let Err(e) = codegen.optimize(llvm_optimize) {
-        eprintln!("Codegen error: {}", e.to_string());
+        emit_summary(args.message_format, format_args!("Codegen error: {}", e));
         anyhow::bail!(e)
     };
 
@@ -240,7 +362,7 @@ pub fn codegen_module<'ctx, 'module>(
     }
 
     if do_write_executable {
-        write_executable(args.debug, out_dir, &module_name)?;
+        write_executable(args.debug, args.message_format, out_dir, &module_name)?;
     }
 
     Ok(codegen)
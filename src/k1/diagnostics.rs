@@ -0,0 +1,422 @@
+use std::fmt::Write;
+
+use colored::{Color, Colorize};
+
+use crate::lex::{SpanId, Spans};
+use crate::parse::Sources;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+
+    fn color(&self) -> Color {
+        match self {
+            Severity::Error => Color::Red,
+            Severity::Warning => Color::Yellow,
+        }
+    }
+}
+
+/// A class of warning, which can be turned on or off from the command line by name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lint {
+    UnusedVariable,
+    UnusedFunction,
+    UnreachableCode,
+    UnusedMut,
+    UselessArm,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 5] = [
+        Lint::UnusedVariable,
+        Lint::UnusedFunction,
+        Lint::UnreachableCode,
+        Lint::UnusedMut,
+        Lint::UselessArm,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Lint::UnusedVariable => "unused-variable",
+            Lint::UnusedFunction => "unused-function",
+            Lint::UnreachableCode => "unreachable-code",
+            Lint::UnusedMut => "unused-mut",
+            Lint::UselessArm => "useless-arm",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Lint> {
+        Lint::ALL.iter().find(|lint| lint.name() == name).copied()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: SpanId,
+    /// May be empty, in which case only the span is highlighted
+    pub message: String,
+}
+
+/// A message about the user's program, pointing at one primary location and any number of
+/// related ones. Every error or warning the compiler reports goes through this type, so that it
+/// can be rendered for a human or as json for tools
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Set for warnings, so they can be filtered by `-W` and `-A`
+    pub lint: Option<Lint>,
+    pub message: String,
+    pub primary: Label,
+    pub secondary: Vec<Label>,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn make(severity: Severity, message: impl Into<String>, span: SpanId) -> Diagnostic {
+        Diagnostic {
+            severity,
+            lint: None,
+            message: message.into(),
+            primary: Label { span, message: String::new() },
+            secondary: Vec::new(),
+            notes: Vec::new(),
+            help: None,
+        }
+    }
+
+    pub fn error(message: impl Into<String>, span: SpanId) -> Diagnostic {
+        Diagnostic::make(Severity::Error, message, span)
+    }
+
+    pub fn warning(lint: Lint, message: impl Into<String>, span: SpanId) -> Diagnostic {
+        let mut diagnostic = Diagnostic::make(Severity::Warning, message, span);
+        diagnostic.lint = Some(lint);
+        diagnostic
+    }
+
+    pub fn with_primary_label(mut self, message: impl Into<String>) -> Diagnostic {
+        self.primary.message = message.into();
+        self
+    }
+
+    pub fn with_label(mut self, span: SpanId, message: impl Into<String>) -> Diagnostic {
+        self.secondary.push(Label { span, message: message.into() });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Diagnostic {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Diagnostic {
+        self.help = Some(help.into());
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    fn resolve_labels(&self, spans: &Spans, sources: &Sources) -> Vec<ResolvedLabel<'_>> {
+        let primary = std::iter::once((&self.primary, true));
+        let secondary = self.secondary.iter().map(|label| (label, false));
+        primary
+            .chain(secondary)
+            .filter(|(label, _)| label.span != SpanId::NONE)
+            .filter_map(|(label, is_primary)| {
+                let span = spans.get(label.span);
+                let source = sources.source_by_span(span);
+                let line = source.get_line_for_span(span)?;
+                let column = span.start - line.start_char;
+                // If the span is longer than the line, just highlight the rest of the line
+                let line_len = line.content.chars().count() as u32;
+                let len = span.len.min(line_len.saturating_sub(column)).max(1);
+                Some(ResolvedLabel {
+                    file_id: span.file_id,
+                    line_index: line.line_index,
+                    column,
+                    len,
+                    start: span.start,
+                    message: &label.message,
+                    is_primary,
+                })
+            })
+            .collect()
+    }
+
+    /// Renders a rustc-style report with a source snippet for every labelled line
+    pub fn render(&self, spans: &Spans, sources: &Sources) -> String {
+        let mut out = String::new();
+        let severity_color = self.severity.color();
+        let code = self.lint.map(|lint| format!("[{}]", lint.name())).unwrap_or_default();
+        let _ = writeln!(
+            out,
+            "{}{} {}",
+            format!("{}{}", self.severity.as_str(), code).color(severity_color).bold(),
+            ":".bold(),
+            self.message.bold()
+        );
+
+        let mut labels = self.resolve_labels(spans, sources);
+        let Some(primary_file) = labels.first().map(|l| l.file_id) else {
+            self.render_footer(&mut out, 0);
+            return out;
+        };
+        let gutter_width =
+            labels.iter().map(|l| (l.line_index + 1).to_string().len()).max().unwrap_or(1);
+        let gutter = " ".repeat(gutter_width);
+
+        // The primary label's file comes first; within a file, lines are in source order
+        labels.sort_by_key(|l| (l.file_id != primary_file, l.file_id, l.line_index, l.column));
+
+        let mut prev: Option<(u32, u32)> = None;
+        for (index, label) in labels.iter().enumerate() {
+            let source = sources.get_source(label.file_id);
+            let new_file = prev.map_or(true, |(file_id, _)| file_id != label.file_id);
+            if new_file {
+                let arrow = if prev.is_none() { "-->" } else { ":::" };
+                let _ = writeln!(
+                    out,
+                    "{gutter}{} {}/{}:{}:{}",
+                    arrow.blue().bold(),
+                    source.directory,
+                    source.filename,
+                    label.line_index + 1,
+                    label.column + 1
+                );
+                let _ = writeln!(out, "{gutter} {}", "|".blue().bold());
+            }
+            let same_line = prev == Some((label.file_id, label.line_index));
+            if !same_line {
+                if let Some((_, prev_line)) = prev.filter(|_| !new_file) {
+                    if label.line_index > prev_line + 1 {
+                        let _ = writeln!(out, "{}", "...".blue().bold());
+                    }
+                }
+                let line = &source.lines[label.line_index as usize];
+                let _ = writeln!(
+                    out,
+                    "{:>gutter_width$} {} {}",
+                    (label.line_index + 1).to_string().blue().bold(),
+                    "|".blue().bold(),
+                    line.content
+                );
+            }
+            let (marker, color) =
+                if label.is_primary { ("^", severity_color) } else { ("-", Color::Blue) };
+            let underline = format!("{} {}", marker.repeat(label.len as usize), label.message);
+            let _ = writeln!(
+                out,
+                "{gutter} {} {}{}",
+                "|".blue().bold(),
+                " ".repeat(label.column as usize),
+                underline.trim_end().color(color).bold()
+            );
+            prev = Some((label.file_id, label.line_index));
+            if index == labels.len() - 1 {
+                self.render_footer(&mut out, gutter_width);
+            }
+        }
+        out
+    }
+
+    fn render_footer(&self, out: &mut String, gutter_width: usize) {
+        let gutter = " ".repeat(gutter_width);
+        for note in self.notes.iter() {
+            let _ = writeln!(out, "{gutter} {} {}: {}", "=".blue().bold(), "note".bold(), note);
+        }
+        if let Some(help) = &self.help {
+            let _ = writeln!(out, "{gutter} {} {}: {}", "=".blue().bold(), "help".bold(), help);
+        }
+    }
+
+    /// Renders as a single line of json, for editors and CI
+    pub fn render_json(&self, spans: &Spans, sources: &Sources) -> String {
+        let mut out = String::new();
+        out.push_str("{\"severity\":");
+        write_json_string(&mut out, self.severity.as_str());
+        out.push_str(",\"code\":");
+        match self.lint {
+            None => out.push_str("null"),
+            Some(lint) => write_json_string(&mut out, lint.name()),
+        }
+        out.push_str(",\"message\":");
+        write_json_string(&mut out, &self.message);
+        out.push_str(",\"spans\":[");
+        for (index, label) in self.resolve_labels(spans, sources).iter().enumerate() {
+            if index > 0 {
+                out.push(',');
+            }
+            let source = sources.get_source(label.file_id);
+            out.push_str("{\"file\":");
+            write_json_string(&mut out, &format!("{}/{}", source.directory, source.filename));
+            let _ = write!(
+                out,
+                ",\"line\":{},\"column\":{},\"start\":{},\"length\":{},\"primary\":{},\"label\":",
+                label.line_index + 1,
+                label.column + 1,
+                label.start,
+                label.len,
+                label.is_primary
+            );
+            write_json_string(&mut out, label.message);
+            out.push('}');
+        }
+        out.push_str("],\"notes\":[");
+        for (index, note) in self.notes.iter().enumerate() {
+            if index > 0 {
+                out.push(',');
+            }
+            write_json_string(&mut out, note);
+        }
+        out.push_str("],\"help\":");
+        match &self.help {
+            None => out.push_str("null"),
+            Some(help) => write_json_string(&mut out, help),
+        }
+        out.push('}');
+        out
+    }
+}
+
+struct ResolvedLabel<'a> {
+    file_id: u32,
+    line_index: u32,
+    /// 0-based, in chars from the start of the line
+    column: u32,
+    len: u32,
+    start: u32,
+    message: &'a str,
+    is_primary: bool,
+}
+
+/// Messages can carry the terminal styling that highlights names in the human format; it means
+/// nothing to a json consumer, so ANSI escape sequences are dropped
+fn write_json_string(out: &mut String, s: &str) {
+    out.push('"');
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' && chars.peek() == Some(&'[') {
+            // A CSI sequence runs up to and including its final byte, in '@'..='~'
+            chars.next();
+            while let Some(c) = chars.next() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+            continue;
+        }
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod test {
+    use super::{write_json_string, Diagnostic, Lint};
+    use crate::lex::{Span, Spans};
+    use crate::parse::{Source, Sources};
+
+    const SOURCE: &str = "fn main(): int {\n  val x = nope;\n  x\n}\n";
+
+    /// An error on `nope`, with a secondary label on the `x` that uses it
+    fn nope_diagnostic(spans: &mut Spans, message: &str) -> (Diagnostic, Sources) {
+        let mut sources = Sources::default();
+        sources.insert(Source::make(
+            0,
+            "src".to_string(),
+            "main.k1".to_string(),
+            SOURCE.to_string(),
+        ));
+        let nope = spans.add(Span { start: 27, len: 4, file_id: 0 });
+        let x_use = spans.add(Span { start: 35, len: 1, file_id: 0 });
+        let diagnostic = Diagnostic::error(message, nope)
+            .with_primary_label("not found")
+            .with_label(x_use, "used here")
+            .with_help("define 'nope' first");
+        (diagnostic, sources)
+    }
+
+    #[test]
+    fn json_escapes() {
+        let mut out = String::new();
+        write_json_string(&mut out, "say \"hi\"\n\\ \u{1}");
+        assert_eq!(out, r#""say \"hi\"\n\\ \u0001""#);
+    }
+
+    #[test]
+    fn json_strips_styling() {
+        let mut out = String::new();
+        write_json_string(&mut out, "Method '\u{1b}[34mfoo\u{1b}[0m' does not exist");
+        assert_eq!(out, r#""Method 'foo' does not exist""#);
+    }
+
+    #[test]
+    fn render_human() {
+        colored::control::set_override(false);
+        let mut spans = Spans::new();
+        let (diagnostic, sources) = nope_diagnostic(&mut spans, "Variable 'nope' is not defined");
+        let expected = "\
+error: Variable 'nope' is not defined
+ --> src/main.k1:2:11
+  |
+2 |   val x = nope;
+  |           ^^^^ not found
+3 |   x
+  |   - used here
+  = help: define 'nope' first
+";
+        assert_eq!(diagnostic.render(&spans, &sources), expected);
+
+        let warning = Diagnostic::warning(
+            Lint::UnusedVariable,
+            "Unused variable 'x'",
+            spans.add(Span { start: 23, len: 1, file_id: 0 }),
+        );
+        let expected = "\
+warning[unused-variable]: Unused variable 'x'
+ --> src/main.k1:2:7
+  |
+2 |   val x = nope;
+  |       ^
+";
+        assert_eq!(warning.render(&spans, &sources), expected);
+    }
+
+    #[test]
+    fn render_json() {
+        let mut spans = Spans::new();
+        let (diagnostic, sources) =
+            nope_diagnostic(&mut spans, "Variable '\u{1b}[1;34mnope\u{1b}[0m' is not defined");
+        let expected = concat!(
+            r#"{"severity":"error","code":null,"message":"Variable 'nope' is not defined","#,
+            r#""spans":[{"file":"src/main.k1","line":2,"column":11,"start":27,"length":4,"primary":true,"label":"not found"},"#,
+            r#"{"file":"src/main.k1","line":3,"column":3,"start":35,"length":1,"primary":false,"label":"used here"}],"#,
+            r#""notes":[],"help":"define 'nope' first"}"#
+        );
+        assert_eq!(diagnostic.render_json(&spans, &sources), expected);
+    }
+}
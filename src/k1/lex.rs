@@ -2,6 +2,7 @@ use std::fmt;
 use std::fmt::{Display, Formatter};
 use std::str::Chars;
 
+use crate::diagnostics::Diagnostic;
 use crate::parse::FileId;
 use crate::typer::BinaryOpKind;
 use log::trace;
@@ -10,10 +11,11 @@ use TokenKind as K;
 pub const EOF_CHAR: char = '\0';
 pub const EOF_TOKEN: Token = Token { span: SpanId::NONE, kind: TokenKind::Eof, flags: 0 };
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct LexError {
     pub msg: String,
     pub line_index: u32,
+    pub span: SpanId,
 }
 
 pub type LexResult<A> = anyhow::Result<A, LexError>;
@@ -55,6 +57,12 @@ impl Spans {
     }
 }
 
+impl LexError {
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        Diagnostic::error(&self.msg, self.span)
+    }
+}
+
 impl Display for LexError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.write_fmt(format_args!("LexError at line {}: {}", self.line_index, self.msg))
@@ -116,6 +124,12 @@ impl<'toks> TokenIter<'toks> {
 pub enum TokenKind {
     Ident,
     String,
+    /// `"text{`: the start of a string with interpolated expressions
+    StringStart,
+    /// `}text{`: the text between two interpolated expressions
+    StringMiddle,
+    /// `}text"`: the end of a string with interpolated expressions
+    StringEnd,
 
     Char,
 
@@ -146,9 +160,16 @@ pub enum TokenKind {
     KeywordNot,
     KeywordBuiltin,
     KeywordWhere,
+    KeywordBreak,
+    KeywordContinue,
+    KeywordDefer,
 
     Slash,
     LineComment,
+    /// `/* ... */`, possibly containing other block comments
+    BlockComment,
+    /// `///`, documenting the definition that follows it
+    DocComment,
 
     // Symbols
     OpenParen,
@@ -165,12 +186,18 @@ pub enum TokenKind {
     EqualsEquals,
     BangEquals,
     Dot,
+    /// `..`, as in the range `0..n` and the slice pattern `[x, ..rest]`
+    DotDot,
+    /// `..=`, as in the inclusive range `1..=9`
+    DotDotEquals,
     Comma,
     Bang,
     QuestionMark,
     Pipe,
     Ampersand,
     Percent,
+    Backslash,
+    At,
 
     DoubleQuote,
     SingleQuote,
@@ -227,9 +254,14 @@ impl TokenKind {
             K::KeywordNot => Some("not"),
             K::KeywordBuiltin => Some("builtin"),
             K::KeywordWhere => Some("where"),
+            K::KeywordBreak => Some("break"),
+            K::KeywordContinue => Some("continue"),
+            K::KeywordDefer => Some("defer"),
 
             K::Slash => Some("/"),
             K::LineComment => Some("//"),
+            K::BlockComment => Some("/*"),
+            K::DocComment => Some("///"),
 
             K::OpenParen => Some("("),
             K::CloseParen => Some(")"),
@@ -245,12 +277,16 @@ impl TokenKind {
             K::EqualsEquals => Some("=="),
             K::BangEquals => Some("!="),
             K::Dot => Some("."),
+            K::DotDot => Some(".."),
+            K::DotDotEquals => Some("..="),
             K::Comma => Some(","),
             K::Bang => Some("!"),
             K::QuestionMark => Some("?"),
             K::Pipe => Some("|"),
             K::Ampersand => Some("&"),
             K::Percent => Some("%"),
+            K::Backslash => Some("\\"),
+            K::At => Some("@"),
 
             K::Plus => Some("+"),
             K::Minus => Some("-"),
@@ -263,6 +299,9 @@ impl TokenKind {
 
             K::Ident => None,
             K::String => Some("\"?\""),
+            K::StringStart => Some("\"?{"),
+            K::StringMiddle => Some("}?{"),
+            K::StringEnd => Some("}?\""),
             K::Char => Some("'?'"),
 
             K::Eof => Some("<EOF>"),
@@ -294,6 +333,8 @@ impl TokenKind {
             '|' => Some(K::Pipe),
             '&' => Some(K::Ampersand),
             '%' => Some(K::Percent),
+            '\\' => Some(K::Backslash),
+            '@' => Some(K::At),
             _ => None,
         }
     }
@@ -326,10 +367,15 @@ impl TokenKind {
             "is" => Some(K::KeywordIs),
             "builtin" => Some(K::KeywordBuiltin),
             "where" => Some(K::KeywordWhere),
+            "break" => Some(K::KeywordBreak),
+            "continue" => Some(K::KeywordContinue),
+            "defer" => Some(K::KeywordDefer),
             "==" => Some(K::EqualsEquals),
             "!=" => Some(K::BangEquals),
             "<=" => Some(K::LessThanEqual),
             ">=" => Some(K::GreaterThanEqual),
+            ".." => Some(K::DotDot),
+            "..=" => Some(K::DotDotEquals),
             _ => None,
         }
     }
@@ -372,6 +418,7 @@ impl TokenKind {
             K::Asterisk => true,
             K::KeywordNot => true,
             K::Ampersand => true,
+            K::Minus => true,
             _ => false,
         }
     }
@@ -450,6 +497,9 @@ pub struct Lexer<'a, 'spans> {
     pub spans: &'spans mut Spans,
     pub line_index: u32,
     pub pos: u32,
+    /// For each interpolated expression we're inside of, how many braces are open within it,
+    /// so we know which `}` resumes the string
+    interpolation_depths: Vec<u32>,
 }
 
 impl<'content, 'spans> Lexer<'content, 'spans> {
@@ -458,16 +508,41 @@ impl<'content, 'spans> Lexer<'content, 'spans> {
         spans: &'spans mut Spans,
         file_id: FileId,
     ) -> Lexer<'content, 'spans> {
-        Lexer { file_id, content: input.chars(), spans, line_index: 0, pos: 0 }
+        Lexer {
+            file_id,
+            content: input.chars(),
+            spans,
+            line_index: 0,
+            pos: 0,
+            interpolation_depths: Vec::new(),
+        }
     }
 
-    fn err(&self, msg: impl Into<String>) -> LexError {
-        LexError { msg: msg.into(), line_index: self.line_index }
+    fn err(&mut self, msg: impl Into<String>, start: u32, len: u32) -> LexError {
+        let span = self.add_span(start, len);
+        LexError { msg: msg.into(), line_index: self.line_index, span }
     }
 
     pub fn run(&mut self) -> LexResult<Vec<Token>> {
         let mut tokens = Vec::with_capacity(1024);
         while let Some(tok) = self.eat_token()? {
+            match tok.kind {
+                K::StringStart => self.interpolation_depths.push(0),
+                K::StringEnd => {
+                    self.interpolation_depths.pop();
+                }
+                K::OpenBrace => {
+                    if let Some(depth) = self.interpolation_depths.last_mut() {
+                        *depth += 1
+                    }
+                }
+                K::CloseBrace => {
+                    if let Some(depth) = self.interpolation_depths.last_mut() {
+                        *depth -= 1
+                    }
+                }
+                _ => {}
+            }
             tokens.push(tok);
         }
         Ok(tokens)
@@ -481,8 +556,8 @@ impl<'content, 'spans> Lexer<'content, 'spans> {
         let mut tok_buf = String::new();
         let mut tok_len = 0;
         let mut is_line_comment = false;
+        let mut is_doc_comment = false;
         let mut line_comment_start = 0;
-        let mut is_string = false;
         let peeked_whitespace = self.peek().is_whitespace();
         let make_token = |lex: &mut Lexer, kind: TokenKind, start: u32, len: u32| {
             let span = lex.add_span(start, len);
@@ -494,31 +569,30 @@ impl<'content, 'spans> Lexer<'content, 'spans> {
             trace!("LEX line={} char={} '{}' buf={}", self.line_index, n, c, tok_buf);
             if is_line_comment {
                 if c == '\n' || c == EOF_CHAR {
-                    let len = n - line_comment_start - 1;
-                    let comment_tok = make_token(self, K::LineComment, line_comment_start, len);
+                    let comment_tok = if is_doc_comment {
+                        let len = n - line_comment_start;
+                        make_token(self, K::DocComment, line_comment_start, len)
+                    } else {
+                        let len = n - line_comment_start - 1;
+                        make_token(self, K::LineComment, line_comment_start, len)
+                    };
                     break Some(comment_tok);
                 } else {
                     self.advance();
                     continue;
                 }
             }
-            if is_string {
-                if c == '"' {
-                    self.advance();
-                    break Some(make_token(self, K::String, n - tok_len, tok_len));
-                } else if c == '\n' {
-                    return Err(self.err("No newlines inside strings"));
-                } else {
-                    tok_len += 1;
-                    tok_buf.push(c);
-                    self.advance();
-                    continue;
+            if c == '"' {
+                let is_raw = tok_buf == "r";
+                if !tok_buf.is_empty() && !is_raw {
+                    break Some(make_token(self, K::Ident, n - tok_len, tok_len));
                 }
+                let start = if is_raw { n - 1 } else { n };
+                break Some(self.eat_string(start, is_raw, false, peeked_whitespace)?);
             }
-            if c == '"' {
-                is_string = true;
-                self.advance();
-                continue;
+            if c == '}' && tok_buf.is_empty() && self.interpolation_depths.last() == Some(&0) {
+                // The end of an interpolated expression
+                break Some(self.eat_string(n, false, true, peeked_whitespace)?);
             }
             if c == EOF_CHAR {
                 if !tok_buf.is_empty() {
@@ -529,13 +603,33 @@ impl<'content, 'spans> Lexer<'content, 'spans> {
             }
             if let Some(single_char_tok) = TokenKind::from_char(c) {
                 let (_, next) = self.peek_two();
+                if continues_float_literal(&tok_buf, c, next) {
+                    // A decimal point or exponent sign inside a number literal, as in
+                    // `1.5` or `2e-3`
+                    self.advance();
+                    tok_len += 1;
+                    tok_buf.push(c);
+                    continue;
+                }
                 if !tok_buf.is_empty() {
                     // Break without advancing; we'll have a clear buffer next time
                     // and will advance
 
                     // Watch out for peeked_whitespace regressions in this case; we were passing 'false'
                     // but I think it should be just using the current value
-                    break Some(make_token(self, K::Ident, n - tok_len, tok_len));
+
+                    // Keywords double as names when directly followed by a symbol, as in
+                    // `Bitwise::and(a, b)`; `fn(` is the exception, since it starts a function type,
+                    // as is `impl[`, which starts a blanket impl, and so are `break;` and
+                    // `continue;`, which are never names
+                    let kind = match tok_buf.as_str() {
+                        "fn" if c == '(' => K::KeywordFn,
+                        "impl" if c == '[' => K::KeywordImpl,
+                        "break" => K::KeywordBreak,
+                        "continue" => K::KeywordContinue,
+                        _ => K::Ident,
+                    };
+                    break Some(make_token(self, kind, n - tok_len, tok_len));
                 } else if single_char_tok == K::SingleQuote {
                     self.advance(); // eat opening '
                     let mut len = 1;
@@ -544,6 +638,12 @@ impl<'content, 'spans> Lexer<'content, 'spans> {
                         len += 1;
                         if c == '\'' {
                             break;
+                        } else if c == EOF_CHAR || c == '\n' {
+                            return Err(self.err("Unterminated char literal", n, len - 1));
+                        } else if c == '\\' && self.peek() != EOF_CHAR && self.peek() != '\n' {
+                            // Skip the escaped char, so that an escaped quote doesn't end the literal
+                            self.advance();
+                            len += 1;
                         }
                     }
                     // `n` is the index of the opening quote
@@ -564,11 +664,24 @@ impl<'content, 'spans> Lexer<'content, 'spans> {
                     self.advance();
                     self.advance();
                     break Some(make_token(self, K::GreaterThanEqual, n, 2));
+                } else if single_char_tok == TokenKind::Dot && next == '.' {
+                    self.advance();
+                    self.advance();
+                    if self.peek() == '=' {
+                        self.advance();
+                        break Some(make_token(self, K::DotDotEquals, n, 3));
+                    }
+                    break Some(make_token(self, K::DotDot, n, 2));
                 } else if single_char_tok == TokenKind::Slash && next == '/' {
                     is_line_comment = true;
                     line_comment_start = n;
                     self.advance();
                     self.advance();
+                    // Exactly three slashes; a line of four or more is just a comment
+                    let (third, fourth) = self.peek_two();
+                    is_doc_comment = third == '/' && fourth != '/';
+                } else if single_char_tok == TokenKind::Slash && next == '*' {
+                    break Some(self.eat_block_comment(n, peeked_whitespace)?);
                 } else {
                     self.advance();
                     break Some(make_token(self, single_char_tok, n, 1));
@@ -583,7 +696,7 @@ impl<'content, 'spans> Lexer<'content, 'spans> {
             }
             if (tok_buf.is_empty() && is_ident_or_num_start(c)) || is_ident_char(c) {
                 if tok_buf.len() == 1 && tok_buf.starts_with('_') && c == '_' {
-                    return Err(self.err("Identifiers cannot begin with __"));
+                    return Err(self.err("Identifiers cannot begin with __", n - 1, 2));
                 }
                 tok_len += 1;
                 tok_buf.push(c);
@@ -597,6 +710,94 @@ impl<'content, 'spans> Lexer<'content, 'spans> {
         Ok(token)
     }
 
+    /// Eats a string literal; `start` is the opening quote, or the `r` of a raw string. The token
+    /// spans the whole literal including its delimiters; the parser handles escapes and
+    /// indentation. Only triple-quoted and raw strings may contain newlines, and in a
+    /// triple-quoted string a `\` at the end of a line continues it onto the next.
+    ///
+    /// A plain string is split into segments around any `{expr}` holes, which are lexed as
+    /// normal tokens in between. Every `{` in a plain string opens a hole: a literal brace is
+    /// written `\{`, or the string is made raw or triple-quoted, since those never interpolate.
+    /// `is_continuation` means we're resuming the string at the `}` closing a hole
+    fn eat_string(
+        &mut self,
+        start: u32,
+        is_raw: bool,
+        is_continuation: bool,
+        whitespace_preceeded: bool,
+    ) -> LexResult<Token> {
+        let is_multiline = !is_raw && !is_continuation && self.peek_three() == ('"', '"', '"');
+        let quote_count = if is_multiline { 3 } else { 1 };
+        for _ in 0..quote_count {
+            self.advance();
+        }
+        let opener_len = self.pos - start;
+        let kind = loop {
+            let c = self.peek();
+            if c == EOF_CHAR {
+                return Err(self.err("Unterminated string", start, opener_len));
+            } else if c == '\n' && !is_multiline && !is_raw {
+                return Err(self.err("No newlines inside strings", start, self.pos - start));
+            } else if c == '{' && !is_multiline && !is_raw {
+                self.advance();
+                break if is_continuation { K::StringMiddle } else { K::StringStart };
+            } else if c == '"' && !is_multiline {
+                self.advance();
+                break if is_continuation { K::StringEnd } else { K::String };
+            } else if c == '"' && self.peek_three() == ('"', '"', '"') {
+                self.advance();
+                self.advance();
+                self.advance();
+                break K::String;
+            }
+            self.advance();
+            let next = self.peek();
+            if c == '\\' && !is_raw && next != EOF_CHAR && (next != '\n' || is_multiline) {
+                // Skip the escaped char, so that an escaped quote doesn't end the string, and
+                // the braces of a unicode escape don't start an interpolation. The escape
+                // itself is checked by the parser
+                self.advance();
+                if next == 'u' && self.peek() == '{' {
+                    while !matches!(self.peek(), '}' | '"' | '\n' | EOF_CHAR) {
+                        self.advance();
+                    }
+                    if self.peek() == '}' {
+                        self.advance();
+                    }
+                }
+            }
+        };
+        let span = self.add_span(start, self.pos - start);
+        Ok(Token::new(kind, span, whitespace_preceeded))
+    }
+
+    /// Eats a block comment starting at `start`, including any nested block comments, so that
+    /// code containing a comment can itself be commented out
+    fn eat_block_comment(&mut self, start: u32, whitespace_preceeded: bool) -> LexResult<Token> {
+        self.advance();
+        self.advance();
+        let mut depth = 1;
+        let mut opener_starts = vec![start];
+        while depth > 0 {
+            let (c, next) = self.peek_two();
+            if c == EOF_CHAR {
+                let innermost_start = opener_starts.last().copied().unwrap_or(start);
+                return Err(self.err("Unterminated block comment", innermost_start, 2));
+            } else if c == '/' && next == '*' {
+                opener_starts.push(self.pos);
+                depth += 1;
+                self.advance();
+            } else if c == '*' && next == '/' {
+                opener_starts.pop();
+                depth -= 1;
+                self.advance();
+            }
+            self.advance();
+        }
+        let span = self.add_span(start, self.pos - start);
+        Ok(Token::new(K::BlockComment, span, whitespace_preceeded))
+    }
+
     fn next(&mut self) -> char {
         self.pos += 1;
         let c = self.content.next().unwrap_or(EOF_CHAR);
@@ -616,6 +817,14 @@ impl<'content, 'spans> Lexer<'content, 'spans> {
         let mut peek_iter = self.content.clone();
         (peek_iter.next().unwrap_or(EOF_CHAR), peek_iter.next().unwrap_or(EOF_CHAR))
     }
+    fn peek_three(&self) -> (char, char, char) {
+        let mut peek_iter = self.content.clone();
+        (
+            peek_iter.next().unwrap_or(EOF_CHAR),
+            peek_iter.next().unwrap_or(EOF_CHAR),
+            peek_iter.next().unwrap_or(EOF_CHAR),
+        )
+    }
     fn peek_with_pos(&self) -> (char, u32) {
         (self.peek(), self.pos)
     }
@@ -624,6 +833,111 @@ impl<'content, 'spans> Lexer<'content, 'spans> {
     }
 }
 
+/// A malformed escape sequence, located by char offset into the text passed to `unescape`.
+/// `msg` says what was expected instead, like a `ParseError`
+#[derive(Debug, PartialEq, Eq)]
+pub struct EscapeError {
+    pub offset: u32,
+    pub len: u32,
+    pub msg: String,
+}
+
+/// Appends `text` to `out`, replacing escape sequences with the chars they stand for.
+/// Malformed escapes are left out of `out`, and each is reported so it can get its own span
+pub fn unescape(text: &str, out: &mut String) -> Vec<EscapeError> {
+    let mut errors = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut offset: u32 = 0;
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            offset += 1;
+            continue;
+        }
+        let escape_start = offset;
+        offset += 1;
+        let Some(kind) = chars.next() else {
+            errors.push(EscapeError {
+                offset: escape_start,
+                len: 1,
+                msg: "a char following the backslash".to_string(),
+            });
+            break;
+        };
+        offset += 1;
+        let simple = match kind {
+            'n' => Some('\n'),
+            't' => Some('\t'),
+            'r' => Some('\r'),
+            '0' => Some('\0'),
+            '\\' => Some('\\'),
+            '"' => Some('"'),
+            '\'' => Some('\''),
+            '{' => Some('{'),
+            '}' => Some('}'),
+            _ => None,
+        };
+        if let Some(simple) = simple {
+            out.push(simple);
+            continue;
+        }
+        let result = match kind {
+            'x' => {
+                let digits: String = chars.by_ref().take(2).collect();
+                offset += digits.chars().count() as u32;
+                let is_hex = digits.len() == 2 && digits.chars().all(|d| d.is_ascii_hexdigit());
+                match u8::from_str_radix(&digits, 16).ok().filter(|_| is_hex) {
+                    Some(byte) if byte <= 0x7f => Ok(char::from(byte)),
+                    Some(_) => Err("an ascii escape, at most \\x7f"),
+                    None => Err("2 hex digits after \\x"),
+                }
+            }
+            'u' => {
+                if chars.peek() != Some(&'{') {
+                    Err("'{' after \\u")
+                } else {
+                    chars.next();
+                    offset += 1;
+                    let mut digits = String::new();
+                    let mut closed = false;
+                    while let Some(&d) = chars.peek() {
+                        if d == '}' {
+                            chars.next();
+                            offset += 1;
+                            closed = true;
+                            break;
+                        } else if d.is_ascii_hexdigit() {
+                            chars.next();
+                            offset += 1;
+                            digits.push(d);
+                        } else {
+                            break;
+                        }
+                    }
+                    if !closed || digits.is_empty() || digits.len() > 6 {
+                        Err("1 to 6 hex digits between braces after \\u")
+                    } else {
+                        u32::from_str_radix(&digits, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or("a valid unicode code point")
+                    }
+                }
+            }
+            _ => Err("a valid escape sequence"),
+        };
+        match result {
+            Ok(c) => out.push(c),
+            Err(msg) => errors.push(EscapeError {
+                offset: escape_start,
+                len: offset - escape_start,
+                msg: msg.to_string(),
+            }),
+        }
+    }
+    errors
+}
+
 fn is_ident_char(c: char) -> bool {
     c.is_alphanumeric() || c == '_' || c == '.'
 }
@@ -632,9 +946,25 @@ fn is_ident_or_num_start(c: char) -> bool {
     c.is_alphabetic() || c == '_' || c == '-'
 }
 
+/// Whether `c` belongs to the number literal in `buf` rather than starting a new token: a single
+/// decimal point followed by a digit, or the sign of an exponent
+fn continues_float_literal(buf: &str, c: char, next: char) -> bool {
+    let is_decimal = buf.starts_with(|c: char| c.is_ascii_digit())
+        && !buf.starts_with("0x")
+        && !buf.starts_with("0b");
+    if !is_decimal || !next.is_ascii_digit() {
+        return false;
+    }
+    match c {
+        '.' => buf.chars().all(|c| c.is_ascii_digit()),
+        '-' | '+' => buf.ends_with(['e', 'E']),
+        _ => false,
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use crate::lex::{Lexer, Span, TokenKind};
+    use crate::lex::{unescape, Lexer, Span, TokenKind};
     use crate::lex::{Spans, TokenKind as K};
 
     use super::Token;
@@ -707,6 +1037,83 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn float_literals() -> anyhow::Result<()> {
+        let input = "12.0 + 1.5e-3 * 2E+8 - x.y";
+        let (spans, tokens) = set_up(input)?;
+        let kinds: Vec<TokenKind> = tokens.iter().map(|t| t.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                K::Ident,
+                K::Plus,
+                K::Ident,
+                K::Asterisk,
+                K::Ident,
+                K::Minus,
+                K::Ident,
+                K::Dot,
+                K::Ident
+            ]
+        );
+        assert_eq!(spans.get(tokens[0].span).len, 4);
+        assert_eq!(spans.get(tokens[2].span).len, 6);
+        assert_eq!(spans.get(tokens[4].span).len, 4);
+        Ok(())
+    }
+
+    #[test]
+    fn lambda_and_function_type() -> anyhow::Result<()> {
+        expect_token_kinds(
+            r"\x -> and(x)",
+            vec![
+                K::Backslash,
+                K::Ident,
+                K::Minus,
+                K::CloseAngle,
+                K::Ident,
+                K::OpenParen,
+                K::Ident,
+                K::CloseParen,
+            ],
+        )?;
+        expect_token_kinds(
+            "fn(int) -> int",
+            vec![
+                K::KeywordFn,
+                K::OpenParen,
+                K::Ident,
+                K::CloseParen,
+                K::Minus,
+                K::CloseAngle,
+                K::Ident,
+            ],
+        )
+    }
+
+    #[test]
+    fn break_and_continue() -> anyhow::Result<()> {
+        expect_token_kinds(
+            "{ continue; break x }",
+            vec![
+                K::OpenBrace,
+                K::KeywordContinue,
+                K::Semicolon,
+                K::KeywordBreak,
+                K::Ident,
+                K::CloseBrace,
+            ],
+        )
+    }
+
+    #[test]
+    fn blanket_impl() -> anyhow::Result<()> {
+        expect_token_kinds(
+            "impl[T] Show",
+            vec![K::KeywordImpl, K::OpenBracket, K::Ident, K::CloseBracket, K::Ident],
+        )
+    }
+
     #[test]
     fn literal_string() -> anyhow::Result<()> {
         let input = "val x = println(\"foobear\")";
@@ -739,6 +1146,132 @@ mod test {
         expect_token_kinds(input, vec![K::Ident, K::EqualsEquals, K::Ident])
     }
 
+    #[test]
+    fn ranges() -> anyhow::Result<()> {
+        let input = "0..n 1..=9 1.5..2 [x, ..rest]";
+        expect_token_kinds(
+            input,
+            vec![
+                K::Ident,
+                K::DotDot,
+                K::Ident,
+                K::Ident,
+                K::DotDotEquals,
+                K::Ident,
+                K::Ident,
+                K::DotDot,
+                K::Ident,
+                K::OpenBracket,
+                K::Ident,
+                K::Comma,
+                K::DotDot,
+                K::Ident,
+                K::CloseBracket,
+            ],
+        )
+    }
+
+    #[test]
+    fn block_comment() -> anyhow::Result<()> {
+        let input = "a /* outer /* inner */ still a comment */ b";
+        let (spans, tokens) = set_up(input)?;
+        let kinds: Vec<TokenKind> = tokens.iter().map(|t| t.kind).collect();
+        assert_eq!(kinds, vec![K::Ident, K::BlockComment, K::Ident]);
+        assert_eq!(spans.get(tokens[1].span), Span { start: 2, len: 39, file_id: 0 });
+        assert!(tokens[2].is_whitespace_preceeded());
+        Ok(())
+    }
+
+    #[test]
+    fn unterminated_block_comment() {
+        let input = "a /* outer /* inner */ b";
+        let mut spans = Spans::new();
+        let err = Lexer::make(input, &mut spans, 0).run().unwrap_err();
+        assert_eq!(err.msg, "Unterminated block comment");
+        assert_eq!(spans.get(err.span), Span { start: 2, len: 2, file_id: 0 });
+    }
+
+    #[test]
+    fn doc_comment() -> anyhow::Result<()> {
+        let input = "/// Adds one\n//// Not docs\nfn foo";
+        let (spans, tokens) = set_up(input)?;
+        let kinds: Vec<TokenKind> = tokens.iter().map(|t| t.kind).collect();
+        assert_eq!(kinds, vec![K::DocComment, K::LineComment, K::KeywordFn, K::Ident]);
+        assert_eq!(spans.get(tokens[0].span), Span { start: 0, len: 12, file_id: 0 });
+        Ok(())
+    }
+
+    #[test]
+    fn string_delimiters() -> anyhow::Result<()> {
+        let input = r#"a "x\"y" r"C:\dir" """
+  multi "quoted"
+""" b"#;
+        let (spans, tokens) = set_up(input)?;
+        let kinds: Vec<TokenKind> = tokens.iter().map(|t| t.kind).collect();
+        assert_eq!(kinds, vec![K::Ident, K::String, K::String, K::String, K::Ident]);
+        assert_eq!(spans.get(tokens[1].span), Span { start: 2, len: 6, file_id: 0 });
+        assert_eq!(spans.get(tokens[2].span), Span { start: 9, len: 9, file_id: 0 });
+        assert_eq!(spans.get(tokens[3].span), Span { start: 19, len: 24, file_id: 0 });
+        Ok(())
+    }
+
+    #[test]
+    fn char_escapes() -> anyhow::Result<()> {
+        expect_token_kinds(r"'\\' '\'' 'a'", vec![K::Char, K::Char, K::Char])
+    }
+
+    #[test]
+    fn interpolation() -> anyhow::Result<()> {
+        let input = r#""a {x} b {p.f({ 1 })} \{c\} {"in{y}ner"}""#;
+        expect_token_kinds(
+            input,
+            vec![
+                K::StringStart,
+                K::Ident,
+                K::StringMiddle,
+                K::Ident,
+                K::Dot,
+                K::Ident,
+                K::OpenParen,
+                K::OpenBrace,
+                K::Ident,
+                K::CloseBrace,
+                K::CloseParen,
+                K::StringMiddle,
+                K::StringStart,
+                K::Ident,
+                K::StringEnd,
+                K::StringEnd,
+            ],
+        )
+    }
+
+    #[test]
+    fn newline_in_string() {
+        let mut spans = Spans::new();
+        let err = Lexer::make("\"abc\n\"", &mut spans, 0).run().unwrap_err();
+        assert_eq!(err.msg, "No newlines inside strings");
+        assert_eq!(spans.get(err.span), Span { start: 0, len: 4, file_id: 0 });
+    }
+
+    #[test]
+    fn escapes() {
+        let mut out = String::new();
+        let errors = unescape(r#"a\n\t\\\"\0\x41\u{1F600}\u{e9}"#, &mut out);
+        assert!(errors.is_empty());
+        assert_eq!(out, "a\n\t\\\"\0A\u{1F600}\u{e9}");
+    }
+
+    #[test]
+    fn bad_escapes() {
+        let mut out = String::new();
+        let errors = unescape(r"\q ok \xZZ \x80 \u{110000} \u{12", &mut out);
+        let located: Vec<(u32, u32)> = errors.iter().map(|e| (e.offset, e.len)).collect();
+        assert_eq!(located, vec![(0, 2), (6, 4), (11, 4), (16, 10), (27, 5)]);
+        assert_eq!(errors[0].msg, "a valid escape sequence");
+        assert_eq!(out, " ok    ");
+    }
+
     #[test]
     fn line_comment() -> anyhow::Result<()> {
         let input = r#"// Hello, world
@@ -1,5 +1,6 @@
 pub mod codegen_llvm;
 pub mod compiler;
+pub mod diagnostics;
 pub mod gui;
 pub mod lex;
 pub mod parse;
@@ -5,6 +5,7 @@ use log::trace;
 use string_interner::backend::StringBackend;
 use string_interner::Symbol;
 
+use crate::diagnostics::Diagnostic;
 use crate::lex::*;
 use crate::typer::{BinaryOpKind, Linkage};
 use TokenKind as K;
@@ -58,6 +59,13 @@ impl ParsedId {
             _ => panic!("Expected type definition"),
         }
     }
+
+    pub fn expect_function(self) -> ParsedFunctionId {
+        match self {
+            ParsedId::Function(id) => id,
+            _ => panic!("Expected function"),
+        }
+    }
 }
 
 impl From<ParsedTypeExpressionId> for ParsedId {
@@ -106,6 +114,18 @@ pub struct ArrayExpr {
     pub span: SpanId,
 }
 
+#[derive(Debug, Clone)]
+pub enum InterpolatedStringPart {
+    String(String, SpanId),
+    Expr(ParsedExpressionId),
+}
+
+#[derive(Debug, Clone)]
+pub struct InterpolatedString {
+    pub parts: Vec<InterpolatedStringPart>,
+    pub span: SpanId,
+}
+
 #[derive(Debug, Clone)]
 pub struct ParsedNumericLiteral {
     pub text: String,
@@ -189,7 +209,7 @@ pub struct Identifiers {
     intern_pool: string_interner::StringInterner<StringBackend>,
 }
 impl Identifiers {
-    pub const BUILTIN_IDENTS: [&'static str; 15] = [
+    pub const BUILTIN_IDENTS: [&'static str; 22] = [
         "self",
         "it",
         "unit",
@@ -198,6 +218,11 @@ impl Identifiers {
         "length",
         "iteree",
         "it_index",
+        "next_index",
+        "next_item",
+        "next_value",
+        "has_next",
+        "range_remaining",
         "as",
         "array_lit",
         "yielded_coll",
@@ -205,6 +230,8 @@ impl Identifiers {
         "block_expr_val",
         "optelse_lhs",
         "array_literal",
+        "try_value",
+        "optchain_base",
     ];
 
     pub fn intern(&mut self, s: impl AsRef<str>) -> Identifier {
@@ -282,6 +309,7 @@ pub enum ParsedUnaryOpKind {
     BooleanNegation,
     Reference,
     Dereference,
+    Negation,
 }
 
 impl Display for ParsedUnaryOpKind {
@@ -290,6 +318,7 @@ impl Display for ParsedUnaryOpKind {
             ParsedUnaryOpKind::BooleanNegation => f.write_str("not "),
             ParsedUnaryOpKind::Reference => f.write_char('&'),
             ParsedUnaryOpKind::Dereference => f.write_char('*'),
+            ParsedUnaryOpKind::Negation => f.write_char('-'),
         }
     }
 }
@@ -300,6 +329,7 @@ impl ParsedUnaryOpKind {
             TokenKind::KeywordNot => Some(ParsedUnaryOpKind::BooleanNegation),
             TokenKind::Ampersand => Some(ParsedUnaryOpKind::Reference),
             TokenKind::Asterisk => Some(ParsedUnaryOpKind::Dereference),
+            TokenKind::Minus => Some(ParsedUnaryOpKind::Negation),
             _ => None,
         }
     }
@@ -368,6 +398,23 @@ pub struct OptionalGet {
     pub span: SpanId,
 }
 
+/// `foo?`: unwraps an Ok or Some, or returns the Err or None from the enclosing function
+#[derive(Debug, Clone)]
+pub struct ParsedTry {
+    pub base: ParsedExpressionId,
+    pub span: SpanId,
+}
+
+/// `foo?.bar`, `foo?.bar()`: `access` is evaluated against `binding`, the payload of `base`,
+/// only when `base` has a value
+#[derive(Debug, Clone)]
+pub struct ParsedOptionalChain {
+    pub base: ParsedExpressionId,
+    pub binding: Identifier,
+    pub access: ParsedExpressionId,
+    pub span: SpanId,
+}
+
 #[derive(Debug, Clone)]
 pub struct AnonEnumVariant {
     pub name: Identifier,
@@ -391,6 +438,8 @@ pub struct ParsedIsExpression {
 #[derive(Debug, Clone)]
 pub struct ParsedMatchCase {
     pub pattern: ParsedPatternId,
+    /// `if <expr>` after the pattern; the arm is only taken if the guard is also true
+    pub guard: Option<ParsedExpressionId>,
     pub expression: ParsedExpressionId,
 }
 
@@ -408,6 +457,29 @@ pub struct ParsedAsCast {
     pub span: SpanId,
 }
 
+#[derive(Debug, Clone)]
+pub struct LambdaArgDefn {
+    pub binding: Identifier,
+    /// Optional; when missing, the type comes from the lambda's expected function type
+    pub ty: Option<ParsedTypeExpressionId>,
+    pub span: SpanId,
+}
+
+#[derive(Debug, Clone)]
+pub struct ParsedLambda {
+    pub arguments: Vec<LambdaArgDefn>,
+    pub return_type: Option<ParsedTypeExpressionId>,
+    pub body: ParsedExpressionId,
+    pub span: SpanId,
+}
+
+#[derive(Debug, Clone)]
+pub struct ParsedBreak {
+    /// Only `for ... yield` loops can be broken out of with a value
+    pub value: Option<ParsedExpressionId>,
+    pub span: SpanId,
+}
+
 #[derive(Debug, Clone)]
 pub enum ParsedExpression {
     BinaryOp(BinaryOp),                     // a == b
@@ -421,6 +493,8 @@ pub enum ParsedExpression {
     Struct(Struct),           // { x: 1, y: 3 }
     Array(ArrayExpr),         // [1, 3, 5, 7]
     OptionalGet(OptionalGet), // foo!
+    Try(ParsedTry),           // foo?
+    OptionalChain(ParsedOptionalChain), // foo?.bar
     For(ForExpr),             // for i in [1,2,3] do println(i)
     AnonEnumVariant(AnonEnumVariant), // .A
     EnumConstructor(ParsedEnumConstructor), // .A(<expr>)
@@ -430,6 +504,10 @@ pub enum ParsedExpression {
     // | b => ...
     // }
     AsCast(ParsedAsCast),
+    InterpolatedString(InterpolatedString), // "x = {x}"
+    Lambda(ParsedLambda),                   // \x -> x + 1
+    Break(ParsedBreak),                     // break, break x
+    Continue(SpanId),                       // continue
 }
 
 impl ParsedExpression {
@@ -456,12 +534,18 @@ impl ParsedExpression {
             Self::Struct(struc) => struc.span,
             Self::Array(array_expr) => array_expr.span,
             Self::OptionalGet(optional_get) => optional_get.span,
+            Self::Try(try_expr) => try_expr.span,
+            Self::OptionalChain(chain) => chain.span,
             Self::For(for_expr) => for_expr.span,
             Self::AnonEnumVariant(tag_expr) => tag_expr.span,
             Self::EnumConstructor(e) => e.span,
             Self::Is(is_expr) => is_expr.span,
             Self::Match(match_expr) => match_expr.span,
             Self::AsCast(as_cast) => as_cast.span,
+            Self::InterpolatedString(interpolated) => interpolated.span,
+            Self::Lambda(lambda) => lambda.span,
+            Self::Break(brk) => brk.span,
+            Self::Continue(span) => *span,
         }
     }
 
@@ -478,12 +562,18 @@ impl ParsedExpression {
             Self::Struct(_struct) => false,
             Self::Array(_array_expr) => false,
             Self::OptionalGet(_optional_get) => false,
+            Self::Try(_try_expr) => false,
+            Self::OptionalChain(_chain) => false,
             Self::For(_) => false,
             Self::AnonEnumVariant(_) => false,
             Self::EnumConstructor(_) => false,
             Self::Is(_) => false,
             Self::Match(_) => false,
             Self::AsCast(_) => false,
+            Self::InterpolatedString(_) => false,
+            Self::Lambda(_) => false,
+            Self::Break(_) => false,
+            Self::Continue(_) => false,
         }
     }
 
@@ -543,6 +633,44 @@ pub struct ParsedEnumPattern {
     pub span: SpanId,
 }
 
+#[derive(Debug, Clone)]
+pub struct ParsedOrPattern {
+    pub alternatives: Vec<ParsedPatternId>,
+    pub span: SpanId,
+}
+
+#[derive(Debug, Clone)]
+pub struct ParsedBindingPattern {
+    pub name: Identifier,
+    pub inner_pattern: ParsedPatternId,
+    pub span: SpanId,
+}
+
+/// `[first, second, ..rest]`; `rest` is a variable or wildcard pattern for the remaining elements
+#[derive(Debug, Clone)]
+pub struct ParsedArrayPattern {
+    pub elements: Vec<ParsedPatternId>,
+    pub rest: Option<ParsedPatternId>,
+    pub span: SpanId,
+}
+
+/// `1..=9`
+#[derive(Debug, Clone)]
+pub struct ParsedRangePattern {
+    pub start: ParsedExpressionId,
+    pub end: ParsedExpressionId,
+    pub inclusive: bool,
+    pub span: SpanId,
+}
+
+/// `"GET " ++ path`
+#[derive(Debug, Clone)]
+pub struct ParsedStringPrefixPattern {
+    pub prefix: ParsedExpressionId,
+    pub rest: ParsedPatternId,
+    pub span: SpanId,
+}
+
 #[derive(Debug, Clone)]
 pub struct ParsedSomePattern {
     pub inner_pattern: ParsedPatternId,
@@ -550,7 +678,11 @@ pub struct ParsedSomePattern {
 }
 
 // https://bnfplayground.pauliankline.com/?bnf=%3Cpattern%3E%20%3A%3A%3D%20%3Cliteral%3E%20%7C%20%3Cvariable%3E%20%7C%20%3Cenum%3E%20%7C%20%3Cstruct%3E%0A%3Cliteral%3E%20%3A%3A%3D%20%22(%22%20%22)%22%20%7C%20%22%5C%22%22%20%3Cident%3E%20%22%5C%22%22%20%7C%20%5B0-9%5D%2B%20%7C%20%22%27%22%20%5Ba-z%5D%20%22%27%22%20%7C%20%22None%22%0A%3Cvariable%3E%20%3A%3A%3D%20%3Cident%3E%0A%3Cident%3E%20%3A%3A%3D%20%5Ba-z%5D*%0A%3Cenum%3E%20%3A%3A%3D%20%22.%22%20%3Cident%3E%20(%20%22(%22%20%3Cpattern%3E%20%22)%22%20)%3F%0A%3Cstruct%3E%20%3A%3A%3D%20%22%7B%22%20(%20%3Cident%3E%20%22%3A%20%22%20%3Cpattern%3E%20%22%2C%22%3F%20)*%20%22%7D%22%20&name=
-// <pattern> ::= <literal> | <variable> | <enum> | <struct>
+// <pattern> ::= <single> ( "|" <single> )*
+// <single> ::= <literal> | <variable> | <enum> | <struct> | <binding> | <array> | <prefix>
+// <binding> ::= <ident> "@" <single>
+// <array> ::= "[" ( <pattern> ","? )* ( ".." <ident>? )? "]"
+// <prefix> ::= "\"" <ident> "\"" "++" <single>
 // <literal> ::= "(" ")" | "\"" <ident> "\"" | [0-9]+ | "'" [a-z] "'" | "None"
 // <variable> ::= <ident>
 // <ident> ::= [a-z]*
@@ -563,6 +695,11 @@ pub enum ParsedPattern {
     Struct(ParsedStructPattern),
     Enum(ParsedEnumPattern),
     Wildcard(SpanId),
+    Or(ParsedOrPattern),
+    Binding(ParsedBindingPattern),
+    Array(ParsedArrayPattern),
+    StringPrefix(ParsedStringPrefixPattern),
+    Range(ParsedRangePattern),
 }
 
 impl ParsedPattern {}
@@ -590,6 +727,12 @@ pub struct WhileStmt {
     pub span: SpanId,
 }
 
+#[derive(Debug, Clone)]
+pub struct DeferStmt {
+    pub expr: ParsedExpressionId,
+    pub span: SpanId,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ForExprType {
     Yield,
@@ -611,6 +754,7 @@ pub enum ParsedStmt {
     Assignment(Assignment),             // x = 42
     LoneExpression(ParsedExpressionId), // println("asdfasdf")
     While(WhileStmt),
+    Defer(DeferStmt), // defer free(ptr)
 }
 
 #[derive(Debug, Clone)]
@@ -650,6 +794,14 @@ pub struct ParsedReference {
     pub span: SpanId,
 }
 
+/// `fn(int, string) -> bool`
+#[derive(Debug, Clone)]
+pub struct ParsedFunctionType {
+    pub params: Vec<ParsedTypeExpressionId>,
+    pub return_type: ParsedTypeExpressionId,
+    pub span: SpanId,
+}
+
 #[derive(Debug, Clone)]
 pub struct ParsedEnumVariant {
     pub tag_name: Identifier,
@@ -713,6 +865,7 @@ pub enum ParsedTypeExpression {
     Reference(ParsedReference),
     Enum(ParsedEnumType),
     DotMemberAccess(ParsedDotMemberAccess),
+    Function(ParsedFunctionType),
 }
 
 impl ParsedTypeExpression {
@@ -732,6 +885,7 @@ impl ParsedTypeExpression {
             ParsedTypeExpression::Reference(r) => r.span,
             ParsedTypeExpression::Enum(e) => e.span,
             ParsedTypeExpression::DotMemberAccess(a) => a.span,
+            ParsedTypeExpression::Function(f) => f.span,
         }
     }
 }
@@ -760,6 +914,7 @@ pub struct ParsedFunction {
     pub span: SpanId,
     pub linkage: Linkage,
     pub id: ParsedFunctionId,
+    pub doc_comment: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -817,19 +972,29 @@ pub struct ParsedTypeDefn {
     pub span: SpanId,
     pub id: ParsedTypeDefnId,
     pub flags: ParsedTypeDefnFlags,
+    pub doc_comment: Option<String>,
+    /// `type Point = { x: int, y: int } deriving(Equals, Show)`
+    pub deriving: Vec<NamespacedIdentifier>,
 }
 
 #[derive(Debug, Clone)]
 pub struct ParsedAbility {
     pub name: Identifier,
+    /// `ability Into[T]`
+    pub type_params: Vec<ParsedTypeParamDefn>,
     pub functions: Vec<ParsedFunctionId>,
     pub span: SpanId,
     pub id: ParsedAbilityId,
+    pub doc_comment: Option<String>,
 }
 
 #[derive(Debug, Clone)]
 pub struct ParsedAbilityImplementation {
     pub ability_name: Identifier,
+    /// `impl[T] Equals for Array[T] where T: Equals`
+    pub type_params: Vec<ParsedTypeParamDefn>,
+    /// `impl Into[string] for int`
+    pub ability_type_args: Vec<ParsedTypeExpressionId>,
     pub target_type: ParsedTypeExpressionId,
     pub functions: Vec<ParsedFunctionId>,
     pub id: ParsedAbilityImplId,
@@ -843,6 +1008,7 @@ pub struct ParsedNamespace {
     pub definitions: Vec<ParsedId>,
     pub id: ParsedNamespaceId,
     pub span: SpanId,
+    pub doc_comment: Option<String>,
 }
 
 #[derive(Debug, Default, Clone)]
@@ -971,6 +1137,11 @@ pub struct ParsedModule {
     pub type_expressions: ParsedTypeExpressionPool,
     pub patterns: ParsedPatternPool,
     pub errors: Vec<ParseError>,
+    /// A file that fails to lex contributes nothing to the module but its error
+    pub lex_errors: Vec<LexError>,
+    /// Doc comments found by `lex_text`, keyed by the span of the token that follows them,
+    /// waiting to be attached to the definition that starts with that token
+    doc_comments: HashMap<SpanId, String>,
 }
 
 impl ParsedModule {
@@ -993,6 +1164,8 @@ impl ParsedModule {
             type_expressions: ParsedTypeExpressionPool::default(),
             patterns: ParsedPatternPool::default(),
             errors: Vec::new(),
+            lex_errors: Vec::new(),
+            doc_comments: HashMap::new(),
         }
     }
 
@@ -1030,6 +1203,11 @@ impl ParsedModule {
             ParsedPattern::Variable(_var_pattern, span) => *span,
             ParsedPattern::Struct(struct_pattern) => struct_pattern.span,
             ParsedPattern::Wildcard(span) => *span,
+            ParsedPattern::Or(or_pattern) => or_pattern.span,
+            ParsedPattern::Binding(binding_pattern) => binding_pattern.span,
+            ParsedPattern::Array(array_pattern) => array_pattern.span,
+            ParsedPattern::StringPrefix(prefix_pattern) => prefix_pattern.span,
+            ParsedPattern::Range(range_pattern) => range_pattern.span,
         }
     }
 
@@ -1039,6 +1217,7 @@ impl ParsedModule {
             ParsedStmt::Assignment(a) => a.span,
             ParsedStmt::LoneExpression(expr_id) => self.expressions.get_span(*expr_id),
             ParsedStmt::While(w) => w.span,
+            ParsedStmt::Defer(defer) => defer.span,
         }
     }
 
@@ -1139,6 +1318,26 @@ impl ParseError {
     pub fn span(&self) -> SpanId {
         self.token.span
     }
+
+    fn describe(&self, spans: &Spans, sources: &Sources) -> String {
+        let got_str = if matches!(self.token.kind, K::Ident | K::String | K::Char) {
+            sources.get_span_content(spans.get(self.token.span)).to_string()
+        } else {
+            self.token.kind.to_string()
+        };
+        format!("Expected '{}', but got '{}'", self.expected, got_str)
+    }
+
+    /// The errors in the cause chain become secondary labels, each at its own location
+    pub fn to_diagnostic(&self, spans: &Spans, sources: &Sources) -> Diagnostic {
+        let mut diagnostic = Diagnostic::error(self.describe(spans, sources), self.span());
+        let mut cause = self.cause.as_deref();
+        while let Some(c) = cause {
+            diagnostic = diagnostic.with_label(c.span(), c.describe(spans, sources));
+            cause = c.cause.as_deref();
+        }
+        diagnostic
+    }
 }
 
 impl Display for ParseError {
@@ -1161,30 +1360,6 @@ pub fn get_span_source_line<'sources>(
     line
 }
 
-pub fn print_error_location(spans: &Spans, sources: &Sources, span_id: SpanId) {
-    let span = spans.get(span_id);
-    let source = sources.source_by_span(span);
-    let Some(line) = source.get_line_for_span(span) else {
-        eprintln!("Error: could not find line for span {:?}", span);
-        return;
-    };
-    use colored::*;
-
-    // If the span is longer than the line, just highlight the whole line
-    let highlight_length =
-        if span.len as usize > line.content.len() { line.content.len() } else { span.len as usize };
-    let thingies = "^".repeat(highlight_length);
-    let spaces = " ".repeat((span.start - line.start_char) as usize);
-    let code = format!("  ->{}\n  ->{spaces}{thingies}", &line.content);
-    println!(
-        "  {} at {}/{}:{}\n\n{code}",
-        "Error".red(),
-        source.directory,
-        source.filename,
-        line.line_index + 1,
-    );
-}
-
 #[derive(Debug, Clone)]
 pub struct Line {
     pub start_char: u32,
@@ -1210,6 +1385,8 @@ pub struct Source {
     /// This is an inefficient copy but we need the lines cached because utf8
     /// Eventually it can be references not copies
     pub lines: Vec<Line>,
+    /// Library sources, like core, are not linted for unused functions
+    pub is_library: bool,
 }
 
 impl Source {
@@ -1239,7 +1416,7 @@ impl Source {
                 buf.push(c);
             }
         }
-        Source { file_id, directory, filename, content, lines }
+        Source { file_id, directory, filename, content, lines, is_library: false }
     }
 
     pub fn get_content(&self, start: u32, len: u32) -> &str {
@@ -1302,11 +1479,106 @@ impl<'toks, 'module> Parser<'toks, 'module> {
     }
 
     fn expect_pattern(&mut self) -> ParseResult<ParsedPatternId> {
-        let first = self.peek();
+        let first_alternative = self.expect_single_pattern()?;
+        if self.peek().kind != K::Pipe {
+            return Ok(first_alternative);
+        }
+        let mut alternatives = vec![first_alternative];
+        while self.peek().kind == K::Pipe {
+            self.tokens.advance();
+            alternatives.push(self.expect_single_pattern()?);
+        }
+        let first_span = self.module.get_pattern_span(first_alternative);
+        let last_span = self.module.get_pattern_span(*alternatives.last().unwrap());
+        let span = self.extend_span(first_span, last_span);
+        let pattern = ParsedPattern::Or(ParsedOrPattern { alternatives, span });
+        Ok(self.module.patterns.add_pattern(pattern))
+    }
+
+    fn expect_single_pattern(&mut self) -> ParseResult<ParsedPatternId> {
+        let (first, second) = self.peek_two();
+        if first.kind == K::Ident && second.kind == K::At {
+            let ident_token = self.tokens.next();
+            self.tokens.advance();
+            let name = self.intern_ident_token(ident_token);
+            let inner_pattern = self.expect_single_pattern()?;
+            let inner_span = self.module.get_pattern_span(inner_pattern);
+            let span = self.extend_span(ident_token.span, inner_span);
+            let pattern =
+                ParsedPattern::Binding(ParsedBindingPattern { name, inner_pattern, span });
+            return Ok(self.module.patterns.add_pattern(pattern));
+        }
+        if first.kind == K::StringStart {
+            return Err(Parser::error("a string pattern without interpolation", first));
+        }
         if let Some(literal_id) = self.parse_literal()? {
+            let (plus1, plus2) = self.peek_two();
+            if first.kind == K::String && plus1.kind == K::Plus && plus2.kind == K::Plus {
+                self.tokens.advance();
+                self.tokens.advance();
+                let rest = self.expect_single_pattern()?;
+                let span = self.extend_span(first.span, self.module.get_pattern_span(rest));
+                let pattern = ParsedPattern::StringPrefix(ParsedStringPrefixPattern {
+                    prefix: literal_id,
+                    rest,
+                    span,
+                });
+                return Ok(self.module.patterns.add_pattern(pattern));
+            }
+            let range_token = self.peek();
+            if range_token.kind == K::DotDot || range_token.kind == K::DotDotEquals {
+                self.tokens.advance();
+                let end = Parser::expect("range pattern end", self.peek(), self.parse_literal())?;
+                let span = self.extend_span(first.span, self.get_expression_span(end));
+                let pattern = ParsedPattern::Range(ParsedRangePattern {
+                    start: literal_id,
+                    end,
+                    inclusive: range_token.kind == K::DotDotEquals,
+                    span,
+                });
+                return Ok(self.module.patterns.add_pattern(pattern));
+            }
             let pattern = ParsedPattern::Literal(literal_id);
             let id = self.module.patterns.add_pattern(pattern);
             Ok(id)
+        } else if first.kind == K::OpenBracket {
+            let open_bracket = self.tokens.next();
+            let mut elements = Vec::new();
+            let mut rest = None;
+            while self.peek().kind != K::CloseBracket {
+                if self.peek().kind == K::DotDot {
+                    let dot_dot = self.tokens.next();
+                    let rest_pattern = if self.peek().kind == K::Ident {
+                        let ident_token = self.tokens.next();
+                        let ident = self.intern_ident_token(ident_token);
+                        if self.module.identifiers.get_name(ident) == "_" {
+                            ParsedPattern::Wildcard(ident_token.span)
+                        } else {
+                            ParsedPattern::Variable(ident, ident_token.span)
+                        }
+                    } else {
+                        ParsedPattern::Wildcard(dot_dot.span)
+                    };
+                    rest = Some(self.module.patterns.add_pattern(rest_pattern));
+                    // The rest pattern is always last
+                    break;
+                }
+                elements.push(self.expect_pattern()?);
+                let next = self.peek();
+                if next.kind == K::Comma {
+                    self.tokens.advance();
+                } else if next.kind != K::CloseBracket {
+                    return Err(ParseError {
+                        expected: "comma or close bracket".to_string(),
+                        token: next,
+                        cause: None,
+                    });
+                }
+            }
+            let close_bracket = self.expect_eat_token(K::CloseBracket)?;
+            let span = self.extend_token_span(open_bracket, close_bracket);
+            let pattern = ParsedPattern::Array(ParsedArrayPattern { elements, rest, span });
+            Ok(self.module.patterns.add_pattern(pattern))
         } else if first.kind == K::OpenBrace {
             // Struct
             let open_brace = self.tokens.next();
@@ -1382,21 +1654,8 @@ impl<'toks, 'module> Parser<'toks, 'module> {
 
 impl<'toks, 'module> Parser<'toks, 'module> {
     pub fn print_error(&self, parse_error: &ParseError) {
-        let span = parse_error.span();
-
-        if let Some(cause) = &parse_error.cause {
-            self.print_error(cause);
-        }
-        let got_str = if parse_error.token.kind == K::Ident {
-            self.token_chars(parse_error.token).to_string()
-        } else {
-            parse_error.token.kind.to_string()
-        };
-
-        use colored::*;
-
-        print_error_location(&self.module.spans, &self.module.sources, span);
-        println!("\tExpected '{}', but got '{}'\n", parse_error.expected.blue(), got_str,);
+        let diagnostic = parse_error.to_diagnostic(&self.module.spans, &self.module.sources);
+        eprint!("{}", diagnostic.render(&self.module.spans, &self.module.sources));
     }
 
     #[inline]
@@ -1496,52 +1755,48 @@ impl<'toks, 'module> Parser<'toks, 'module> {
                 let text = self.token_chars(first);
                 assert!(text.starts_with('\''));
                 assert!(text.ends_with('\''));
-                let bytes = text.as_bytes();
-                if bytes[1] == b'\\' {
-                    assert_eq!(bytes.len(), 4);
-                    let esc_char = bytes[2];
-                    let literal = match esc_char {
-                        b'n' => Ok(Literal::Char(b'\n', first.span)),
-                        b'0' => Ok(Literal::Char(b'\0', first.span)),
-                        b'\'' => Ok(Literal::Char(b'\'', first.span)),
-                        b't' => Ok(Literal::Char(b'\t', first.span)),
-                        _ => Err(Parser::error(
-                            format!(
-                                "Valid escaped char following escape sequence: {}",
-                                char::from(esc_char)
-                            ),
+                let body = text[1..text.len() - 1].to_string();
+                let value = self.unescape_literal_part(&body, first, 1);
+                let mut chars = value.chars();
+                let byte = match (chars.next(), chars.next()) {
+                    (Some(c), None) if c.is_ascii() => c as u8,
+                    // Any bad escape has already been reported
+                    _ if value.is_empty() && !body.is_empty() => 0,
+                    _ => {
+                        return Err(Parser::error(
+                            "a char literal containing exactly one ascii character",
                             first,
-                        )),
-                    }?;
-                    Ok(Some(self.add_expression(ParsedExpression::Literal(literal))))
-                } else {
-                    assert_eq!(bytes.len(), 3);
-                    let byte = bytes[1];
-                    Ok(Some(self.add_expression(ParsedExpression::Literal(Literal::Char(
-                        byte, first.span,
-                    )))))
-                }
+                        ))
+                    }
+                };
+                Ok(Some(
+                    self.add_expression(ParsedExpression::Literal(Literal::Char(byte, first.span))),
+                ))
             }
             (K::String, _) => {
                 trace!("parse_literal string");
                 self.tokens.advance();
-                let text = self.token_chars(first);
-                let literal = Literal::String(text.to_string(), first.span);
+                let (value, span) = self.string_literal_value(first);
+                let literal = Literal::String(value, span);
                 Ok(Some(self.add_expression(ParsedExpression::Literal(literal))))
             }
-            (K::Minus, K::Ident) if !second.is_whitespace_preceeded() => {
-                let text = self.token_chars(second);
-                if text.chars().next().unwrap().is_numeric() {
-                    let mut s = "-".to_string();
-                    s.push_str(text);
-                    self.tokens.advance();
-                    self.tokens.advance();
-                    let span = self.extend_token_span(first, second);
-                    let numeric = Literal::Numeric(ParsedNumericLiteral { text: s, span });
-                    Ok(Some(self.add_expression(ParsedExpression::Literal(numeric))))
-                } else {
-                    Err(Parser::error("number following '-'", second))
-                }
+            (K::StringStart, _) => {
+                trace!("parse_literal interpolated string");
+                self.tokens.advance();
+                Ok(Some(self.parse_interpolated_string(first)?))
+            }
+            // Anything else after a '-' is a negation, parsed as a prefix operator
+            (K::Minus, K::Ident)
+                if !second.is_whitespace_preceeded()
+                    && self.token_chars(second).starts_with(|c: char| c.is_numeric()) =>
+            {
+                let mut s = "-".to_string();
+                s.push_str(self.token_chars(second));
+                self.tokens.advance();
+                self.tokens.advance();
+                let span = self.extend_token_span(first, second);
+                let numeric = Literal::Numeric(ParsedNumericLiteral { text: s, span });
+                Ok(Some(self.add_expression(ParsedExpression::Literal(numeric))))
             }
             (K::Ident, _) => {
                 let text = self.token_chars(first);
@@ -1636,6 +1891,132 @@ impl<'toks, 'module> Parser<'toks, 'module> {
         Ok(Some(result))
     }
 
+    /// Parses the holes and text segments of a string with interpolated expressions, starting
+    /// after its `StringStart` token
+    fn parse_interpolated_string(&mut self, start: Token) -> ParseResult<ParsedExpressionId> {
+        let mut parts = Vec::new();
+        let mut segment = start;
+        loop {
+            let (value, span) = self.string_literal_value(segment);
+            if !value.is_empty() {
+                parts.push(InterpolatedStringPart::String(value, span));
+            }
+            if segment.kind == K::StringEnd {
+                break;
+            }
+            let expr = self.expect_expression()?;
+            parts.push(InterpolatedStringPart::Expr(expr));
+            let next = self.peek();
+            if next.kind != K::StringMiddle && next.kind != K::StringEnd {
+                return Err(Parser::error("} to end the interpolated expression", next));
+            }
+            self.tokens.advance();
+            segment = next;
+        }
+        let span = self.extend_token_span(start, segment);
+        Ok(self.add_expression(ParsedExpression::InterpolatedString(InterpolatedString {
+            parts,
+            span,
+        })))
+    }
+
+    /// Computes the value of a string literal token, and the span of its contents. Escapes are
+    /// processed except in raw strings (`r"..."`). In a triple-quoted string, a line break
+    /// right after the opening quotes and the line holding the closing quotes are dropped,
+    /// along with the indentation common to every other non-blank line
+    fn string_literal_value(&mut self, token: Token) -> (String, SpanId) {
+        let text = self.token_chars(token).to_string();
+        let (opener, closer) = if token.kind != K::String {
+            // A segment of an interpolated string, delimited by a quote or brace on each side
+            (&text[0..1], &text[text.len() - 1..])
+        } else if text.starts_with("r\"") {
+            ("r\"", "\"")
+        } else if text.starts_with("\"\"\"") && text.len() >= 6 {
+            ("\"\"\"", "\"\"\"")
+        } else {
+            ("\"", "\"")
+        };
+        let body = &text[opener.len()..text.len() - closer.len()];
+        let span = self.module.spans.get(token.span);
+        let content_span = self.module.spans.add(Span {
+            start: span.start + opener.len() as u32,
+            len: body.chars().count() as u32,
+            file_id: span.file_id,
+        });
+        if opener == "r\"" {
+            return (body.to_string(), content_span);
+        }
+        if opener.len() == 1 {
+            let value = self.unescape_literal_part(body, token, 1);
+            return (value, content_span);
+        }
+
+        // Each line, with its offset in chars from the start of the token
+        let mut lines: Vec<(u32, &str)> = Vec::new();
+        let mut offset = opener.len() as u32;
+        for line in body.split('\n') {
+            lines.push((offset, line.strip_suffix('\r').unwrap_or(line)));
+            offset += line.chars().count() as u32 + 1;
+        }
+        let is_blank = |line: &str| line.trim().is_empty();
+        if lines.len() > 1 && is_blank(lines[0].1) {
+            lines.remove(0);
+        }
+        if lines.len() > 1 && is_blank(lines[lines.len() - 1].1) {
+            lines.pop();
+        }
+        let indent_of = |line: &str| line.chars().take_while(|c| *c == ' ' || *c == '\t').count();
+        let common_indent = lines
+            .iter()
+            .filter(|(_, line)| !is_blank(line))
+            .map(|(_, line)| indent_of(line))
+            .min()
+            .unwrap_or(0);
+        let mut value = String::new();
+        // A line ending in a lone `\` continues onto the next one, leaving out the line break
+        // and the next line's indentation
+        let mut continues_previous = false;
+        for (index, (offset, line)) in lines.iter().enumerate() {
+            if index > 0 && !continues_previous {
+                value.push('\n');
+            }
+            let strip = if continues_previous {
+                indent_of(line)
+            } else {
+                common_indent.min(indent_of(line))
+            };
+            let trailing_backslashes = line.chars().rev().take_while(|c| *c == '\\').count();
+            continues_previous = trailing_backslashes % 2 == 1;
+            let end = if continues_previous { line.len() - 1 } else { line.len() };
+            let line_value =
+                self.unescape_literal_part(&line[strip..end], token, offset + strip as u32);
+            value.push_str(&line_value);
+        }
+        (value, content_span)
+    }
+
+    /// Processes the escapes in `text`, which starts `offset` chars into `token`. Each bad escape
+    /// is recorded as an error with its own span; we carry on, leaving it out of the result
+    fn unescape_literal_part(&mut self, text: &str, token: Token, offset: u32) -> String {
+        let mut value = String::with_capacity(text.len());
+        let token_span = self.module.spans.get(token.span);
+        for escape_error in unescape(text, &mut value) {
+            let span = self.module.spans.add(Span {
+                start: token_span.start + offset + escape_error.offset,
+                len: escape_error.len,
+                file_id: token_span.file_id,
+            });
+            let error_token = Token::new(token.kind, span, false);
+            self.module.errors.push(Parser::error(escape_error.msg, error_token));
+        }
+        value
+    }
+
+    /// The doc comment directly preceding `first_token`, if any
+    fn take_doc_comment(&mut self, first_token: Token) -> Option<String> {
+        self.module.doc_comments.remove(&first_token.span)
+    }
+
     fn get_token_chars(&self, token: Token) -> &str {
         Parser::tok_chars(&self.module.spans, self.source(), token)
     }
@@ -1646,6 +2027,22 @@ impl<'toks, 'module> Parser<'toks, 'module> {
             let enumm = self.expect_enum_type_expression()?;
             let type_expr_id = self.module.type_expressions.add(ParsedTypeExpression::Enum(enumm));
             Ok(Some(type_expr_id))
+        } else if first.kind == K::KeywordFn {
+            self.tokens.advance();
+            self.expect_eat_token(K::OpenParen)?;
+            let (params, _params_span) =
+                self.eat_delimited("Function type parameters", K::Comma, K::CloseParen, |p| {
+                    Parser::expect("type_expression", p.peek(), p.parse_type_expression())
+                })?;
+            self.expect_eat_token(K::Minus)?;
+            self.expect_eat_token(K::CloseAngle)?;
+            let return_type = self.expect_type_expression()?;
+            let span =
+                self.extend_span(first.span, self.module.get_type_expression_span(return_type));
+            let function_type = ParsedFunctionType { params, return_type, span };
+            Ok(Some(
+                self.module.type_expressions.add(ParsedTypeExpression::Function(function_type)),
+            ))
         } else if first.kind == K::KeywordBuiltin {
             self.tokens.advance();
             let builtin_id =
@@ -1822,7 +2219,37 @@ impl<'toks, 'module> Parser<'toks, 'module> {
         // Looping for postfix ops inspired by Jakt's parser
         let with_postfix: ParsedExpressionId = loop {
             let next = self.peek();
-            if next.kind.is_postfix_operator() {
+            let next_is_attached_question_mark =
+                next.kind == K::QuestionMark && !next.is_whitespace_preceeded();
+            // Optional chaining `user?.address?.city`. An attached `?.` always chains, so it
+            // takes precedence over '?' propagation: `result?.len` is a chain, and propagating
+            // first is written `(result?).len`
+            if next_is_attached_question_mark && self.peek_two().1.kind == K::Dot {
+                self.tokens.advance();
+                self.tokens.advance();
+                // The access is parsed against a variable that the typer binds to the payload.
+                // Its name can't be written in source, so nothing in the access can mean it
+                let base_span = self.get_expression_span(result);
+                let binding = self.module.identifiers.intern("<optchain_value>");
+                let payload = self.add_expression(ParsedExpression::Variable(Variable {
+                    name: NamespacedIdentifier::naked(binding, base_span),
+                }));
+                let access = self.expect_field_access_or_method_call(payload)?;
+                let span = self.extend_span(base_span, self.get_expression_span(access));
+                result =
+                    self.add_expression(ParsedExpression::OptionalChain(ParsedOptionalChain {
+                        base: result,
+                        binding,
+                        access,
+                        span,
+                    }));
+            } else if next_is_attached_question_mark {
+                // Error propagation `parse(s)?`; a `?` set apart by whitespace is the binary 'else'
+                self.tokens.advance();
+                let span = self.extend_span(self.get_expression_span(result), next.span);
+                result =
+                    self.add_expression(ParsedExpression::Try(ParsedTry { base: result, span }));
+            } else if next.kind.is_postfix_operator() {
                 // Optional uwrap `config!.url`
                 if next.kind == K::Bang {
                     self.tokens.advance();
@@ -1844,43 +2271,8 @@ impl<'toks, 'module> Parser<'toks, 'module> {
                         span,
                     }));
                 } else if next.kind == K::Dot {
-                    // Field access syntax; a.b with optional bracketed type args []
                     self.tokens.advance();
-                    let target = self.expect_eat_token(K::Ident)?;
-                    let (type_args, type_args_span) = self.parse_optional_type_args()?;
-                    let next = self.peek();
-                    // a.b[int](...)
-                    if next.kind == K::OpenParen {
-                        self.tokens.advance();
-                        let (args, args_span) = self.eat_delimited(
-                            "Function arguments",
-                            K::Comma,
-                            K::CloseParen,
-                            Parser::expect_fn_arg,
-                        )?;
-                        let span = self.extend_span(self.get_expression_span(result), args_span);
-                        let name = self.intern_ident_token(target);
-                        let mut all_args = vec![FnCallArg { name: None, value: result }];
-                        all_args.extend(args);
-                        result = self.add_expression(ParsedExpression::FnCall(FnCall {
-                            name: NamespacedIdentifier::naked(name, target.span),
-                            type_args,
-                            args: all_args,
-                            span,
-                            is_method: true,
-                        }));
-                    } else {
-                        // a.b[int] <complete expression>
-                        let span = self
-                            .extend_span_maybe(self.get_expression_span(result), type_args_span);
-                        let target = self.intern_ident_token(target);
-                        result = self.add_expression(ParsedExpression::FieldAccess(FieldAccess {
-                            base: result,
-                            target,
-                            type_args,
-                            span,
-                        }));
-                    };
+                    result = self.expect_field_access_or_method_call(result)?;
                 }
             } else {
                 break result;
@@ -1894,6 +2286,47 @@ impl<'toks, 'module> Parser<'toks, 'module> {
         Ok(Some(with_postfix))
     }
 
+    /// Field access or method call syntax, after the '.': `a.b`, `a.b[int]`, `a.b[int](...)`
+    fn expect_field_access_or_method_call(
+        &mut self,
+        base: ParsedExpressionId,
+    ) -> ParseResult<ParsedExpressionId> {
+        let target = self.expect_eat_token(K::Ident)?;
+        let (type_args, type_args_span) = self.parse_optional_type_args()?;
+        let next = self.peek();
+        // a.b[int](...)
+        if next.kind == K::OpenParen {
+            self.tokens.advance();
+            let (args, args_span) = self.eat_delimited(
+                "Function arguments",
+                K::Comma,
+                K::CloseParen,
+                Parser::expect_fn_arg,
+            )?;
+            let span = self.extend_span(self.get_expression_span(base), args_span);
+            let name = self.intern_ident_token(target);
+            let mut all_args = vec![FnCallArg { name: None, value: base }];
+            all_args.extend(args);
+            Ok(self.add_expression(ParsedExpression::FnCall(FnCall {
+                name: NamespacedIdentifier::naked(name, target.span),
+                type_args,
+                args: all_args,
+                span,
+                is_method: true,
+            })))
+        } else {
+            // a.b[int] <complete expression>
+            let span = self.extend_span_maybe(self.get_expression_span(base), type_args_span);
+            let target = self.intern_ident_token(target);
+            Ok(self.add_expression(ParsedExpression::FieldAccess(FieldAccess {
+                base,
+                target,
+                type_args,
+                span,
+            })))
+        }
+    }
+
     fn expect_block(&mut self) -> ParseResult<Block> {
         Parser::expect("block", self.peek(), self.parse_block())
     }
@@ -1910,7 +2343,11 @@ impl<'toks, 'module> Parser<'toks, 'module> {
             let mut expr_stack: Vec<ExprStackMember> = vec![ExprStackMember::Expr(expr)];
             let mut last_precedence = 100_000;
             loop {
-                let tok = self.peek();
+                let (tok, next) = self.peek_two();
+                // `->` ends the expression, for example a match arm's guard
+                if tok.kind == K::Minus && next.kind == K::CloseAngle {
+                    break;
+                }
                 let Some(op_kind) = BinaryOpKind::from_tokenkind(tok.kind) else {
                     break;
                 };
@@ -2068,11 +2505,22 @@ impl<'toks, 'module> Parser<'toks, 'module> {
             while self.peek().kind != K::CloseBrace {
                 let arm_pattern_id = self.expect_pattern()?;
 
+                let guard = if self.peek().kind == K::KeywordIf {
+                    self.tokens.advance();
+                    Some(self.expect_expression()?)
+                } else {
+                    None
+                };
+
                 self.expect_eat_token(K::Minus)?;
                 self.expect_eat_token(K::CloseAngle)?;
 
                 let arm_expr_id = self.expect_expression()?;
-                cases.push(ParsedMatchCase { pattern: arm_pattern_id, expression: arm_expr_id });
+                cases.push(ParsedMatchCase {
+                    pattern: arm_pattern_id,
+                    guard,
+                    expression: arm_expr_id,
+                });
                 let next = self.peek();
                 if next.kind == K::Comma {
                     self.tokens.advance();
@@ -2094,6 +2542,20 @@ impl<'toks, 'module> Parser<'toks, 'module> {
             // Note: Here would be where we would support tuples
             self.expect_eat_token(K::CloseParen)?;
             Ok(Some(expr))
+        } else if first.kind == K::Backslash {
+            Ok(Some(self.parse_lambda()?))
+        } else if first.kind == K::KeywordBreak {
+            self.tokens.advance();
+            let value = match second.kind {
+                K::Semicolon | K::CloseBrace | K::CloseParen | K::Comma | K::Eof => None,
+                _ => Some(self.expect_expression()?),
+            };
+            let value_span = value.map(|value| self.get_expression_span(value));
+            let span = self.extend_span_maybe(first.span, value_span);
+            Ok(Some(self.add_expression(ParsedExpression::Break(ParsedBreak { value, span }))))
+        } else if first.kind == K::KeywordContinue {
+            self.tokens.advance();
+            Ok(Some(self.add_expression(ParsedExpression::Continue(first.span))))
         } else if first.kind == K::KeywordFor {
             self.tokens.advance();
             let binding = if third.kind == K::KeywordIn {
@@ -2134,7 +2596,12 @@ impl<'toks, 'module> Parser<'toks, 'module> {
                 return Err(Parser::error("unexpected prefix operator", first));
             };
             self.tokens.advance();
-            let expr = self.expect_expression()?;
+            // Negation binds tighter than any binary operator, so that `-a * b` is `(-a) * b`
+            let expr = if op_kind == ParsedUnaryOpKind::Negation {
+                Parser::expect("expression", self.peek(), self.parse_expression_with_postfix_ops())?
+            } else {
+                self.expect_expression()?
+            };
             let span = self.extend_span(first.span, self.get_expression_span(expr));
             Ok(Some(self.add_expression(ParsedExpression::UnaryOp(UnaryOp {
                 expr,
@@ -2307,6 +2774,56 @@ impl<'toks, 'module> Parser<'toks, 'module> {
         Ok(FnArgDef { name: self.intern_ident_token(name_token), ty: typ, span })
     }
 
+    /// Parses `\x -> body`, or with parens, `\(x, y: int): int -> body`
+    fn parse_lambda(&mut self) -> ParseResult<ParsedExpressionId> {
+        let backslash = self.expect_eat_token(K::Backslash)?;
+        let (arguments, return_type) = if self.peek().kind == K::OpenParen {
+            self.tokens.advance();
+            let (arguments, _span) =
+                self.eat_delimited("Lambda arguments", K::Comma, K::CloseParen, |p| {
+                    let name_token = p.expect_eat_token(K::Ident)?;
+                    let ty = if p.peek().kind == K::Colon {
+                        p.tokens.advance();
+                        Some(p.expect_type_expression()?)
+                    } else {
+                        None
+                    };
+                    let span = match ty {
+                        None => name_token.span,
+                        Some(ty) => {
+                            p.extend_span(name_token.span, p.module.get_type_expression_span(ty))
+                        }
+                    };
+                    Ok(LambdaArgDefn { binding: p.intern_ident_token(name_token), ty, span })
+                })?;
+            let return_type = if self.peek().kind == K::Colon {
+                self.tokens.advance();
+                Some(self.expect_type_expression()?)
+            } else {
+                None
+            };
+            (arguments, return_type)
+        } else {
+            let name_token = self.expect_eat_token(K::Ident)?;
+            let argument = LambdaArgDefn {
+                binding: self.intern_ident_token(name_token),
+                ty: None,
+                span: name_token.span,
+            };
+            (vec![argument], None)
+        };
+        self.expect_eat_token(K::Minus)?;
+        self.expect_eat_token(K::CloseAngle)?;
+        let body = self.expect_expression()?;
+        let span = self.extend_span(backslash.span, self.get_expression_span(body));
+        Ok(self.add_expression(ParsedExpression::Lambda(ParsedLambda {
+            arguments,
+            return_type,
+            body,
+            span,
+        })))
+    }
+
     fn eat_fndef_args(&mut self) -> ParseResult<(Vec<FnArgDef>, SpanId)> {
         self.eat_delimited("Function arguments", K::Comma, K::CloseParen, Parser::eat_fn_arg_def)
     }
@@ -2434,10 +2951,21 @@ impl<'toks, 'module> Parser<'toks, 'module> {
         Ok(Some(WhileStmt { cond, block, span }))
     }
 
+    fn parse_defer(&mut self) -> ParseResult<Option<DeferStmt>> {
+        let Some(defer_token) = self.eat_token(K::KeywordDefer) else {
+            return Ok(None);
+        };
+        let expr = self.expect_expression()?;
+        let span = self.extend_span(defer_token.span, self.get_expression_span(expr));
+        Ok(Some(DeferStmt { expr, span }))
+    }
+
     fn parse_statement(&mut self) -> ParseResult<Option<ParsedStmt>> {
         trace!("eat_statement {:?}", self.peek());
         if let Some(while_loop) = self.parse_while_loop()? {
             Ok(Some(ParsedStmt::While(while_loop)))
+        } else if let Some(defer) = self.parse_defer()? {
+            Ok(Some(ParsedStmt::Defer(defer)))
         } else if let Some(mut_def) = self.parse_mut()? {
             Ok(Some(ParsedStmt::ValDef(mut_def)))
         } else if let Some(val_def) = self.parse_val(false)? {
@@ -2462,11 +2990,42 @@ impl<'toks, 'module> Parser<'toks, 'module> {
         let Some(block_start) = self.eat_token(K::OpenBrace) else {
             return Ok(None);
         };
-        let parse_statement =
-            |p: &mut Parser| Parser::expect("statement", p.peek(), Parser::parse_statement(p));
-        let (block_statements, statements_span) =
-            self.eat_delimited("Block statements", K::Semicolon, K::CloseBrace, parse_statement)?;
-        let span = self.extend_span(block_start.span, statements_span);
+        let mut block_statements = Vec::with_capacity(8);
+        // We don't use eat_delimited here because a bad statement shouldn't take the rest of the
+        // block down with it; we record the error, skip to the end of the statement, and keep going
+        let close_brace = loop {
+            if let Some(close_brace) = self.eat_token(K::CloseBrace) {
+                break close_brace;
+            }
+            match Parser::expect("statement", self.peek(), self.parse_statement()) {
+                Ok(stmt) => {
+                    block_statements.push(stmt);
+                    if let Some(close_brace) = self.eat_token(K::CloseBrace) {
+                        break close_brace;
+                    }
+                    // Running into the next definition means this block is missing its `}`,
+                    // which is reported below, rather than the statement its `;`
+                    let next = self.peek();
+                    if next.kind != K::Eof
+                        && !self.is_definition_start(true)
+                        && self.eat_token(K::Semicolon).is_none()
+                    {
+                        self.module.errors.push(Parser::error(K::Semicolon, next));
+                        self.recover_to_statement_end();
+                    }
+                }
+                Err(e) => {
+                    self.module.errors.push(e);
+                    self.recover_to_statement_end();
+                }
+            }
+            // If we've run into the next definition, this block was never closed
+            let next = self.peek();
+            if next.kind == K::Eof || self.is_definition_start(true) {
+                return Err(Parser::error(K::CloseBrace, next));
+            }
+        };
+        let span = self.extend_token_span(block_start, close_brace);
         Ok(Some(Block { stmts: block_statements, span }))
     }
 
@@ -2478,6 +3037,7 @@ impl<'toks, 'module> Parser<'toks, 'module> {
 
     fn parse_function(&mut self) -> ParseResult<Option<ParsedFunctionId>> {
         trace!("parse_function");
+        let first = self.peek();
         let is_intrinsic = if self.peek().kind == K::KeywordIntern {
             self.tokens.advance();
             true
@@ -2534,6 +3094,7 @@ impl<'toks, 'module> Parser<'toks, 'module> {
                 .cloned()
                 .collect();
         }
+        let doc_comment = self.take_doc_comment(first);
         let function_id = self.module.add_function(ParsedFunction {
             name: func_name_id,
             type_args: type_arguments,
@@ -2543,6 +3104,7 @@ impl<'toks, 'module> Parser<'toks, 'module> {
             span,
             linkage,
             id: ParsedFunctionId(0),
+            doc_comment,
         });
         Ok(Some(function_id))
     }
@@ -2579,6 +3141,18 @@ impl<'toks, 'module> Parser<'toks, 'module> {
         };
         let name_token = self.expect_eat_token(K::Ident)?;
         let name_identifier = self.intern_ident_token(name_token);
+        let type_params = if self.peek().kind == K::OpenBracket {
+            self.tokens.advance();
+            let (type_params, _span) = self.eat_delimited(
+                "Ability type parameters",
+                K::Comma,
+                K::CloseBracket,
+                Parser::expect_type_param,
+            )?;
+            type_params
+        } else {
+            Vec::new()
+        };
         self.expect_eat_token(K::OpenBrace)?;
         let mut functions = Vec::new();
         while let Some(parsed_function) = self.parse_function()? {
@@ -2586,11 +3160,14 @@ impl<'toks, 'module> Parser<'toks, 'module> {
         }
         let close_token = self.expect_eat_token(K::CloseBrace)?;
         let span = self.extend_token_span(keyword_ability, close_token);
+        let doc_comment = self.take_doc_comment(keyword_ability);
         let ability_id = self.module.add_ability(ParsedAbility {
             name: name_identifier,
+            type_params,
             functions,
             span,
             id: ParsedAbilityId(0),
+            doc_comment,
         });
         Ok(Some(ability_id))
     }
@@ -2600,9 +3177,45 @@ impl<'toks, 'module> Parser<'toks, 'module> {
         let Some(keyword_impl) = keyword_impl else {
             return Ok(None);
         };
+        let mut type_params = if self.peek().kind == K::OpenBracket {
+            self.tokens.advance();
+            let (type_params, _span) = self.eat_delimited(
+                "Impl type parameters",
+                K::Comma,
+                K::CloseBracket,
+                Parser::expect_type_param,
+            )?;
+            type_params
+        } else {
+            Vec::new()
+        };
         let ability_name = self.expect_eat_token(K::Ident)?;
+        let ability_type_args = if self.peek().kind == K::OpenBracket {
+            self.tokens.advance();
+            let (type_args, _span) = self.eat_delimited(
+                "Ability type arguments",
+                K::Comma,
+                K::CloseBracket,
+                Parser::expect_type_expression,
+            )?;
+            type_args
+        } else {
+            Vec::new()
+        };
         self.expect_eat_token(K::KeywordFor)?;
         let target_type = self.expect_type_expression()?;
+        let mut type_constraints = Vec::new();
+        if let K::KeywordWhere = self.peek().kind {
+            self.tokens.advance();
+            self.parse_type_constraints(&mut type_constraints)?;
+        }
+        for type_param in type_params.iter_mut() {
+            type_param.constraints = type_constraints
+                .iter()
+                .filter(|tc| tc.param_name == type_param.ident)
+                .cloned()
+                .collect();
+        }
 
         // Functions or auto
         let next = self.peek();
@@ -2625,6 +3238,8 @@ impl<'toks, 'module> Parser<'toks, 'module> {
         let span = self.extend_token_span(keyword_impl, final_token);
         let ability_impl_id = self.module.add_ability_impl(ParsedAbilityImplementation {
             ability_name: ability_name_ident,
+            type_params,
+            ability_type_args,
             target_type,
             functions,
             id: ParsedAbilityImplId(0),
@@ -2675,9 +3290,28 @@ impl<'toks, 'module> Parser<'toks, 'module> {
 
         let equals = self.expect_eat_token(K::Equals)?;
         let type_expr = Parser::expect("Type expression", equals, self.parse_type_expression())?;
-        let span =
+        let mut span =
             self.extend_span(keyword_type.span, self.module.get_type_expression_span(type_expr));
+        let (deriving_kw, open_paren) = self.tokens.peek_two();
+        let deriving = if deriving_kw.kind == K::Ident
+            && open_paren.kind == K::OpenParen
+            && self.get_token_chars(deriving_kw) == "deriving"
+        {
+            self.tokens.advance();
+            self.tokens.advance();
+            let (abilities, deriving_span) = self.eat_delimited(
+                "Derived abilities",
+                K::Comma,
+                K::CloseParen,
+                Parser::expect_namespaced_ident,
+            )?;
+            span = self.extend_span(span, deriving_span);
+            abilities
+        } else {
+            Vec::new()
+        };
         let name = self.intern_ident_token(name);
+        let doc_comment = self.take_doc_comment(keyword_type);
         let type_defn_id = self.module.add_typedefn(ParsedTypeDefn {
             name,
             value_expr: type_expr,
@@ -2685,6 +3319,8 @@ impl<'toks, 'module> Parser<'toks, 'module> {
             type_params,
             id: ParsedTypeDefnId(0), // The id is set by add_typedefn
             flags,
+            doc_comment,
+            deriving,
         });
         Ok(Some(type_defn_id))
     }
@@ -2698,17 +3334,17 @@ impl<'toks, 'module> Parser<'toks, 'module> {
         let ident = self.expect_eat_token(K::Ident)?;
         self.expect_eat_token(K::OpenBrace)?;
         let mut definitions = Vec::new();
-        while let Some(def) = self.parse_definition()? {
-            definitions.push(def);
-        }
+        self.parse_definitions(&mut definitions, K::CloseBrace);
         let close = self.expect_eat_token(K::CloseBrace)?;
         let name = self.intern_ident_token(ident);
         let span = self.extend_token_span(keyword, close);
+        let doc_comment = self.take_doc_comment(keyword);
         let namespace_id = self.module.add_namespace(ParsedNamespace {
             name,
             definitions,
             id: ParsedNamespaceId(0),
             span,
+            doc_comment,
         });
         Ok(Some(namespace_id))
     }
@@ -2732,6 +3368,108 @@ impl<'toks, 'module> Parser<'toks, 'module> {
         }
     }
 
+    /// Parses definitions until `terminator` (which is not consumed), recording any errors
+    /// on the module and resynchronizing at the next definition so that we can report
+    /// every syntax error in a file rather than just the first
+    fn parse_definitions(&mut self, definitions: &mut Vec<ParsedId>, terminator: TokenKind) {
+        loop {
+            let start = self.peek();
+            if start.kind == terminator || start.kind == K::Eof {
+                break;
+            }
+            match self.parse_definition() {
+                Ok(Some(def)) => definitions.push(def),
+                Ok(None) => {
+                    let err = Parser::error("End of file or start of new definition", start);
+                    self.module.errors.push(err);
+                    self.recover_to_definition(terminator);
+                }
+                Err(err) => {
+                    self.module.errors.push(err);
+                    self.recover_to_definition(terminator);
+                }
+            }
+            // Always make progress, even if we failed on the very token we resynchronized to
+            if self.peek().span == start.span && self.peek().kind == start.kind {
+                self.tokens.advance();
+            }
+        }
+    }
+
+    /// `fn` also begins a function type, as in `f: fn(int) -> int`, so in the middle of a
+    /// statement it only starts a definition when a name follows it
+    fn is_definition_start(&self, at_statement_start: bool) -> bool {
+        let (tok, next) = self.peek_two();
+        match tok.kind {
+            K::KeywordFn => at_statement_start || next.kind == K::Ident,
+            kind => matches!(
+                kind,
+                K::KeywordIntern
+                    | K::KeywordExtern
+                    | K::KeywordType
+                    | K::KeywordNamespace
+                    | K::KeywordAbility
+                    | K::KeywordImpl
+            ),
+        }
+    }
+
+    /// Skips tokens until the start of the next definition, or past a `;` or `}` that ends
+    /// the broken one. Braces are tracked so that we don't stop inside a function body
+    fn recover_to_definition(&mut self, terminator: TokenKind) {
+        let mut depth: u32 = 0;
+        loop {
+            let tok = self.peek();
+            match tok.kind {
+                K::Eof => break,
+                _ if depth == 0 && self.is_definition_start(false) => break,
+                K::Semicolon if depth == 0 => {
+                    self.tokens.advance();
+                    break;
+                }
+                K::CloseBrace if depth == 0 => {
+                    // Leave the enclosing namespace's closing brace for it to eat
+                    if terminator != K::CloseBrace {
+                        self.tokens.advance();
+                    }
+                    break;
+                }
+                K::OpenBrace => depth += 1,
+                K::CloseBrace => depth -= 1,
+                _ => {}
+            }
+            self.tokens.advance();
+        }
+        trace!("recovered to definition at {}", self.peek().kind);
+    }
+
+    /// Skips tokens until the end of the current statement: past the next `;`, or up to the
+    /// `}` closing the current block, ignoring any nested in brackets opened after the error
+    fn recover_to_statement_end(&mut self) {
+        let mut depth: u32 = 0;
+        loop {
+            let tok = self.peek();
+            match tok.kind {
+                K::Eof => break,
+                _ if depth == 0 && self.is_definition_start(false) => break,
+                K::Semicolon if depth == 0 => {
+                    self.tokens.advance();
+                    break;
+                }
+                K::CloseBrace if depth == 0 => break,
+                K::OpenBrace | K::OpenParen | K::OpenBracket => depth += 1,
+                K::CloseBrace | K::CloseParen | K::CloseBracket => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            self.tokens.advance();
+        }
+        trace!("recovered to statement end at {}", self.peek().kind);
+    }
+
+    /// Parses every definition in the file into the root namespace. Errors are collected
+    /// in `module.errors` and do not stop parsing; the definitions we did manage to parse
+    /// are kept, so the typer can still run over the partial module.
+    /// Returns the first error encountered in this file, if any
     pub fn parse_module(&mut self) -> ParseResult<()> {
         let root_namespace_id = if self.module.namespaces.is_empty() {
             let name = self.module.identifiers.intern("_root");
@@ -2740,40 +3478,22 @@ impl<'toks, 'module> Parser<'toks, 'module> {
                 definitions: Vec::new(),
                 id: ParsedNamespaceId(0),
                 span: self.peek().span,
+                doc_comment: None,
             })
         } else {
             self.module.get_root_namespace().id
         };
 
+        let errors_before = self.module.errors.len();
         let mut new_definitions: Vec<ParsedId> = vec![];
-        loop {
-            match self.parse_definition() {
-                Ok(Some(def)) => new_definitions.push(def),
-                Err(err) => {
-                    self.module.errors.push(err);
-                    // For now, break on first parse error
-                    break;
-                }
-                Ok(None) => break,
-            }
-        }
-        while let Some(def) = self.parse_definition()? {
-            new_definitions.push(def)
-        }
-        if self.tokens.peek().kind != K::Eof {
-            if self.module.errors.is_empty() {
-                let err =
-                    Parser::error("End of file or start of new definition", self.tokens.peek());
-                self.module.errors.push(err.clone());
-                return Err(err);
-            } else {
-                return Err(self.module.errors.last().unwrap().clone());
-            }
-        }
+        self.parse_definitions(&mut new_definitions, K::Eof);
 
         self.module.get_namespace_mut(root_namespace_id).definitions.extend(new_definitions);
 
-        Ok(())
+        match self.module.errors.get(errors_before) {
+            None => Ok(()),
+            Some(first_error) => Err(first_error.clone()),
+        }
     }
 }
 
@@ -2821,6 +3541,8 @@ impl ParsedModule {
             ParsedExpression::OptionalGet(optional_get) => {
                 f.write_fmt(format_args!("{:?}", optional_get))
             }
+            ParsedExpression::Try(try_expr) => f.write_fmt(format_args!("{:?}", try_expr)),
+            ParsedExpression::OptionalChain(chain) => f.write_fmt(format_args!("{:?}", chain)),
             ParsedExpression::For(for_expr) => f.write_fmt(format_args!("{:?}", for_expr)),
             ParsedExpression::AnonEnumVariant(tag_expr) => {
                 f.write_char('.')?;
@@ -2842,9 +3564,13 @@ impl ParsedModule {
                 f.write_str("when ")?;
                 self.display_expr_id(match_expr.target_expression, f)?;
                 f.write_str(" {")?;
-                for ParsedMatchCase { pattern, expression } in match_expr.cases.iter() {
+                for ParsedMatchCase { pattern, guard, expression } in match_expr.cases.iter() {
                     f.write_str(" , ")?;
                     self.display_pattern_expression_id(*pattern, f)?;
+                    if let Some(guard) = guard {
+                        f.write_str(" if ")?;
+                        self.display_expr_id(*guard, f)?;
+                    }
                     f.write_str(" -> ")?;
                     self.display_expr_id(*expression, f)?;
                 }
@@ -2855,6 +3581,49 @@ impl ParsedModule {
                 f.write_str(" as ")?;
                 self.display_type_expression_id(cast.dest_type, f)
             }
+            ParsedExpression::InterpolatedString(interpolated) => {
+                f.write_char('"')?;
+                for part in interpolated.parts.iter() {
+                    match part {
+                        InterpolatedStringPart::String(s, _) => f.write_str(s)?,
+                        InterpolatedStringPart::Expr(expr) => {
+                            f.write_char('{')?;
+                            self.display_expr_id(*expr, f)?;
+                            f.write_char('}')?;
+                        }
+                    }
+                }
+                f.write_char('"')
+            }
+            ParsedExpression::Lambda(lambda) => {
+                f.write_str("\\(")?;
+                for (index, arg) in lambda.arguments.iter().enumerate() {
+                    if index > 0 {
+                        f.write_str(", ")?;
+                    }
+                    f.write_str(self.identifiers.get_name(arg.binding))?;
+                    if let Some(ty) = arg.ty {
+                        f.write_str(": ")?;
+                        self.display_type_expression_id(ty, f)?;
+                    }
+                }
+                f.write_str(")")?;
+                if let Some(return_type) = lambda.return_type {
+                    f.write_str(": ")?;
+                    self.display_type_expression_id(return_type, f)?;
+                }
+                f.write_str(" -> ")?;
+                self.display_expr_id(lambda.body, f)
+            }
+            ParsedExpression::Break(brk) => {
+                f.write_str("break")?;
+                if let Some(value) = brk.value {
+                    f.write_char(' ')?;
+                    self.display_expr_id(value, f)?;
+                }
+                Ok(())
+            }
+            ParsedExpression::Continue(_) => f.write_str("continue"),
         }
     }
 
@@ -2939,6 +3708,17 @@ impl ParsedModule {
                 f.write_str(self.identifiers.get_name(acc.member_name))
             }
             ParsedTypeExpression::Builtin(_builtin) => f.write_str("builtin"),
+            ParsedTypeExpression::Function(fun) => {
+                f.write_str("fn(")?;
+                for (index, param) in fun.params.iter().enumerate() {
+                    if index > 0 {
+                        f.write_str(", ")?;
+                    }
+                    self.display_type_expression_id(*param, f)?;
+                }
+                f.write_str(") -> ")?;
+                self.display_type_expression_id(fun.return_type, f)
+            }
         }
     }
 }
@@ -2957,19 +3737,35 @@ pub fn display_namespaced_identifier(
     Ok(())
 }
 
-pub fn lex_text(module: &mut ParsedModule, source: Source) -> ParseResult<Vec<Token>> {
+pub fn lex_text(module: &mut ParsedModule, source: Source) -> LexResult<Vec<Token>> {
     let file_id = source.file_id;
     module.sources.insert(source);
     let text = &module.sources.get_source(file_id).content;
     let mut lexer = Lexer::make(text, &mut module.spans, file_id);
-    let tokens = lexer.run().map_err(|lex_error| ParseError {
-        expected: lex_error.msg,
-        token: EOF_TOKEN,
-        cause: None,
-    })?;
-
-    let token_vec: Vec<Token> =
-        tokens.into_iter().filter(|token| token.kind != K::LineComment).collect();
+    let tokens = lexer.run()?;
+
+    let source = module.sources.get_source(file_id);
+    let mut token_vec: Vec<Token> = Vec::with_capacity(tokens.len());
+    let mut doc_lines: Vec<&str> = Vec::new();
+    for token in tokens.into_iter() {
+        match token.kind {
+            K::LineComment | K::BlockComment => {
+                doc_lines.clear();
+            }
+            K::DocComment => {
+                let line = Parser::chars_at_span(&module.spans, source, token.span);
+                let line = line.strip_prefix("///").unwrap_or(line).trim_end();
+                doc_lines.push(line.strip_prefix(' ').unwrap_or(line));
+            }
+            _ => {
+                if !doc_lines.is_empty() {
+                    module.doc_comments.insert(token.span, doc_lines.join("\n"));
+                    doc_lines.clear();
+                }
+                token_vec.push(token);
+            }
+        }
+    }
     Ok(token_vec)
 }
 
@@ -2979,7 +3775,7 @@ pub fn test_parse_module(source: Source) -> ParseResult<ParsedModule> {
     let mut module = ParsedModule::make(module_name);
 
     let file_id = source.file_id;
-    let token_vec = lex_text(&mut module, source)?;
+    let token_vec = lex_text(&mut module, source).unwrap();
     let mut parser = Parser::make(&token_vec, file_id, &mut module);
 
     let result = parser.parse_module();
@@ -241,6 +241,18 @@ fn while_loop_1() -> Result<(), ParseError> {
     panic!()
 }
 
+#[test]
+fn defer_stmt() -> Result<(), ParseError> {
+    let input = "defer { close(f); free(p) }";
+    let mut module = make_test_module();
+    let mut parser = set_up(input, &mut module);
+    let result = parser.parse_statement()?.unwrap();
+    let ParsedStmt::Defer(defer) = result else { panic!() };
+    let ParsedExpression::Block(block) = module.expressions.get(defer.expr) else { panic!() };
+    assert_eq!(block.stmts.len(), 2);
+    Ok(())
+}
+
 #[test]
 fn cmp_operators() -> Result<(), ParseError> {
     let input = "a < b <= c > d >= e";
@@ -359,3 +371,416 @@ fn when_pattern() -> ParseResult<()> {
     println!("{}", &module.expr_id_to_string(expr_id));
     Ok(())
 }
+
+#[test]
+fn when_guards_or_patterns_and_bindings() -> ParseResult<()> {
+    let input = r#"
+        when x {
+           .A(n) if n > 3 -> 1,
+           .A(_) | .B -> 2,
+           whole @ .C(inner) -> 3
+        };
+"#;
+    let (module, result) = test_single_expr(input)?;
+    let ParsedExpression::Match(match_expr) = result else { panic!() };
+    assert_eq!(match_expr.cases.len(), 3);
+
+    let guard = match_expr.cases[0].guard.expect("guard");
+    assert!(matches!(module.expressions.get(guard), ParsedExpression::BinaryOp(_)));
+
+    let ParsedPattern::Or(or_pattern) = module.patterns.get_pattern(match_expr.cases[1].pattern)
+    else {
+        panic!()
+    };
+    assert_eq!(or_pattern.alternatives.len(), 2);
+    assert!(match_expr.cases[1].guard.is_none());
+
+    let ParsedPattern::Binding(binding) = module.patterns.get_pattern(match_expr.cases[2].pattern)
+    else {
+        panic!()
+    };
+    assert_eq!(module.identifiers.get_name(binding.name), "whole");
+    assert!(matches!(module.patterns.get_pattern(binding.inner_pattern), ParsedPattern::Enum(_)));
+    Ok(())
+}
+
+#[test]
+fn when_slice_patterns() -> ParseResult<()> {
+    let input = r#"
+        when x {
+           [] -> 0,
+           [first, ..rest] -> 1,
+           [_, _, ..] -> 2,
+           "GET " ++ path -> 3
+        };
+"#;
+    let (module, result) = test_single_expr(input)?;
+    let ParsedExpression::Match(match_expr) = result else { panic!() };
+    let patterns: Vec<&ParsedPattern> =
+        match_expr.cases.iter().map(|c| module.patterns.get_pattern(c.pattern)).collect();
+
+    let ParsedPattern::Array(empty) = patterns[0] else { panic!() };
+    assert!(empty.elements.is_empty() && empty.rest.is_none());
+
+    let ParsedPattern::Array(first_rest) = patterns[1] else { panic!() };
+    assert_eq!(first_rest.elements.len(), 1);
+    let rest = first_rest.rest.expect("rest");
+    assert!(matches!(module.patterns.get_pattern(rest), ParsedPattern::Variable(_, _)));
+
+    let ParsedPattern::Array(anonymous_rest) = patterns[2] else { panic!() };
+    assert_eq!(anonymous_rest.elements.len(), 2);
+    let rest = anonymous_rest.rest.expect("rest");
+    assert!(matches!(module.patterns.get_pattern(rest), ParsedPattern::Wildcard(_)));
+
+    let ParsedPattern::StringPrefix(prefix) = patterns[3] else { panic!() };
+    assert!(matches!(module.patterns.get_pattern(prefix.rest), ParsedPattern::Variable(_, _)));
+    Ok(())
+}
+
+#[test]
+fn error_recovery() {
+    let src = r#"
+    fn one(): int { val x = ; 1 }
+    fn two(: int { 2 }
+    fn three(): int {
+      val y = 3 +;
+      y
+    }
+    namespace ns {
+      fn bad( {}
+      fn good(): int { 1 }
+    }
+    type Four = { a: int }
+    fn five(): int {
+      val z = 5;
+      z
+    fn six(): int { 6 }
+    "#;
+    let mut module = make_test_module();
+    let mut parser = set_up(src, &mut module);
+    assert!(parser.parse_module().is_err());
+    assert_eq!(module.errors.len(), 5);
+    // 'five' is missing its closing brace, and that's all that's reported for it
+    assert_eq!(module.errors[4].expected, "}");
+    // 'two', 'bad' and 'five' are lost, but we keep 'one' and 'three' minus their bad statements
+    assert_eq!(module.functions.len(), 4);
+    assert_eq!(module.functions[0].block.as_ref().unwrap().stmts.len(), 1);
+    assert_eq!(module.functions[1].block.as_ref().unwrap().stmts.len(), 1);
+    assert_eq!(module.get_root_namespace().definitions.len(), 5);
+    assert_eq!(module.type_defns.len(), 1);
+}
+
+#[test]
+fn error_recovery_function_types() {
+    let src = r#"
+    fn one(f: fn(int) -> int): int {
+      val broken: Array[, fn(int) -> int] = [];
+      f(1)
+    }
+    fn two(: int, g: fn(int) -> int): int { 2 }
+    fn three(h: fn(int) -> int): int { h(3) }
+    "#;
+    let mut module = make_test_module();
+    let mut parser = set_up(src, &mut module);
+    assert!(parser.parse_module().is_err());
+    // Recovery skips over each `fn(` type rather than stopping there as if it began a
+    // definition, so each mistake is reported once
+    assert_eq!(module.errors.len(), 2);
+    assert_eq!(module.functions.len(), 2);
+    assert_eq!(module.functions[0].block.as_ref().unwrap().stmts.len(), 1);
+    assert_eq!(module.functions[1].args.len(), 1);
+}
+
+#[test]
+fn doc_comments() -> ParseResult<()> {
+    let src = r#"
+    /// A namespace
+    namespace ns {
+      /// Adds one
+      ///
+      /// Really!
+      fn add_one(x: int): int { x + 1 }
+    }
+    /// Not attached: interrupted by a plain comment
+    // plain
+    type Point = { x: int /* block /* nested */ */, y: int }
+    /// Equality
+    ability Eq {
+      fn eq(self: Self, other: Self): bool
+    }
+    fn undocumented(): int {
+      /// Ignored inside a block
+      1
+    }
+    "#;
+    let mut module = make_test_module();
+    let mut parser = set_up(src, &mut module);
+    parser.parse_module()?;
+    let ns = module.namespaces.iter().find(|ns| ns.doc_comment.is_some()).unwrap();
+    assert_eq!(ns.doc_comment.as_deref(), Some("A namespace"));
+    assert_eq!(module.functions[0].doc_comment.as_deref(), Some("Adds one\n\nReally!"));
+    assert_eq!(module.type_defns[0].doc_comment, None);
+    assert_eq!(module.abilities[0].doc_comment.as_deref(), Some("Equality"));
+    assert_eq!(module.functions[2].doc_comment, None);
+    Ok(())
+}
+
+#[test]
+fn string_escapes() -> ParseResult<()> {
+    let (_module, result) = test_single_expr(r#""tab\tquote\"\x41\u{e9}""#)?;
+    let ParsedExpression::Literal(Literal::String(s, _)) = result else { panic!() };
+    assert_eq!(&s, "tab\tquote\"A\u{e9}");
+
+    let (_module, result) = test_single_expr(r#"r"C:\new\dir""#)?;
+    let ParsedExpression::Literal(Literal::String(s, _)) = result else { panic!() };
+    assert_eq!(&s, r"C:\new\dir");
+    Ok(())
+}
+
+#[test]
+fn bad_string_escapes() -> ParseResult<()> {
+    let mut module = make_test_module();
+    let mut parser = set_up(r#""a\qb\x80c""#, &mut module);
+    parser.expect_expression()?;
+    let spans: Vec<Span> = module.errors.iter().map(|e| module.spans.get(e.span())).collect();
+    assert_eq!(spans.len(), 2);
+    assert_eq!((spans[0].start, spans[0].len), (2, 2));
+    assert_eq!((spans[1].start, spans[1].len), (5, 4));
+    Ok(())
+}
+
+#[test]
+fn multiline_string() -> ParseResult<()> {
+    let input = "\"\"\"\n      SELECT *\n        FROM t\n\n      WHERE x = \\\"y\\\"\n      \"\"\"";
+    let (_module, result) = test_single_expr(input)?;
+    let ParsedExpression::Literal(Literal::String(s, _)) = result else { panic!() };
+    assert_eq!(&s, "SELECT *\n  FROM t\n\nWHERE x = \"y\"");
+
+    // A trailing backslash joins a line to the next, but an escaped one doesn't
+    let input = "\"\"\"\n  one \\\n    two\\\\\n  three\n  \"\"\"";
+    let (module, result) = test_single_expr(input)?;
+    let ParsedExpression::Literal(Literal::String(s, _)) = result else { panic!() };
+    assert_eq!(&s, "one two\\\nthree");
+    assert!(module.errors.is_empty());
+    Ok(())
+}
+
+#[test]
+fn interpolated_string() -> ParseResult<()> {
+    let (module, result) = test_single_expr(r#""x = {x}, \{y\} = {name}\u{e9}""#)?;
+    let ParsedExpression::InterpolatedString(interpolated) = result else { panic!() };
+    let parts: Vec<String> = interpolated
+        .parts
+        .iter()
+        .map(|part| match part {
+            InterpolatedStringPart::String(s, _) => s.clone(),
+            InterpolatedStringPart::Expr(expr) => module.expr_id_to_string(*expr),
+        })
+        .collect();
+    assert_eq!(parts, vec!["x = ", "var#x", ", {y} = ", "var#name", "\u{e9}"]);
+    Ok(())
+}
+
+#[test]
+fn lambda() -> ParseResult<()> {
+    let (module, result) = test_single_expr(r"\x -> x + 1")?;
+    let ParsedExpression::Lambda(lambda) = result else { panic!() };
+    assert_eq!(lambda.arguments.len(), 1);
+    assert!(lambda.arguments[0].ty.is_none());
+    assert!(lambda.return_type.is_none());
+    assert!(matches!(module.expressions.get(lambda.body), ParsedExpression::BinaryOp(_)));
+
+    let (_module, result) = test_single_expr(r"\(a: int, b): bool -> a == b")?;
+    let ParsedExpression::Lambda(lambda) = result else { panic!() };
+    assert_eq!(lambda.arguments.len(), 2);
+    assert!(lambda.arguments[0].ty.is_some());
+    assert!(lambda.arguments[1].ty.is_none());
+    assert!(lambda.return_type.is_some());
+    Ok(())
+}
+
+#[test]
+fn break_and_continue() -> ParseResult<()> {
+    let (_module, result) = test_single_expr("break;")?;
+    let ParsedExpression::Break(brk) = result else { panic!() };
+    assert!(brk.value.is_none());
+
+    let (module, result) = test_single_expr("break x + 1")?;
+    let ParsedExpression::Break(brk) = result else { panic!() };
+    let value = brk.value.expect("break value");
+    assert!(matches!(module.expressions.get(value), ParsedExpression::BinaryOp(_)));
+
+    let (_module, result) = test_single_expr("continue;")?;
+    assert!(matches!(result, ParsedExpression::Continue(_)));
+    Ok(())
+}
+
+#[test]
+fn function_type() -> ParseResult<()> {
+    let (module, result) = test_single_type_expr("fn(int, string) -> bool")?;
+    let ParsedTypeExpression::Function(fun) = result else { panic!() };
+    assert_eq!(fun.params.len(), 2);
+    assert!(matches!(
+        module.type_expressions.get(fun.return_type),
+        ParsedTypeExpression::TypeApplication(_)
+    ));
+    Ok(())
+}
+
+#[test]
+fn generic_ability() -> ParseResult<()> {
+    let src = r#"
+    ability Into[T] {
+      fn into(self: Self): T
+    }
+    impl Into[string] for int {
+      fn into(self: Self): string { "int" }
+    }
+    impl Equals for int auto
+    "#;
+    let mut module = make_test_module();
+    let mut parser = set_up(src, &mut module);
+    parser.parse_module()?;
+    let ability = &module.abilities[0];
+    assert_eq!(ability.type_params.len(), 1);
+    assert_eq!(ability.type_params[0].ident, module.identifiers.intern("T"));
+    assert_eq!(module.ability_impls[0].ability_type_args.len(), 1);
+    assert!(module.ability_impls[1].ability_type_args.is_empty());
+    Ok(())
+}
+
+#[test]
+fn blanket_impl() -> ParseResult<()> {
+    let src = r#"
+    impl[T, U] Equals for Pair[T, U] where T: Equals, U: Equals {
+      fn equals(self: Self, other: Self): bool { true }
+    }
+    "#;
+    let mut module = make_test_module();
+    let mut parser = set_up(src, &mut module);
+    parser.parse_module()?;
+    let ability_impl = &module.ability_impls[0];
+    assert_eq!(ability_impl.type_params.len(), 2);
+    assert_eq!(ability_impl.type_params[0].constraints.len(), 1);
+    assert_eq!(ability_impl.type_params[1].constraints.len(), 1);
+    assert_eq!(ability_impl.functions.len(), 1);
+    Ok(())
+}
+
+#[test]
+fn type_defn_deriving() -> ParseResult<()> {
+    let src = r#"
+    type Point = { x: int, y: int } deriving(Equals, Show)
+    type Shape = enum Circle(int), Square deriving(core::Hash)
+    type Plain = { x: int }
+    "#;
+    let mut module = make_test_module();
+    let mut parser = set_up(src, &mut module);
+    parser.parse_module()?;
+    assert_eq!(module.type_defns.len(), 3);
+    let point = &module.type_defns[0];
+    assert_eq!(point.deriving.len(), 2);
+    assert_eq!(point.deriving[0].name, module.identifiers.intern("Equals"));
+    assert_eq!(point.deriving[1].name, module.identifiers.intern("Show"));
+    let shape = &module.type_defns[1];
+    assert_eq!(shape.deriving.len(), 1);
+    assert_eq!(shape.deriving[0].namespaces.len(), 1);
+    assert!(module.type_defns[2].deriving.is_empty());
+    Ok(())
+}
+
+#[test]
+fn unary_negation() -> ParseResult<()> {
+    let (module, expr) = test_single_expr("-a * b")?;
+    let ParsedExpression::BinaryOp(mul) = expr else { panic!("expected binary op") };
+    assert_eq!(mul.op_kind, BinaryOpKind::Multiply);
+    let ParsedExpression::UnaryOp(neg) = module.expressions.get(mul.lhs) else {
+        panic!("expected unary op")
+    };
+    assert_eq!(neg.op_kind, ParsedUnaryOpKind::Negation);
+
+    let (_module, literal) = test_single_expr("-42")?;
+    assert!(matches!(literal, ParsedExpression::Literal(Literal::Numeric(_))));
+
+    let (_module, subtract) = test_single_expr("a - b")?;
+    assert!(
+        matches!(subtract, ParsedExpression::BinaryOp(op) if op.op_kind == BinaryOpKind::Subtract)
+    );
+    Ok(())
+}
+
+#[test]
+fn ranges() -> ParseResult<()> {
+    let (module, expr) = test_single_expr("0..n + 1")?;
+    let ParsedExpression::BinaryOp(range) = expr else { panic!("expected binary op") };
+    assert_eq!(range.op_kind, BinaryOpKind::Range);
+    assert!(matches!(module.expressions.get(range.rhs), ParsedExpression::BinaryOp(_)));
+
+    let (_module, inclusive) = test_single_expr("1..=9")?;
+    assert!(
+        matches!(inclusive, ParsedExpression::BinaryOp(op) if op.op_kind == BinaryOpKind::RangeInclusive)
+    );
+
+    let input = r#"
+        when n {
+           1..=9 -> 1,
+           -5..0 -> 2,
+           _ -> 0
+        };
+"#;
+    let (module, result) = test_single_expr(input)?;
+    let ParsedExpression::Match(match_expr) = result else { panic!() };
+    let patterns: Vec<&ParsedPattern> =
+        match_expr.cases.iter().map(|c| module.patterns.get_pattern(c.pattern)).collect();
+    let ParsedPattern::Range(digit) = patterns[0] else { panic!() };
+    assert!(digit.inclusive);
+    let ParsedPattern::Range(negative) = patterns[1] else { panic!() };
+    assert!(!negative.inclusive);
+    Ok(())
+}
+
+#[test]
+fn try_operator() -> ParseResult<()> {
+    let (module, expr) = test_single_expr("parse(s)? + 1")?;
+    let ParsedExpression::BinaryOp(add) = expr else { panic!("expected binary op") };
+    let ParsedExpression::Try(try_expr) = module.expressions.get(add.lhs) else {
+        panic!("expected try")
+    };
+    assert!(matches!(module.expressions.get(try_expr.base), ParsedExpression::FnCall(_)));
+
+    // `?.` is always optional chaining, so accessing a propagated value needs parentheses
+    let (module, expr) = test_single_expr("(parse(s)?).len")?;
+    let ParsedExpression::FieldAccess(access) = expr else { panic!("expected field access") };
+    assert!(matches!(module.expressions.get(access.base), ParsedExpression::Try(_)));
+    let (_module, expr) = test_single_expr("parse(s)?.len")?;
+    assert!(matches!(expr, ParsedExpression::OptionalChain(_)));
+
+    let (_module, optional_else) = test_single_expr("a ? b")?;
+    assert!(
+        matches!(optional_else, ParsedExpression::BinaryOp(op) if op.op_kind == BinaryOpKind::OptionalElse)
+    );
+    Ok(())
+}
+
+#[test]
+fn optional_chain() -> ParseResult<()> {
+    let (module, expr) = test_single_expr("user?.address?.city")?;
+    let ParsedExpression::OptionalChain(outer) = expr else { panic!("expected optional chain") };
+    let ParsedExpression::OptionalChain(inner) = module.expressions.get(outer.base) else {
+        panic!("expected optional chain")
+    };
+    assert!(matches!(module.expressions.get(inner.base), ParsedExpression::Variable(_)));
+    let ParsedExpression::FieldAccess(city) = module.expressions.get(outer.access) else {
+        panic!("expected field access")
+    };
+    assert_eq!(module.identifiers.get_name(city.target), "city");
+
+    let (module, method) = test_single_expr("user?.greet(1) ? \"nobody\"")?;
+    let ParsedExpression::BinaryOp(optional_else) = method else { panic!("expected binary op") };
+    assert_eq!(optional_else.op_kind, BinaryOpKind::OptionalElse);
+    let ParsedExpression::OptionalChain(chain) = module.expressions.get(optional_else.lhs) else {
+        panic!("expected optional chain")
+    };
+    assert!(matches!(module.expressions.get(chain.access), ParsedExpression::FnCall(_)));
+    Ok(())
+}
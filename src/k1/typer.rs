@@ -5,24 +5,26 @@ pub mod scopes;
 pub mod types;
 
 use std::cmp::Ordering;
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::error::Error;
 use std::fmt::{Display, Formatter, Write};
 
 use anyhow::bail;
 use colored::Colorize;
 use either::Either;
-use log::{debug, trace};
+use log::{debug, info, trace};
 
 use scopes::*;
 use types::*;
 
-use crate::lex::{SpanId, Spans, TokenKind};
+use crate::diagnostics::{Diagnostic, Label, Lint};
+use crate::lex::{SpanId, TokenKind};
 use crate::parse::{
     self, ForExpr, ForExprType, Identifiers, IfExpr, NamedTypeArg, NamespacedIdentifier,
-    NumericWidth, ParsedAbilityId, ParsedAbilityImplId, ParsedConstantId, ParsedExpressionId,
-    ParsedFunctionId, ParsedId, ParsedNamespaceId, ParsedPattern, ParsedPatternId,
-    ParsedTypeDefnId, ParsedTypeExpression, ParsedTypeExpressionId, ParsedUnaryOpKind, Sources,
+    NumericWidth, ParsedAbilityId, ParsedAbilityImplId, ParsedBreak, ParsedConstantId,
+    ParsedExpressionId, ParsedFunctionId, ParsedId, ParsedNamespaceId, ParsedOptionalChain,
+    ParsedPattern, ParsedPatternId, ParsedTry, ParsedTypeDefnId, ParsedTypeExpression,
+    ParsedTypeExpressionId, ParsedUnaryOpKind,
 };
 use crate::parse::{
     Block, FnCall, Identifier, Literal, ParsedExpression, ParsedModule, ParsedStmt,
@@ -56,7 +58,18 @@ pub struct TypedAbilityFunctionRef {
 
 pub const EQUALS_ABILITY_ID: AbilityId = AbilityId(0);
 pub const SHOW_ABILITY_ID: AbilityId = AbilityId(1);
-pub const BITWISE_ABILITY_ID: AbilityId = AbilityId(2);
+pub const INTO_ABILITY_ID: AbilityId = AbilityId(2);
+pub const ITERATOR_ABILITY_ID: AbilityId = AbilityId(3);
+pub const ITERABLE_ABILITY_ID: AbilityId = AbilityId(4);
+pub const HASH_ABILITY_ID: AbilityId = AbilityId(5);
+pub const ADD_ABILITY_ID: AbilityId = AbilityId(6);
+pub const SUB_ABILITY_ID: AbilityId = AbilityId(7);
+pub const MUL_ABILITY_ID: AbilityId = AbilityId(8);
+pub const DIV_ABILITY_ID: AbilityId = AbilityId(9);
+pub const REM_ABILITY_ID: AbilityId = AbilityId(10);
+pub const NEG_ABILITY_ID: AbilityId = AbilityId(11);
+pub const COMPARE_ABILITY_ID: AbilityId = AbilityId(12);
+pub const BITWISE_ABILITY_ID: AbilityId = AbilityId(14);
 
 enum CoerceResult {
     Fail(TypedExpr),
@@ -90,11 +103,21 @@ pub enum PatternConstructor {
         variant_name: Identifier,
         inner: Option<Box<PatternConstructor>>,
     },
+    /// An array of exactly `elements.len()` elements, or if `has_more`, of at least one more than that
+    Array {
+        elements: Vec<PatternConstructor>,
+        has_more: bool,
+    },
 }
 
+/// The most element combinations that exhaustiveness checking enumerates for an array pattern
+const MAX_ARRAY_PATTERN_CONSTRUCTORS: usize = 4096;
+
 #[derive(Debug, Clone)]
 pub struct TypedAbility {
     pub name: Identifier,
+    /// Bound to concrete types by each implementation, e.g. `T` in `ability Into[T]`
+    pub type_params: Vec<TypeParam>,
     pub functions: Vec<TypedAbilityFunctionRef>,
     pub scope_id: ScopeId,
     pub ast_id: ParsedAbilityId,
@@ -139,7 +162,49 @@ pub struct VariablePattern {
     pub span: SpanId,
 }
 
-// <pattern> ::= <literal> | <variable> | <enum> | <struc>
+#[derive(Debug, Clone)]
+pub struct TypedOrPattern {
+    pub alternatives: Vec<TypedPattern>,
+    pub span: SpanId,
+}
+
+#[derive(Debug, Clone)]
+pub struct TypedBindingPattern {
+    pub name: Identifier,
+    pub inner: Box<TypedPattern>,
+    pub span: SpanId,
+}
+
+#[derive(Debug, Clone)]
+pub struct TypedArrayPattern {
+    pub element_type_id: TypeId,
+    pub elements: Vec<TypedPattern>,
+    pub rest: Option<Box<TypedPattern>>,
+    pub span: SpanId,
+}
+
+/// `1..=9`; the bounds are the same integer type as the target
+#[derive(Debug, Clone)]
+pub struct TypedRangePattern {
+    pub start: TypedIntegerValue,
+    pub end: TypedIntegerValue,
+    pub inclusive: bool,
+    pub span: SpanId,
+}
+
+#[derive(Debug, Clone)]
+pub struct TypedStringPrefixPattern {
+    pub prefix: String,
+    pub rest: Box<TypedPattern>,
+    pub span: SpanId,
+}
+
+// <pattern> ::= <single> ( "|" <single> )*
+// <single> ::= <literal> | <variable> | <enum> | <struc> | <binding> | <array> | <prefix> | <range>
+// <binding> ::= <ident> "@" <single>
+// <array> ::= "[" ( <pattern> ","? )* ( ".." <ident>? )? "]"
+// <prefix> ::= "\"" <ident> "\"" "++" <single>
+// <range> ::= [0-9]+ ( ".." | "..=" ) [0-9]+
 // <literal> ::= "(" ")" | "\"" <ident> "\"" | [0-9]+ | "'" [a-z] "'" | "None"
 // <variable> ::= <ident>
 // <ident> ::= [a-z]*
@@ -156,17 +221,108 @@ pub enum TypedPattern {
     Enum(TypedEnumPattern),
     Struct(TypedStructPattern),
     Wildcard(SpanId),
+    Or(TypedOrPattern),
+    Binding(TypedBindingPattern),
+    Array(TypedArrayPattern),
+    StringPrefix(TypedStringPrefixPattern),
+    Range(TypedRangePattern),
 }
 
 impl TypedPattern {
+    /// Whether this pattern contains a literal that the exhaustiveness checker can't enumerate
     pub fn is_innumerable_literal(&self) -> bool {
         match self {
             TypedPattern::LiteralChar(_, _span) => true,
             TypedPattern::LiteralInteger(_, _span) => true,
             TypedPattern::LiteralString(_, _span) => true,
+            TypedPattern::StringPrefix(_) => true,
+            TypedPattern::Range(_) => true,
+            TypedPattern::Binding(binding_pattern) => {
+                binding_pattern.inner.is_innumerable_literal()
+            }
+            TypedPattern::Enum(enum_pattern) => {
+                enum_pattern.payload.as_ref().is_some_and(|p| p.is_innumerable_literal())
+            }
+            TypedPattern::Struct(struct_pattern) => {
+                struct_pattern.fields.iter().any(|f| f.pattern.is_innumerable_literal())
+            }
+            TypedPattern::Array(array_pattern) => {
+                array_pattern.elements.iter().any(|e| e.is_innumerable_literal())
+            }
+            TypedPattern::Or(or_pattern) => {
+                or_pattern.alternatives.iter().any(|a| a.is_innumerable_literal())
+            }
             _ => false,
         }
     }
+
+    /// The longest fixed run of elements in any array pattern within this pattern
+    pub fn max_array_pattern_len(&self) -> usize {
+        match self {
+            TypedPattern::Array(array_pattern) => array_pattern
+                .elements
+                .iter()
+                .map(|e| e.max_array_pattern_len())
+                .max()
+                .unwrap_or(0)
+                .max(array_pattern.elements.len()),
+            TypedPattern::Enum(enum_pattern) => {
+                enum_pattern.payload.as_ref().map_or(0, |p| p.max_array_pattern_len())
+            }
+            TypedPattern::Struct(struct_pattern) => struct_pattern
+                .fields
+                .iter()
+                .map(|f| f.pattern.max_array_pattern_len())
+                .max()
+                .unwrap_or(0),
+            TypedPattern::Or(or_pattern) => {
+                or_pattern.alternatives.iter().map(|a| a.max_array_pattern_len()).max().unwrap_or(0)
+            }
+            TypedPattern::Binding(binding_pattern) => binding_pattern.inner.max_array_pattern_len(),
+            _ => 0,
+        }
+    }
+
+    /// The user-visible variables this pattern binds, in order
+    pub fn collect_bound_names(&self, names: &mut Vec<Identifier>) {
+        match self {
+            TypedPattern::Variable(variable_pattern) => names.push(variable_pattern.name),
+            TypedPattern::Binding(binding_pattern) => {
+                names.push(binding_pattern.name);
+                binding_pattern.inner.collect_bound_names(names)
+            }
+            TypedPattern::Enum(enum_pattern) => {
+                if let Some(payload) = enum_pattern.payload.as_ref() {
+                    payload.collect_bound_names(names)
+                }
+            }
+            TypedPattern::Struct(struct_pattern) => {
+                for field in struct_pattern.fields.iter() {
+                    field.pattern.collect_bound_names(names)
+                }
+            }
+            // Every alternative binds the same names
+            TypedPattern::Or(or_pattern) => or_pattern.alternatives[0].collect_bound_names(names),
+            TypedPattern::Array(array_pattern) => {
+                for element in array_pattern.elements.iter() {
+                    element.collect_bound_names(names)
+                }
+                if let Some(rest) = array_pattern.rest.as_ref() {
+                    rest.collect_bound_names(names)
+                }
+            }
+            TypedPattern::StringPrefix(prefix_pattern) => {
+                prefix_pattern.rest.collect_bound_names(names)
+            }
+            TypedPattern::LiteralUnit(_)
+            | TypedPattern::LiteralChar(_, _)
+            | TypedPattern::LiteralInteger(_, _)
+            | TypedPattern::LiteralBool(_, _)
+            | TypedPattern::LiteralString(_, _)
+            | TypedPattern::Range(_)
+            | TypedPattern::Wildcard(_) => {}
+        }
+    }
     pub fn span_id(&self) -> SpanId {
         match self {
             TypedPattern::LiteralUnit(span) => *span,
@@ -178,6 +334,11 @@ impl TypedPattern {
             TypedPattern::Enum(enum_pattern) => enum_pattern.span,
             TypedPattern::Struct(struct_pattern) => struct_pattern.span,
             TypedPattern::Wildcard(span) => *span,
+            TypedPattern::Or(or_pattern) => or_pattern.span,
+            TypedPattern::Binding(binding_pattern) => binding_pattern.span,
+            TypedPattern::Array(array_pattern) => array_pattern.span,
+            TypedPattern::StringPrefix(prefix_pattern) => prefix_pattern.span,
+            TypedPattern::Range(range_pattern) => range_pattern.span,
         }
     }
 }
@@ -243,17 +404,54 @@ pub enum TypedFunctionKind {
     Standard,
     AbilityDefn(AbilityId),
     AbilityImpl(AbilityId, TypeId),
+    /// `return_type_pending` is set while the body of a lambda without a return type annotation
+    /// is checked, since its return type is only known from the body once that's done
+    Lambda {
+        return_type_pending: bool,
+    },
 }
 impl TypedFunctionKind {
     pub fn ability_id(&self) -> Option<AbilityId> {
         match self {
             TypedFunctionKind::Standard => None,
+            TypedFunctionKind::Lambda { .. } => None,
             TypedFunctionKind::AbilityDefn(ability_id) => Some(*ability_id),
             TypedFunctionKind::AbilityImpl(ability_id, _) => Some(*ability_id),
         }
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureMode {
+    /// Immutable bindings are copied into the environment when the closure is created
+    ByValue,
+    /// Mutable bindings are shared with the enclosing function, through a pointer
+    ByReference,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Capture {
+    pub variable_id: VariableId,
+    pub mode: CaptureMode,
+}
+
+/// The variables a lambda closes over, and the struct type they are packed into; field `i`
+/// of `env_type` holds `captures[i]`
+#[derive(Debug, Clone)]
+pub struct ClosureEnvironment {
+    pub captures: Vec<Capture>,
+    pub env_type: TypeId,
+}
+
+impl ClosureEnvironment {
+    pub fn find_reference_capture(&self) -> Option<VariableId> {
+        self.captures
+            .iter()
+            .find(|capture| capture.mode == CaptureMode::ByReference)
+            .map(|capture| capture.variable_id)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct TypedFunction {
     pub name: Identifier,
@@ -265,10 +463,13 @@ pub struct TypedFunction {
     pub intrinsic_type: Option<IntrinsicFunction>,
     pub linkage: Linkage,
     pub specializations: Vec<SpecializationStruct>,
-    pub parsed_function_id: ParsedFunctionId,
+    /// A function declaration, or the expression for a lambda
+    pub parsed_id: ParsedId,
     #[allow(unused)]
     is_method_of: Option<TypeId>,
     pub kind: TypedFunctionKind,
+    /// Only lambdas that capture variables have an environment
+    pub closure_env: Option<ClosureEnvironment>,
     pub span: SpanId,
 }
 
@@ -315,6 +516,8 @@ pub enum BinaryOpKind {
     // Other
     OptionalElse,
     Pipe,
+    Range,
+    RangeInclusive,
 }
 
 impl Display for BinaryOpKind {
@@ -335,6 +538,8 @@ impl Display for BinaryOpKind {
             BinaryOpKind::NotEquals => f.write_str("!="),
             BinaryOpKind::OptionalElse => f.write_str("?"),
             BinaryOpKind::Pipe => f.write_str("|"),
+            BinaryOpKind::Range => f.write_str(".."),
+            BinaryOpKind::RangeInclusive => f.write_str("..="),
         }
     }
 }
@@ -347,6 +552,7 @@ impl BinaryOpKind {
             B::Rem => 101,
             B::Multiply | B::Divide => 100,
             B::Add | B::Subtract => 90,
+            B::Range | B::RangeInclusive => 85,
             B::Less | B::LessEqual | B::Greater | B::GreaterEqual | B::Equals | B::NotEquals => 80,
             B::And => 70,
             B::Or => 66,
@@ -371,6 +577,8 @@ impl BinaryOpKind {
             TokenKind::QuestionMark => Some(BinaryOpKind::OptionalElse),
             TokenKind::Percent => Some(BinaryOpKind::Rem),
             TokenKind::Pipe => Some(BinaryOpKind::Pipe),
+            TokenKind::DotDot => Some(BinaryOpKind::Range),
+            TokenKind::DotDotEquals => Some(BinaryOpKind::RangeInclusive),
             _ => None,
         }
     }
@@ -392,6 +600,8 @@ impl BinaryOpKind {
             BinaryOpKind::NotEquals => true,
             BinaryOpKind::OptionalElse => false,
             BinaryOpKind::Pipe => false,
+            BinaryOpKind::Range => false,
+            BinaryOpKind::RangeInclusive => false,
         }
     }
 }
@@ -408,6 +618,7 @@ pub struct BinaryOp {
 #[derive(Debug, Eq, PartialEq, Clone, Copy)]
 pub enum UnaryOpKind {
     BooleanNegation,
+    FloatNegation,
     Reference,
     Dereference,
     ReferenceToInt,
@@ -417,6 +628,7 @@ impl Display for UnaryOpKind {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             UnaryOpKind::BooleanNegation => f.write_str("not "),
+            UnaryOpKind::FloatNegation => f.write_char('-'),
             UnaryOpKind::Reference => f.write_char('&'),
             UnaryOpKind::Dereference => f.write_char('*'),
             UnaryOpKind::ReferenceToInt => f.write_str("(*int)"),
@@ -432,9 +644,16 @@ pub struct UnaryOp {
     pub span: SpanId,
 }
 
+#[derive(Debug, Clone, Copy)]
+pub enum Callee {
+    StaticFunction(FunctionId),
+    /// A call through a variable of function type, whose value is only known at runtime
+    DynamicFunction(VariableId),
+}
+
 #[derive(Debug, Clone)]
 pub struct Call {
-    pub callee_function_id: FunctionId,
+    pub callee: Callee,
     pub args: Vec<TypedExpr>,
     pub type_args: Vec<TypeParam>,
     pub ret_type: TypeId,
@@ -537,6 +756,8 @@ pub struct TypedEnumIsVariantExpr {
 #[derive(Debug, Clone)]
 struct TypedMatchCase {
     pattern: TypedPattern,
+    /// Guarded arms don't count towards exhaustiveness, since the guard might be false
+    has_guard: bool,
     pre_stmts: Vec<TypedStmt>,
     condition: TypedExpr,
     arm_block: TypedBlock,
@@ -555,6 +776,19 @@ pub enum TypedIntegerValue {
 }
 
 impl TypedIntegerValue {
+    pub fn zero(integer_type: IntegerType) -> TypedIntegerValue {
+        match integer_type {
+            IntegerType::U8 => TypedIntegerValue::U8(0),
+            IntegerType::U16 => TypedIntegerValue::U16(0),
+            IntegerType::U32 => TypedIntegerValue::U32(0),
+            IntegerType::U64 => TypedIntegerValue::U64(0),
+            IntegerType::I8 => TypedIntegerValue::I8(0),
+            IntegerType::I16 => TypedIntegerValue::I16(0),
+            IntegerType::I32 => TypedIntegerValue::I32(0),
+            IntegerType::I64 => TypedIntegerValue::I64(0),
+        }
+    }
+
     pub fn as_u64(&self) -> u64 {
         match self {
             TypedIntegerValue::U8(v) => *v as u64,
@@ -659,6 +893,12 @@ pub enum CastType {
     ReferenceToPointer,
     PointerToInt,
     IntToPointer,
+    FloatExtend,
+    FloatTruncate,
+    /// Signed or unsigned, depending on the integer type
+    IntegerToFloat,
+    /// Signed or unsigned, depending on the integer type. Rounds toward zero
+    FloatToInteger,
 }
 
 impl CastType {
@@ -673,6 +913,10 @@ impl CastType {
             CastType::ReferenceToPointer => true,
             CastType::PointerToInt => true,
             CastType::IntToPointer => true,
+            CastType::FloatExtend => false,
+            CastType::FloatTruncate => false,
+            CastType::IntegerToFloat => false,
+            CastType::FloatToInteger => false,
         }
     }
 }
@@ -689,6 +933,10 @@ impl Display for CastType {
             CastType::ReferenceToPointer => write!(f, "reftoptr"),
             CastType::PointerToInt => write!(f, "ptrtoint"),
             CastType::IntToPointer => write!(f, "inttoptr"),
+            CastType::FloatExtend => write!(f, "fext"),
+            CastType::FloatTruncate => write!(f, "ftrunc"),
+            CastType::IntegerToFloat => write!(f, "itof"),
+            CastType::FloatToInteger => write!(f, "ftoi"),
         }
     }
 }
@@ -707,12 +955,36 @@ pub struct TypedReturn {
     pub span: SpanId,
 }
 
+/// A named function or lambda used as a value. It evaluates to a pointer to the function,
+/// paired with a pointer to the closure environment, if it captures anything
+#[derive(Debug, Clone)]
+pub struct FunctionReference {
+    pub function_id: FunctionId,
+    pub type_id: TypeId,
+    pub span: SpanId,
+}
+
+/// Exits the loop whose body is `loop_scope_id`. A value is only allowed in `for ... yield`,
+/// and is lowered into an ordinary push before codegen
+#[derive(Debug, Clone)]
+pub struct TypedBreak {
+    pub value: Option<Box<TypedExpr>>,
+    pub loop_scope_id: ScopeId,
+    pub span: SpanId,
+}
+
+#[derive(Debug, Clone)]
+pub struct TypedContinue {
+    pub loop_scope_id: ScopeId,
+    pub span: SpanId,
+}
+
 #[derive(Debug, Clone)]
 pub enum ControlFlowType {
     Returns(TypeId),
     Value(TypeId),
-    //Breaks,
-    //Continues,
+    Breaks,
+    Continues,
 }
 
 #[derive(Debug, Clone)]
@@ -736,6 +1008,12 @@ pub enum TypedExpr {
     EnumGetPayload(GetEnumPayload),
     Cast(TypedCast),
     Return(TypedReturn),
+    FunctionReference(FunctionReference),
+    Break(TypedBreak),
+    Continue(TypedContinue),
+    /// Stands in for a subexpression that failed to typecheck, once its error has been reported,
+    /// so that the rest of the enclosing expression can still be checked
+    Error(SpanId),
 }
 
 impl From<VariableExpr> for TypedExpr {
@@ -767,6 +1045,10 @@ impl TypedExpr {
             TypedExpr::EnumGetPayload(as_variant) => as_variant.payload_type_id,
             TypedExpr::Cast(c) => c.target_type_id,
             TypedExpr::Return(_ret) => NEVER_TYPE_ID,
+            TypedExpr::Break(_) => NEVER_TYPE_ID,
+            TypedExpr::Continue(_) => NEVER_TYPE_ID,
+            TypedExpr::FunctionReference(fun_ref) => fun_ref.type_id,
+            TypedExpr::Error(_) => ERROR_TYPE_ID,
         }
     }
     #[inline]
@@ -791,12 +1073,18 @@ impl TypedExpr {
             TypedExpr::EnumGetPayload(as_variant) => as_variant.span,
             TypedExpr::Cast(c) => c.span,
             TypedExpr::Return(ret) => ret.span,
+            TypedExpr::FunctionReference(fun_ref) => fun_ref.span,
+            TypedExpr::Break(brk) => brk.span,
+            TypedExpr::Continue(cont) => cont.span,
+            TypedExpr::Error(span) => *span,
         }
     }
 
     pub fn control_flow_type(&self) -> ControlFlowType {
         match self {
             TypedExpr::Return(ret) => ControlFlowType::Returns(ret.value.get_type()),
+            TypedExpr::Break(_) => ControlFlowType::Breaks,
+            TypedExpr::Continue(_) => ControlFlowType::Continues,
             TypedExpr::Block(block) => block.control_flow_type(),
             _ => ControlFlowType::Value(self.get_type()),
         }
@@ -830,6 +1118,12 @@ pub struct Assignment {
     pub span: SpanId,
 }
 
+struct YieldBreakLowering {
+    loop_scope_id: ScopeId,
+    element_type: TypeId,
+    yielded_coll: TypedExpr,
+}
+
 #[derive(Debug, Clone)]
 pub struct TypedWhileLoop {
     pub cond: TypedExpr,
@@ -837,12 +1131,20 @@ pub struct TypedWhileLoop {
     pub span: SpanId,
 }
 
+/// An expression that runs when the enclosing block exits, by any path
+#[derive(Debug, Clone)]
+pub struct TypedDefer {
+    pub expr: TypedExpr,
+    pub span: SpanId,
+}
+
 #[derive(Debug, Clone)]
 pub enum TypedStmt {
     Expr(Box<TypedExpr>),
     ValDef(Box<ValDef>),
     Assignment(Box<Assignment>),
     WhileLoop(Box<TypedWhileLoop>),
+    Defer(Box<TypedDefer>),
 }
 
 impl From<TypedExpr> for TypedStmt {
@@ -859,6 +1161,7 @@ impl TypedStmt {
             TypedStmt::ValDef(v) => v.span,
             TypedStmt::Assignment(ass) => ass.span,
             TypedStmt::WhileLoop(w) => w.span,
+            TypedStmt::Defer(defer) => defer.span,
         }
     }
 
@@ -868,6 +1171,7 @@ impl TypedStmt {
             TypedStmt::ValDef(_) => UNIT_TYPE_ID,
             TypedStmt::Assignment(_) => UNIT_TYPE_ID,
             TypedStmt::WhileLoop(_) => UNIT_TYPE_ID,
+            TypedStmt::Defer(_) => UNIT_TYPE_ID,
         }
     }
 
@@ -884,6 +1188,7 @@ impl TypedStmt {
             TypedStmt::ValDef(_) => ControlFlowType::Value(UNIT_TYPE_ID),
             TypedStmt::Assignment(_) => ControlFlowType::Value(UNIT_TYPE_ID),
             TypedStmt::WhileLoop(_) => ControlFlowType::Value(UNIT_TYPE_ID),
+            TypedStmt::Defer(_) => ControlFlowType::Value(UNIT_TYPE_ID),
         }
     }
 }
@@ -892,11 +1197,31 @@ impl TypedStmt {
 pub struct TyperError {
     message: String,
     span: SpanId,
+    /// A cascading error is the consequence of an error that was already reported, such as
+    /// the use of a variable whose initializer failed to typecheck. These are never reported
+    is_cascade: bool,
+    /// Related locations, such as a previous definition
+    labels: Vec<Label>,
 }
 
 impl TyperError {
     fn make(message: impl AsRef<str>, span: SpanId) -> TyperError {
-        TyperError { message: message.as_ref().to_owned(), span }
+        TyperError { message: message.as_ref().to_owned(), span, is_cascade: false, labels: vec![] }
+    }
+
+    fn cascade(span: SpanId) -> TyperError {
+        TyperError { message: "cascading error".to_owned(), span, is_cascade: true, labels: vec![] }
+    }
+
+    fn with_label(mut self, span: SpanId, message: impl Into<String>) -> TyperError {
+        self.labels.push(Label { span, message: message.into() });
+        self
+    }
+
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let mut diagnostic = Diagnostic::error(&self.message, self.span);
+        diagnostic.secondary.extend(self.labels.iter().cloned());
+        diagnostic
     }
 }
 
@@ -910,9 +1235,19 @@ impl Error for TyperError {}
 
 pub type TyperResult<A> = Result<A, TyperError>;
 
+/// How a for loop steps through its iteree
+enum ForLoopCursor {
+    /// Arrays and strings are indexed up to their length
+    Index { length: SynthedVariable },
+    /// A range counts from its start up to its end, and stops before stepping past it
+    Range { next_value: SynthedVariable, has_next: SynthedVariable },
+    /// Anything else pulls its next item from its Iterator in the loop condition, and runs
+    /// until that's None
+    Iterator { next_function_id: FunctionId, next_item: SynthedVariable },
+}
+
 #[derive(Debug, Clone)]
 struct SynthedVariable {
-    #[allow(unused)]
     pub variable_id: VariableId,
     pub defn_stmt: TypedStmt,
     pub variable_expr: TypedExpr,
@@ -1121,15 +1456,16 @@ fn make_fail_ast_id<A, T: AsRef<str>>(
     Err(make_error(message, span))
 }
 
-fn print_error(spans: &Spans, sources: &Sources, message: impl AsRef<str>, span: SpanId) {
-    parse::print_error_location(spans, sources, span);
-    eprintln!("\t{}", message.as_ref());
-}
-
 #[derive(Debug, Clone)]
 pub struct TypedAbilityImpl {
     pub type_id: TypeId,
     pub ability_id: AbilityId,
+    /// Values for the ability's type parameters; implementations are keyed on
+    /// (ability, type args, Self)
+    pub type_args: Vec<TypeId>,
+    /// Non-empty for a blanket implementation, e.g. `T` in `impl[T] Equals for Array[T]`.
+    /// Its functions are generic in these, and are specialized at each use site
+    pub type_params: Vec<FunctionTypeParam>,
     /// In order they are defined in the ability
     pub functions: Vec<FunctionId>,
     pub span: SpanId,
@@ -1204,12 +1540,37 @@ pub struct TypedModule {
     pub constants: Vec<Constant>,
     pub scopes: Scopes,
     pub errors: Vec<TyperError>,
+    pub warnings: Vec<Diagnostic>,
     pub namespaces: Namespaces,
     pub abilities: Vec<TypedAbility>,
     pub ability_impls: Vec<TypedAbilityImpl>,
     pub namespace_ast_mappings: HashMap<ParsedNamespaceId, NamespaceId>,
     pub function_ast_mappings: HashMap<ParsedFunctionId, FunctionId>,
     pub ability_impl_ast_mappings: HashMap<ParsedAbilityImplId, AbilityImplId>,
+    /// Tracks how user-defined locals are used, for the unused-variable and unused-mut lints
+    local_usages: HashMap<VariableId, LocalUsage>,
+    /// The loop scopes of `for ... yield` expressions, and whether a `break` in them has a value
+    yield_loops: HashMap<ScopeId, bool>,
+    /// Variables captured so far by each lambda whose body is being checked, keyed by the
+    /// lambda's scope
+    lambda_captures: HashMap<ScopeId, Vec<VariableId>>,
+    /// Variables bound to a closure that captures by reference, and one variable it captures
+    reference_closure_variables: HashMap<VariableId, VariableId>,
+    /// Every function that is the target of a call, for the unused-function lint
+    called_functions: HashSet<FunctionId>,
+    /// Derived implementations that have been declared, whose bodies get synthesized once
+    /// every other declaration is known
+    pending_derived_impls: Vec<AbilityImplId>,
+    /// Arguments evaluated while choosing between ability implementations, which the call
+    /// takes back when it checks its arguments rather than evaluating them a second time
+    speculative_call_args: HashMap<ParsedExpressionId, TypedExpr>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct LocalUsage {
+    span: SpanId,
+    read: bool,
+    reassigned: bool,
 }
 
 impl TypedModule {
@@ -1224,6 +1585,7 @@ impl TypedModule {
                 Type::Integer(IntegerType::I16),
                 Type::Integer(IntegerType::I32),
                 Type::Integer(IntegerType::I64),
+                Type::Error,
             ],
             type_defn_mapping: HashMap::new(),
             placeholder_mapping: HashMap::new(),
@@ -1236,6 +1598,7 @@ impl TypedModule {
         debug_assert!(matches!(*types.get(I16_TYPE_ID), Type::Integer(IntegerType::I16)));
         debug_assert!(matches!(*types.get(I32_TYPE_ID), Type::Integer(IntegerType::I32)));
         debug_assert!(matches!(*types.get(I64_TYPE_ID), Type::Integer(IntegerType::I64)));
+        debug_assert!(matches!(*types.get(ERROR_TYPE_ID), Type::Error));
 
         let scopes = Scopes::make();
         let namespaces = Namespaces { namespaces: Vec::new() };
@@ -1247,12 +1610,20 @@ impl TypedModule {
             constants: Vec::new(),
             scopes,
             errors: Vec::new(),
+            warnings: Vec::new(),
             namespaces,
             abilities: Vec::new(),
             ability_impls: Vec::new(),
             namespace_ast_mappings: HashMap::new(),
             function_ast_mappings: HashMap::new(),
             ability_impl_ast_mappings: HashMap::new(),
+            local_usages: HashMap::new(),
+            yield_loops: HashMap::new(),
+            lambda_captures: HashMap::new(),
+            reference_closure_variables: HashMap::new(),
+            called_functions: HashSet::new(),
+            pending_derived_impls: Vec::new(),
+            speculative_call_args: HashMap::new(),
         }
     }
 
@@ -1601,6 +1972,24 @@ impl TypedModule {
                 let type_id = self.types.add_type(reference_type);
                 Ok(type_id)
             }
+            ParsedTypeExpression::Function(fun) => {
+                let fun = fun.clone();
+                let mut params = Vec::with_capacity(fun.params.len());
+                for (index, param) in fun.params.iter().enumerate() {
+                    let type_id =
+                        self.eval_type_expr_defn(*param, scope_id, context.no_attach_defn_info())?;
+                    let name = self.ast.identifiers.intern(format!("arg{index}"));
+                    params.push(FnArgType { type_id, name });
+                }
+                let return_type = self.eval_type_expr_defn(
+                    fun.return_type,
+                    scope_id,
+                    context.no_attach_defn_info(),
+                )?;
+                let function_type =
+                    Type::Function(FunctionType { params, return_type, defn_info: None });
+                Ok(self.types.add_type(function_type))
+            }
             ParsedTypeExpression::Enum(e) => {
                 let e = e.clone();
                 let mut variants = Vec::with_capacity(e.variants.len());
@@ -1805,7 +2194,7 @@ impl TypedModule {
                     ast_node: ParsedId::TypeExpression(ty_app_id),
                 });
                 let type_id = self.types.add_type(new_struct);
-                eprintln!("Combined struct: {}", self.type_id_to_string(type_id));
+                debug!("Combined struct: {}", self.type_id_to_string(type_id));
 
                 Ok(Some(type_id))
             }
@@ -1909,7 +2298,7 @@ impl TypedModule {
                     let placeholder_type_id =
                         match self.types.placeholder_mapping.get(&type_defn_id) {
                             None => {
-                                eprintln!(
+                                debug!(
                                     "Inserting recursive reference for {}",
                                     self.get_ident_str(name)
                                 );
@@ -1933,7 +2322,7 @@ impl TypedModule {
                             self.get_ident_str(name),
                         ),
                         Some((pending_defn_id, pending_defn_scope_id)) => {
-                            eprintln!(
+                            debug!(
                                 "Recursing into pending type defn {}",
                                 self.get_ident_str(self.ast.get_type_defn(pending_defn_id).name)
                             );
@@ -2154,12 +2543,50 @@ impl TypedModule {
                     type_id
                 }
             }
+            Type::Function(fun) => {
+                let mut new_fun = fun.clone();
+                let mut any_changed = false;
+                for param in new_fun.params.iter_mut() {
+                    let new_param_type = self.instantiate_generic_type_subst(
+                        param.type_id,
+                        None,
+                        None,
+                        passed_params,
+                        generic_params,
+                        parsed_id,
+                    );
+                    if new_param_type != param.type_id {
+                        any_changed = true;
+                        param.type_id = new_param_type;
+                    }
+                }
+                let new_return_type = self.instantiate_generic_type_subst(
+                    new_fun.return_type,
+                    None,
+                    None,
+                    passed_params,
+                    generic_params,
+                    parsed_id,
+                );
+                if new_return_type != new_fun.return_type {
+                    any_changed = true;
+                    new_fun.return_type = new_return_type;
+                }
+                if any_changed {
+                    new_fun.defn_info = None;
+                    self.types.add_type(Type::Function(new_fun))
+                } else {
+                    type_id
+                }
+            }
             Type::Unit(_)
             | Type::Char(_)
             | Type::Integer(_)
             | Type::Float(_)
             | Type::Bool(_)
-            | Type::Pointer(_) => type_id,
+            | Type::Pointer(_)
+            | Type::Never(_)
+            | Type::Error => type_id,
             Type::EnumVariant(_) => {
                 unreachable!(
                     "instantiate_generic_type is not expected to be called on an EnumVariant"
@@ -2168,12 +2595,6 @@ impl TypedModule {
             Type::Generic(_) => {
                 unreachable!("instantiate_generic_type is not expected to be called on a Generic")
             }
-            Type::Function(_) => {
-                unreachable!("instantiate_generic_type is not expected to be called on a Function")
-            }
-            Type::Never(_) => {
-                unreachable!("instantiate_generic_type is not expected to be called on never")
-            }
             Type::RecursiveReference(_) => unreachable!(
                 "instantiate_generic_type is not expected to be called on RecursiveReference"
             ),
@@ -2358,6 +2779,108 @@ impl TypedModule {
                 };
                 Ok(TypedPattern::Struct(struct_pattern))
             }
+            ParsedPattern::Or(or_pattern) => {
+                let mut alternatives = Vec::with_capacity(or_pattern.alternatives.len());
+                for alternative in or_pattern.alternatives.iter() {
+                    alternatives.push(self.eval_pattern(*alternative, target_type_id, scope_id)?);
+                }
+                Ok(TypedPattern::Or(TypedOrPattern { alternatives, span: or_pattern.span }))
+            }
+            ParsedPattern::Binding(binding_pattern) => {
+                let inner =
+                    self.eval_pattern(binding_pattern.inner_pattern, target_type_id, scope_id)?;
+                Ok(TypedPattern::Binding(TypedBindingPattern {
+                    name: binding_pattern.name,
+                    inner: Box::new(inner),
+                    span: binding_pattern.span,
+                }))
+            }
+            ParsedPattern::Array(array_pattern) => {
+                let Some(array_type) = self.types.get(target_type_id).as_array_instance() else {
+                    return failf!(
+                        array_pattern.span,
+                        "Impossible pattern: Expected an Array; but got {}",
+                        self.type_id_to_string(target_type_id)
+                    );
+                };
+                let mut elements = Vec::with_capacity(array_pattern.elements.len());
+                for element in array_pattern.elements.iter() {
+                    elements.push(self.eval_pattern(
+                        *element,
+                        array_type.element_type,
+                        scope_id,
+                    )?);
+                }
+                let rest = match array_pattern.rest {
+                    None => None,
+                    Some(rest) => {
+                        Some(Box::new(self.eval_pattern(rest, target_type_id, scope_id)?))
+                    }
+                };
+                Ok(TypedPattern::Array(TypedArrayPattern {
+                    element_type_id: array_type.element_type,
+                    elements,
+                    rest,
+                    span: array_pattern.span,
+                }))
+            }
+            ParsedPattern::Range(range_pattern) => {
+                let Type::Integer(_) = self.types.get(target_type_id) else {
+                    return failf!(
+                        range_pattern.span,
+                        "unrelated pattern type int will never match {}",
+                        self.type_id_to_string(target_type_id)
+                    );
+                };
+                let mut bounds =
+                    [range_pattern.start, range_pattern.end].into_iter().map(|bound| {
+                        let Literal::Numeric(num_lit) =
+                            self.ast.expressions.get(bound).expect_literal()
+                        else {
+                            return failf!(
+                                range_pattern.span,
+                                "Range pattern bounds must be integers"
+                            );
+                        };
+                        match self.eval_numeric_value(
+                            &num_lit.text,
+                            num_lit.span,
+                            scope_id,
+                            Some(target_type_id),
+                        )? {
+                            TypedExpr::Integer(int) => Ok(int.value),
+                            _ => failf!(num_lit.span, "Range pattern bounds must be integers"),
+                        }
+                    });
+                let start = bounds.next().unwrap()?;
+                let end = bounds.next().unwrap()?;
+                Ok(TypedPattern::Range(TypedRangePattern {
+                    start,
+                    end,
+                    inclusive: range_pattern.inclusive,
+                    span: range_pattern.span,
+                }))
+            }
+            ParsedPattern::StringPrefix(prefix_pattern) => {
+                if target_type_id != STRING_TYPE_ID {
+                    return failf!(
+                        prefix_pattern.span,
+                        "unrelated pattern type string will never match {}",
+                        self.type_id_to_string(target_type_id)
+                    );
+                }
+                let Literal::String(prefix, _) =
+                    self.ast.expressions.get(prefix_pattern.prefix).expect_literal()
+                else {
+                    return failf!(prefix_pattern.span, "Expected a string literal prefix");
+                };
+                let rest = self.eval_pattern(prefix_pattern.rest, STRING_TYPE_ID, scope_id)?;
+                Ok(TypedPattern::StringPrefix(TypedStringPrefixPattern {
+                    prefix: prefix.clone(),
+                    rest: Box::new(rest),
+                    span: prefix_pattern.span,
+                }))
+            }
         }
     }
 
@@ -2438,6 +2961,10 @@ impl TypedModule {
         if expected == actual {
             return Ok(());
         }
+        if expected == ERROR_TYPE_ID || actual == ERROR_TYPE_ID {
+            // An error has already been reported for this expression
+            return Ok(());
+        }
 
         if let (Some(gen1), Some(gen2)) = (
             self.types.get_generic_instance_info(expected),
@@ -2476,6 +3003,11 @@ impl TypedModule {
             (Type::TypeVariable(t1), Type::TypeVariable(t2)) => {
                 if t1.name == t2.name && t1.scope_id == t2.scope_id {
                     Ok(())
+                } else if let Some(expected_resolved) =
+                    self.scopes.find_type(scope_id, t1.name).filter(|t| *t != expected)
+                {
+                    // An impl for a bare type parameter, `impl[T] Foo for T`, binds Self to T
+                    self.check_types(expected_resolved, actual, scope_id)
                 } else {
                     Err(format!(
                         "expected type variable {} but got {}",
@@ -2648,7 +3180,10 @@ impl TypedModule {
         _scope_id: ScopeId,
         expected_type_id: Option<TypeId>,
     ) -> TyperResult<TypedExpr> {
-        if parsed_text.contains('.') {
+        let is_radix_literal = parsed_text.starts_with("0x") || parsed_text.starts_with("0b");
+        let has_exponent = !is_radix_literal && parsed_text.contains(['e', 'E']);
+        let expects_float = matches!(expected_type_id, Some(F32_TYPE_ID) | Some(F64_TYPE_ID));
+        if parsed_text.contains('.') || has_exponent || (expects_float && !is_radix_literal) {
             Ok(TypedExpr::Float(TypedFloatExpr {
                 value: self.eval_float_value(parsed_text, span, expected_type_id)?,
                 span,
@@ -2765,25 +3300,48 @@ impl TypedModule {
     }
 
     fn eval_variable(
-        &self,
+        &mut self,
         variable: &parse::Variable,
         scope_id: ScopeId,
         is_assignment_lhs: bool,
     ) -> TyperResult<TypedExpr> {
-        let variable_id = self
-            .scopes
-            .find_variable_namespaced(
-                scope_id,
-                &variable.name,
-                &self.namespaces,
-                &self.ast.identifiers,
-            )?
-            .ok_or(errf!(
-                variable.name.span,
-                "Variable '{}' is not defined",
-                self.ast.identifiers.get_name(variable.name.name),
-            ))?;
+        let variable_id = match self.scopes.find_variable_namespaced(
+            scope_id,
+            &variable.name,
+            &self.namespaces,
+            &self.ast.identifiers,
+        )? {
+            Some(variable_id) => variable_id,
+            None => {
+                // A bare function name is a reference to that function, as a value
+                if !is_assignment_lhs {
+                    if let Some(function_id) = self.scopes.find_function_namespaced(
+                        scope_id,
+                        &variable.name,
+                        &self.namespaces,
+                        &self.ast.identifiers,
+                    )? {
+                        return self.eval_function_reference(function_id, variable.name.span);
+                    }
+                }
+                return failf!(
+                    variable.name.span,
+                    "Variable '{}' is not defined",
+                    self.ast.identifiers.get_name(variable.name.name),
+                );
+            }
+        };
         let v = self.variables.get_variable(variable_id);
+        if v.type_id == ERROR_TYPE_ID {
+            return Err(TyperError::cascade(variable.name.span));
+        }
+        // Every lambda between here and the variable's definition has to capture it
+        for lambda_scope_id in self.scopes.find_capturing_function_scopes(scope_id, v.owner_scope) {
+            let captures = self.lambda_captures.entry(lambda_scope_id).or_default();
+            if !captures.contains(&variable_id) {
+                captures.push(variable_id);
+            }
+        }
         if is_assignment_lhs && !v.is_mutable {
             return make_fail_span(
                 format!(
@@ -2798,43 +3356,343 @@ impl TypedModule {
             variable_id,
             span: variable.name.span,
         });
+        if let Some(usage) = self.local_usages.get_mut(&variable_id) {
+            if is_assignment_lhs {
+                usage.reassigned = true;
+            } else {
+                usage.read = true;
+            }
+        }
         Ok(expr)
     }
 
-    fn eval_field_access(
+    fn eval_function_reference(
         &mut self,
-        field_access: &parse::FieldAccess,
-        scope: ScopeId,
-        is_assignment_lhs: bool,
-        expected_type: Option<TypeId>,
+        function_id: FunctionId,
+        span: SpanId,
     ) -> TyperResult<TypedExpr> {
-        // Bailout case: Enum Constructor
-        if let Some(enum_result) = self.handle_enum_constructor(
-            field_access.base,
-            field_access.target,
-            None,
-            expected_type,
-            &field_access.type_args,
-            scope,
-            field_access.span,
-        )? {
-            return Ok(enum_result);
+        let function = self.get_function(function_id);
+        if !function.type_params.is_empty() {
+            return failf!(
+                span,
+                "Cannot use generic function '{}' as a value",
+                self.get_ident_str(function.name)
+            );
+        }
+        if function.kind.ability_id().is_some() || function.intrinsic_type.is_some() {
+            return failf!(
+                span,
+                "Cannot use ability or intrinsic function '{}' as a value",
+                self.get_ident_str(function.name)
+            );
         }
+        let params = function
+            .params
+            .iter()
+            .map(|p| FnArgType { type_id: p.type_id, name: p.name })
+            .collect();
+        let type_id = self.types.add_type(Type::Function(FunctionType {
+            params,
+            return_type: function.ret_type,
+            defn_info: None,
+        }));
+        self.called_functions.insert(function_id);
+        Ok(TypedExpr::FunctionReference(FunctionReference { function_id, type_id, span }))
+    }
 
-        let mut base_expr = self.eval_expr(field_access.base, scope, None)?;
+    fn eval_lambda(
+        &mut self,
+        lambda_expr_id: ParsedExpressionId,
+        scope_id: ScopeId,
+        expected_type: Option<TypeId>,
+    ) -> TyperResult<TypedExpr> {
+        let ParsedExpression::Lambda(lambda) = self.ast.expressions.get(lambda_expr_id) else {
+            panic!("Expected lambda expression")
+        };
+        let lambda = lambda.clone();
+        // The expected type only helps if it has the right shape and is fully known
+        let expected_function_type = expected_type.and_then(|t| match self.types.get(t) {
+            Type::Function(f) if f.params.len() == lambda.arguments.len() => Some(f.clone()),
+            _ => None,
+        });
+        let is_concrete = |typer: &Self, type_id: TypeId| {
+            !typer.types.does_type_reference_type_variables(type_id)
+        };
 
-        // Perform auto-dereference
-        let base_expr_type = base_expr.get_type();
-        let base_type = match self.types.get(base_expr_type) {
-            Type::Reference(reference_type) => {
-                // Auto de-reference everything for field access
-                let inner_type = self.types.get(reference_type.inner_type);
-                if !is_assignment_lhs {
-                    // Dereference the base expression
-                    base_expr = TypedExpr::UnaryOp(UnaryOp {
-                        kind: UnaryOpKind::Dereference,
-                        type_id: reference_type.inner_type,
-                        span: base_expr.get_span(),
+        let name = self.ast.identifiers.intern(format!("lambda_{}", self.functions.len()));
+        let lambda_scope_id =
+            self.scopes.add_child_scope(scope_id, ScopeType::FunctionScope, None, Some(name));
+
+        let mut params = Vec::with_capacity(lambda.arguments.len());
+        for (index, arg) in lambda.arguments.iter().enumerate() {
+            let expected_param_type = expected_function_type
+                .as_ref()
+                .map(|f| f.params[index].type_id)
+                .filter(|t| is_concrete(self, *t));
+            let type_id = match (arg.ty, expected_param_type) {
+                (Some(ty), _) => self.eval_type_expr(ty, scope_id)?,
+                (None, Some(expected_param_type)) => expected_param_type,
+                (None, None) => {
+                    return failf!(
+                        arg.span,
+                        "Cannot infer the type of lambda parameter '{}'; add a type annotation",
+                        self.get_ident_str(arg.binding)
+                    );
+                }
+            };
+            let variable_id = self.variables.add_variable(Variable {
+                name: arg.binding,
+                type_id,
+                is_mutable: false,
+                owner_scope: lambda_scope_id,
+            });
+            params.push(FnArgDefn {
+                name: arg.binding,
+                variable_id,
+                position: index as u32,
+                type_id,
+                span: arg.span,
+            });
+            self.scopes.add_variable(lambda_scope_id, arg.binding, variable_id);
+        }
+
+        let return_type = match lambda.return_type {
+            Some(ty) => Some(self.eval_type_expr(ty, scope_id)?),
+            None => expected_function_type
+                .as_ref()
+                .map(|f| f.return_type)
+                .filter(|t| is_concrete(self, *t)),
+        };
+
+        // The function exists before its body is checked, so that `return` can find it
+        let function_id = self.add_function(TypedFunction {
+            name,
+            scope: lambda_scope_id,
+            // Filled in from the body below when there's no annotation
+            ret_type: return_type.unwrap_or(UNIT_TYPE_ID),
+            params,
+            type_params: Vec::new(),
+            block: None,
+            intrinsic_type: None,
+            linkage: Linkage::Standard,
+            specializations: Vec::new(),
+            parsed_id: lambda_expr_id.into(),
+            is_method_of: None,
+            kind: TypedFunctionKind::Lambda { return_type_pending: return_type.is_none() },
+            closure_env: None,
+            span: lambda.span,
+        });
+        self.scopes.set_scope_owner_id(lambda_scope_id, ScopeOwnerId::Function(function_id));
+
+        let body = self.eval_expr(lambda.body, lambda_scope_id, return_type)?;
+        let return_type = match return_type {
+            Some(return_type) => {
+                if let Err(msg) = self.check_types(return_type, body.get_type(), lambda_scope_id) {
+                    return failf!(body.get_span(), "Lambda return type mismatch: {}", msg);
+                }
+                return_type
+            }
+            None => body.get_type(),
+        };
+        self.check_closure_does_not_escape(&body)?;
+        let body_block = self.coerce_expr_to_block(body, lambda_scope_id);
+
+        let captured_variables = self.lambda_captures.remove(&lambda_scope_id).unwrap_or_default();
+        let closure_env = if captured_variables.is_empty() {
+            None
+        } else {
+            let mut captures = Vec::with_capacity(captured_variables.len());
+            let mut env_fields = Vec::with_capacity(captured_variables.len());
+            for (index, variable_id) in captured_variables.into_iter().enumerate() {
+                let variable = self.variables.get_variable(variable_id);
+                let (mode, field_type) = if variable.is_mutable {
+                    let reference_type = ReferenceType { inner_type: variable.type_id };
+                    (CaptureMode::ByReference, self.types.add_type(Type::Reference(reference_type)))
+                } else {
+                    (CaptureMode::ByValue, variable.type_id)
+                };
+                env_fields.push(StructTypeField {
+                    name: variable.name,
+                    type_id: field_type,
+                    index: index as u32,
+                });
+                captures.push(Capture { variable_id, mode });
+            }
+            let env_type = self.types.add_type(Type::Struct(StructType {
+                fields: env_fields,
+                type_defn_info: None,
+                generic_instance_info: None,
+                ast_node: lambda_expr_id.into(),
+            }));
+            Some(ClosureEnvironment { captures, env_type })
+        };
+
+        let function = self.get_function_mut(function_id);
+        function.ret_type = return_type;
+        function.kind = TypedFunctionKind::Lambda { return_type_pending: false };
+        function.block = Some(body_block);
+        function.closure_env = closure_env;
+        let params = function
+            .params
+            .iter()
+            .map(|p| FnArgType { type_id: p.type_id, name: p.name })
+            .collect();
+        let type_id = self.types.add_type(Type::Function(FunctionType {
+            params,
+            return_type,
+            defn_info: None,
+        }));
+        Ok(TypedExpr::FunctionReference(FunctionReference {
+            function_id,
+            type_id,
+            span: lambda.span,
+        }))
+    }
+
+    /// If this expression is, or holds, a closure that captures something by reference,
+    /// returns one of those captured variables
+    fn find_reference_capture(&self, expr: &TypedExpr) -> Option<VariableId> {
+        match expr {
+            TypedExpr::FunctionReference(fun_ref) => {
+                let function = self.get_function(fun_ref.function_id);
+                function.closure_env.as_ref().and_then(|env| env.find_reference_capture())
+            }
+            TypedExpr::Variable(variable) => {
+                self.reference_closure_variables.get(&variable.variable_id).copied()
+            }
+            TypedExpr::Block(block) => match block.statements.last() {
+                Some(TypedStmt::Expr(expr)) => self.find_reference_capture(expr),
+                _ => None,
+            },
+            TypedExpr::If(if_expr) => self
+                .find_reference_capture(&if_expr.consequent)
+                .or_else(|| self.find_reference_capture(&if_expr.alternate)),
+            TypedExpr::Struct(struc) => {
+                struc.fields.iter().find_map(|field| self.find_reference_capture(&field.expr))
+            }
+            TypedExpr::EnumConstructor(enum_constructor) => enum_constructor
+                .payload
+                .as_ref()
+                .and_then(|payload| self.find_reference_capture(payload)),
+            TypedExpr::UnaryOp(unary_op) => match unary_op.kind {
+                UnaryOpKind::Dereference | UnaryOpKind::Reference => {
+                    self.find_reference_capture(&unary_op.expr)
+                }
+                _ => None,
+            },
+            TypedExpr::Cast(cast) => self.find_reference_capture(&cast.base_expr),
+            // A call may hand any of its arguments back to us inside its result
+            TypedExpr::FunctionCall(call) => {
+                if self.type_may_hold_function(call.ret_type, &mut Vec::new()) {
+                    call.args.iter().find_map(|arg| self.find_reference_capture(arg))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether a value of this type could carry a closure inside it. Generic instances count
+    /// their type arguments, since an Array's elements live behind an untyped Pointer
+    fn type_may_hold_function(&self, type_id: TypeId, visited: &mut Vec<TypeId>) -> bool {
+        if visited.contains(&type_id) {
+            return false;
+        }
+        visited.push(type_id);
+        match self.types.get(type_id) {
+            Type::Function(_) | Type::TypeVariable(_) | Type::Generic(_) => true,
+            Type::Struct(struc) => {
+                struc.fields.iter().any(|field| self.type_may_hold_function(field.type_id, visited))
+                    || struc.generic_instance_info.as_ref().is_some_and(|info| {
+                        info.param_values.iter().any(|t| self.type_may_hold_function(*t, visited))
+                    })
+            }
+            Type::Enum(e) => {
+                e.variants
+                    .iter()
+                    .any(|v| v.payload.is_some_and(|p| self.type_may_hold_function(p, visited)))
+                    || e.generic_instance_info.as_ref().is_some_and(|info| {
+                        info.param_values.iter().any(|t| self.type_may_hold_function(*t, visited))
+                    })
+            }
+            Type::EnumVariant(v) => {
+                v.payload.is_some_and(|p| self.type_may_hold_function(p, visited))
+            }
+            Type::Reference(r) => self.type_may_hold_function(r.inner_type, visited),
+            Type::OpaqueAlias(alias) => self.type_may_hold_function(alias.aliasee, visited),
+            _ => false,
+        }
+    }
+
+    /// A closure that captures by reference points into the stack frame of the function that
+    /// created it, so it may be called or passed down, but never returned or stored
+    fn check_closure_does_not_escape(&self, expr: &TypedExpr) -> TyperResult<()> {
+        match self.find_reference_capture(expr) {
+            None => Ok(()),
+            Some(captured_variable_id) => {
+                let captured = self.variables.get_variable(captured_variable_id);
+                failf!(
+                    expr.get_span(),
+                    "Closure captures '{}' by reference, so it cannot escape the function that defines it",
+                    self.get_ident_str(captured.name)
+                )
+            }
+        }
+    }
+
+    /// A closure that captures by reference can be passed to a parameter that only calls it,
+    /// whose declared type is a function type. Any other parameter that could hold a function,
+    /// such as the `elem: T` of `Array::push`, could store it somewhere that outlives the frame
+    fn check_closure_argument_does_not_escape(
+        &self,
+        param_type_id: TypeId,
+        arg: &TypedExpr,
+    ) -> TyperResult<()> {
+        let is_function_param = matches!(self.types.get(param_type_id), Type::Function(_));
+        if !is_function_param && self.type_may_hold_function(param_type_id, &mut Vec::new()) {
+            self.check_closure_does_not_escape(arg)?;
+        }
+        Ok(())
+    }
+
+    fn eval_field_access(
+        &mut self,
+        field_access: &parse::FieldAccess,
+        scope: ScopeId,
+        is_assignment_lhs: bool,
+        expected_type: Option<TypeId>,
+    ) -> TyperResult<TypedExpr> {
+        // Bailout case: Enum Constructor
+        if let Some(enum_result) = self.handle_enum_constructor(
+            field_access.base,
+            field_access.target,
+            None,
+            expected_type,
+            &field_access.type_args,
+            scope,
+            field_access.span,
+        )? {
+            return Ok(enum_result);
+        }
+
+        let mut base_expr = self.eval_expr(field_access.base, scope, None)?;
+
+        // Perform auto-dereference
+        let base_expr_type = base_expr.get_type();
+        if base_expr_type == ERROR_TYPE_ID {
+            return Err(TyperError::cascade(field_access.span));
+        }
+        let base_type = match self.types.get(base_expr_type) {
+            Type::Reference(reference_type) => {
+                // Auto de-reference everything for field access
+                let inner_type = self.types.get(reference_type.inner_type);
+                if !is_assignment_lhs {
+                    // Dereference the base expression
+                    base_expr = TypedExpr::UnaryOp(UnaryOp {
+                        kind: UnaryOpKind::Dereference,
+                        type_id: reference_type.inner_type,
+                        span: base_expr.get_span(),
                         expr: Box::new(base_expr),
                     });
                 }
@@ -2916,7 +3774,9 @@ impl TypedModule {
         _expected_type: Option<TypeId>,
     ) -> TyperResult<TypedExpr> {
         match self.ast.expressions.get(expr) {
-            ParsedExpression::Variable(variable) => self.eval_variable(variable, scope_id, true),
+            ParsedExpression::Variable(variable) => {
+                self.eval_variable(&variable.clone(), scope_id, true)
+            }
             ParsedExpression::FieldAccess(field_access) => {
                 self.eval_field_access(&field_access.clone(), scope_id, true, None)
             }
@@ -2985,10 +3845,16 @@ impl TypedModule {
                 }),
             );
         };
-        if self.types.get(expected_type_id).as_reference().is_none() {
+        let expected_type = self.types.get(expected_type_id);
+        if expected_type.as_reference().is_none() && !matches!(expected_type, Type::TypeVariable(_))
+        {
             // We only do this if the expected type is not a reference at all. Meaning,
             // if your expected type is T*, and you pass a T**, you need to de-reference that yourself.
             // This rule won't help you or do anything for nested references
+            // An unsolved type parameter could just as well be the reference type itself, so we
+            // don't dereference for those: inferring `T` from a `Point*` as `Point` would copy the
+            // value, and lose any abilities that only the reference implements, such as `Arena*`'s
+            // Allocator
 
             // Note: We could also introduce a 'check_kinds' which only cares about the _shape_ of the type!
             if let Some(reference) = self.types.get(expression.get_type()).as_reference() {
@@ -3101,6 +3967,24 @@ impl TypedModule {
         }
     }
 
+    /// Evaluates a subexpression, such as an operand or a call argument, whose failure shouldn't
+    /// stop us from checking its siblings. The error is reported and an error-typed expression
+    /// takes its place
+    fn eval_expr_or_error(
+        &mut self,
+        expr_id: ParsedExpressionId,
+        scope_id: ScopeId,
+        expected_type: Option<TypeId>,
+    ) -> TypedExpr {
+        match self.eval_expr(expr_id, scope_id, expected_type) {
+            Ok(expr) => expr,
+            Err(e) => {
+                self.report_error(e);
+                TypedExpr::Error(self.ast.expressions.get_span(expr_id))
+            }
+        }
+    }
+
     fn eval_expr_inner(
         &mut self,
         expr_id: ParsedExpressionId,
@@ -3126,66 +4010,36 @@ impl TypedModule {
                 let parsed_elements = array_expr.elements.clone();
                 let element_count = parsed_elements.len();
 
-                let mut array_lit_block = self.synth_block(vec![], scope_id, span);
-                let array_lit_scope = array_lit_block.scope_id;
+                let mut has_error = false;
                 let elements: Vec<TypedExpr> = {
                     let mut elements = Vec::with_capacity(element_count);
                     for elem in parsed_elements.iter() {
-                        let element_expr = self.eval_expr(*elem, scope_id, element_type)?;
-                        if element_type.is_none() {
+                        let element_expr = self.eval_expr_or_error(*elem, scope_id, element_type);
+                        if element_expr.get_type() == ERROR_TYPE_ID {
+                            has_error = true;
+                        } else if element_type.is_none() {
                             element_type = Some(element_expr.get_type())
                         } else if let Err(msg) = self.check_types(
                             element_type.unwrap(),
                             element_expr.get_type(),
-                            array_lit_scope,
+                            scope_id,
                         ) {
-                            return failf!(span, "Array element had incorrect type: {msg}");
+                            has_error = true;
+                            self.report_error(TyperError::make(
+                                format!("Array element had incorrect type: {msg}"),
+                                span,
+                            ));
                         };
                         elements.push(element_expr);
                     }
                     elements
                 };
-                let element_type = element_type.expect("By now this should be populated");
-                let array_new_fn_call = self.synth_function_call(
-                    qident!(self, span, ["Array"], "new"),
-                    span,
-                    array_lit_scope,
-                    Some((
-                        vec![element_type],
-                        vec![TypedExpr::Integer(TypedIntegerExpr {
-                            value: TypedIntegerValue::U64(element_count as u64),
-                            span,
-                        })],
-                    )),
-                )?;
-                let array_new_expr = self.synth_reference(array_new_fn_call);
-                let array_variable = self.synth_variable_defn(
-                    get_ident!(self, "array_literal"),
-                    array_new_expr,
-                    false,
-                    false,
-                    array_lit_scope,
-                );
-                let mut set_elements = Vec::with_capacity(element_count);
-                for element_value_expr in elements.into_iter() {
-                    let element_set = TypedStmt::Expr(Box::new(self.synth_function_call(
-                        qident!(self, span, ["Array"], "push"),
-                        span,
-                        array_lit_scope,
-                        Some((
-                            vec![element_type],
-                            vec![array_variable.variable_expr.clone(), element_value_expr],
-                        )),
-                    )?));
-                    set_elements.push(element_set);
+                // Every element has been checked, but there's no sensible array to build
+                if has_error {
+                    return Ok(TypedExpr::Error(span));
                 }
-                array_lit_block.statements.push(array_variable.defn_stmt);
-                array_lit_block.statements.extend(set_elements);
-                let dereference_array_literal =
-                    self.synth_dereference(array_variable.variable_expr);
-                array_lit_block.push_expr(dereference_array_literal);
-                eprintln!("array_literal_desugar {}", self.block_to_string(&array_lit_block));
-                Ok(TypedExpr::Block(array_lit_block))
+                let element_type = element_type.expect("By now this should be populated");
+                self.synth_array_literal(elements, element_type, scope_id, span)
             }
             ParsedExpression::Struct(ast_struct) => {
                 let mut field_values = Vec::new();
@@ -3214,7 +4068,8 @@ impl TypedModule {
                         .as_ref()
                         .and_then(|(_, rec)| rec.find_field(ast_field.name));
                     let expected_type_id = expected_field.map(|(_, f)| f.type_id);
-                    let expr = self.eval_expr(ast_field.expr, scope_id, expected_type_id)?;
+                    let expr = self.eval_expr_or_error(ast_field.expr, scope_id, expected_type_id);
+                    self.check_closure_does_not_escape(&expr)?;
                     field_defns.push(StructTypeField {
                         name: ast_field.name,
                         type_id: expr.get_type(),
@@ -3312,6 +4167,9 @@ impl TypedModule {
                             span: op.span,
                         }))
                     }
+                    ParsedUnaryOpKind::Negation => {
+                        self.eval_negation(op.expr, op.span, scope_id, expected_type)
+                    }
                     ParsedUnaryOpKind::BooleanNegation => {
                         let base_expr = self.eval_expr(op.expr, scope_id, expected_type)?;
                         self.check_types(BOOL_TYPE_ID, base_expr.get_type(), scope_id)
@@ -3345,7 +4203,9 @@ impl TypedModule {
                 let expr = TypedExpr::Str(s.clone(), *span);
                 Ok(expr)
             }
-            ParsedExpression::Variable(variable) => self.eval_variable(variable, scope_id, false),
+            ParsedExpression::Variable(variable) => {
+                self.eval_variable(&variable.clone(), scope_id, false)
+            }
             ParsedExpression::FieldAccess(field_access) => {
                 let field_access = field_access.clone();
                 self.eval_field_access(&field_access, scope_id, false, expected_type)
@@ -3382,6 +4242,14 @@ impl TypedModule {
                 )?;
                 Ok(get_fn_call)
             }
+            ParsedExpression::Try(try_expr) => {
+                let try_expr = try_expr.clone();
+                self.eval_try(&try_expr, scope_id)
+            }
+            ParsedExpression::OptionalChain(chain) => {
+                let chain = chain.clone();
+                self.eval_optional_chain(&chain, scope_id)
+            }
             ParsedExpression::For(for_expr) => {
                 self.eval_for_expr(&for_expr.clone(), scope_id, expected_type)
             }
@@ -3465,12 +4333,14 @@ impl TypedModule {
                 );
                 let true_case = parse::ParsedMatchCase {
                     pattern: is_expr.pattern,
+                    guard: None,
                     expression: true_expression,
                 };
                 let wildcard_pattern =
                     self.ast.patterns.add_pattern(parse::ParsedPattern::Wildcard(is_expr.span));
                 let false_case = parse::ParsedMatchCase {
                     pattern: wildcard_pattern,
+                    guard: None,
                     expression: false_expression,
                 };
                 let as_match_expr = parse::ParsedMatchExpression {
@@ -3490,6 +4360,25 @@ impl TypedModule {
                 self.eval_match_expr(expr_id, scope_id, expected_type, partial_match)
             }
             ParsedExpression::AsCast(_cast) => self.eval_cast(expr_id, scope_id),
+            ParsedExpression::InterpolatedString(interpolated) => {
+                let interpolated = interpolated.clone();
+                self.eval_interpolated_string(&interpolated, scope_id)
+            }
+            ParsedExpression::Lambda(_lambda) => self.eval_lambda(expr_id, scope_id, expected_type),
+            ParsedExpression::Break(brk) => {
+                let brk = brk.clone();
+                self.eval_break(&brk, scope_id)
+            }
+            ParsedExpression::Continue(span) => {
+                let span = *span;
+                let Some(loop_scope_id) = self.scopes.find_loop_scope(scope_id) else {
+                    return failf!(span, "continue can only be used inside a loop");
+                };
+                if self.scopes.find_defer_scope(scope_id, Some(loop_scope_id)).is_some() {
+                    return failf!(span, "Cannot continue out of a defer");
+                }
+                Ok(TypedExpr::Continue(TypedContinue { loop_scope_id, span }))
+            }
         };
         result
     }
@@ -3618,9 +4507,34 @@ impl TypedModule {
                 self.eval_pattern(parsed_case.pattern, target_expr.type_id, match_scope_id)?;
             let arm_expr_span = self.ast.expressions.get(parsed_case.expression).get_span();
             let mut arm_block = self.synth_block(vec![], match_scope_id, arm_expr_span);
-            let (pre_stmts, condition) =
+            let (pre_stmts, mut condition) =
                 self.eval_match_arm(&pattern, target_expr.clone(), &mut arm_block, match_scope_id)?;
 
+            if let Some(guard_id) = parsed_case.guard {
+                let guard_span = self.ast.expressions.get(guard_id).get_span();
+                let guard_expr =
+                    self.eval_expr(guard_id, arm_block.scope_id, Some(BOOL_TYPE_ID))?;
+                if let Err(msg) =
+                    self.check_types(BOOL_TYPE_ID, guard_expr.get_type(), arm_block.scope_id)
+                {
+                    return failf!(guard_span, "Match guard must be a bool: {}", msg);
+                }
+                // The guard can refer to the arm's bindings, so it gets its own copy of them
+                let mut guard_block =
+                    self.synth_block(arm_block.statements.clone(), arm_block.scope_id, guard_span);
+                guard_block.push_expr(guard_expr);
+                condition = match condition {
+                    TypedExpr::Bool(true, _) => TypedExpr::Block(guard_block),
+                    condition => TypedExpr::BinaryOp(BinaryOp {
+                        kind: BinaryOpKind::And,
+                        ty: BOOL_TYPE_ID,
+                        lhs: Box::new(condition),
+                        rhs: Box::new(TypedExpr::Block(guard_block)),
+                        span: guard_span,
+                    }),
+                };
+            }
+
             // Once we've evaluated the arm pattern, we can eval the consequent expression inside of it,
             // since the bindings are now in scope inside arm_block
             let arm_expr =
@@ -3643,44 +4557,128 @@ impl TypedModule {
             arm_block.push_expr(arm_expr);
 
             expected_arm_type_id = Some(arm_block.expr_type);
-            typed_cases.push(TypedMatchCase { pattern, pre_stmts, condition, arm_block });
+            typed_cases.push(TypedMatchCase {
+                pattern,
+                has_guard: parsed_case.guard.is_some(),
+                pre_stmts,
+                condition,
+                arm_block,
+            });
         }
 
         // Exhaustiveness Checking
         if !partial {
-            let trial_constructors: Vec<PatternConstructor> =
-                self.generate_constructors_for_type(target_expr.type_id, target_expr.span);
-            let mut trial_alives: Vec<bool> = vec![true; trial_constructors.len()];
+            let max_array_len =
+                typed_cases.iter().map(|c| c.pattern.max_array_pattern_len()).max().unwrap_or(0);
+            let mut array_elements_elided = false;
+            let trial_constructors: Vec<PatternConstructor> = self.generate_constructors_for_type(
+                target_expr.type_id,
+                max_array_len,
+                &mut array_elements_elided,
+                target_expr.span,
+            );
+            // The first unguarded arm that covers each trial constructor, if any
+            let mut trial_claimed_by: Vec<Option<usize>> = vec![None; trial_constructors.len()];
             let mut pattern_scores: Vec<usize> = vec![0; typed_cases.len()];
             'trial: for (trial_index, trial_expr) in trial_constructors.iter().enumerate() {
-                '_pattern: for (index, pattern) in typed_cases.iter().enumerate() {
-                    let pattern = &pattern.pattern;
-                    if self.pattern_matches(&pattern, trial_expr) {
+                '_pattern: for (index, typed_case) in typed_cases.iter().enumerate() {
+                    if self.pattern_matches(&typed_case.pattern, trial_expr) {
                         pattern_scores[index] += 1;
-                        trial_alives[trial_index] = false;
-                        continue 'trial;
+                        // A guarded arm may not be taken, so the trial stays alive for later arms
+                        if !typed_case.has_guard {
+                            trial_claimed_by[trial_index] = Some(index);
+                            continue 'trial;
+                        }
                     }
                 }
             }
 
-            if let Some(alive_index) = trial_alives.iter().position(|p| *p) {
-                let pattern = &trial_constructors[alive_index];
+            let missing: Vec<String> = trial_constructors
+                .iter()
+                .zip(trial_claimed_by.iter())
+                .filter(|(_, claimed_by)| claimed_by.is_none())
+                .map(|(ctor, _)| self.pattern_ctor_to_string(ctor))
+                .collect();
+            if !missing.is_empty() {
+                const MAX_WITNESSES: usize = 5;
+                let mut listed = missing.iter().take(MAX_WITNESSES).cloned().collect::<Vec<_>>();
+                if missing.len() > MAX_WITNESSES {
+                    listed.push(format!("and {} more", missing.len() - MAX_WITNESSES));
+                }
+                // Those arrays' elements were only checked against wildcards and bindings, so
+                // literal elements that do cover every case aren't seen to
+                let elided_note = if array_elements_elided {
+                    format!(
+                        " (array patterns this long have over {} element combinations, so only wildcard and binding elements are counted)",
+                        MAX_ARRAY_PATTERN_CONSTRUCTORS
+                    )
+                } else {
+                    String::new()
+                };
                 return failf!(
                     target_expr.span,
-                    "Unhandled pattern: {}",
-                    self.pattern_ctor_to_string(pattern)
+                    "Match is not exhaustive; missing: {}{}",
+                    listed.join(", "),
+                    elided_note
                 );
             }
 
-            if let Some(useless_index) = pattern_scores.iter().position(|p| *p == 0) {
-                let pattern = &typed_cases[useless_index].pattern;
-                if !pattern.is_innumerable_literal() {
-                    return failf!(
-                        pattern.span_id(),
-                        "Useless pattern: {}",
-                        self.pattern_to_string(pattern)
-                    );
+            for (index, typed_case) in typed_cases.iter().enumerate() {
+                let pattern = &typed_case.pattern;
+                if pattern_scores[index] != 0 || pattern.is_innumerable_literal() {
+                    continue;
+                }
+                // Everything this arm matches was claimed by earlier arms; find out which
+                let mut subsuming_arms: Vec<usize> = Vec::new();
+                for (ctor, claimed_by) in trial_constructors.iter().zip(trial_claimed_by.iter()) {
+                    if let Some(claimed_by) = claimed_by {
+                        if self.pattern_matches(pattern, ctor)
+                            && !subsuming_arms.contains(claimed_by)
+                        {
+                            subsuming_arms.push(*claimed_by);
+                        }
+                    }
                 }
+                let warning = match subsuming_arms.as_slice() {
+                    [] => Diagnostic::warning(
+                        Lint::UselessArm,
+                        format!("Useless arm: {} never matches", self.pattern_to_string(pattern)),
+                        pattern.span_id(),
+                    ),
+                    [subsuming] => {
+                        let subsuming_pattern = &typed_cases[*subsuming].pattern;
+                        Diagnostic::warning(
+                            Lint::UselessArm,
+                            format!(
+                                "Useless arm: {} is already covered by the arm {}",
+                                self.pattern_to_string(pattern),
+                                self.pattern_to_string(subsuming_pattern)
+                            ),
+                            pattern.span_id(),
+                        )
+                        .with_label(
+                            subsuming_pattern.span_id(),
+                            "this arm matches everything it would",
+                        )
+                    }
+                    subsuming => subsuming.iter().fold(
+                        Diagnostic::warning(
+                            Lint::UselessArm,
+                            format!(
+                                "Useless arm: {} is already covered by earlier arms",
+                                self.pattern_to_string(pattern)
+                            ),
+                            pattern.span_id(),
+                        ),
+                        |warning, subsuming| {
+                            warning.with_label(
+                                typed_cases[*subsuming].pattern.span_id(),
+                                "covers part of it",
+                            )
+                        },
+                    ),
+                };
+                self.report_warning(warning);
             }
         }
 
@@ -3844,6 +4842,31 @@ impl TypedModule {
                 };
                 Ok((vec![], TypedExpr::BinaryOp(bin_op)))
             }
+            TypedPattern::Range(range_pattern) => {
+                let span = range_pattern.span;
+                let bound_check = |kind: BinaryOpKind, bound: TypedIntegerValue| {
+                    TypedExpr::BinaryOp(BinaryOp {
+                        kind,
+                        ty: BOOL_TYPE_ID,
+                        lhs: Box::new(target_expr_variable_expr.clone().into()),
+                        rhs: Box::new(TypedExpr::Integer(TypedIntegerExpr { value: bound, span })),
+                        span,
+                    })
+                };
+                let end_kind = if range_pattern.inclusive {
+                    BinaryOpKind::LessEqual
+                } else {
+                    BinaryOpKind::Less
+                };
+                let condition = TypedExpr::BinaryOp(BinaryOp {
+                    kind: BinaryOpKind::And,
+                    ty: BOOL_TYPE_ID,
+                    lhs: Box::new(bound_check(BinaryOpKind::GreaterEqual, range_pattern.start)),
+                    rhs: Box::new(bound_check(end_kind, range_pattern.end)),
+                    span,
+                });
+                Ok((vec![], condition))
+            }
             TypedPattern::LiteralBool(bool_value, span) => {
                 let bin_op = self.synth_equals_binop(
                     target_expr_variable_expr.into(),
@@ -3861,57 +4884,417 @@ impl TypedModule {
                 Ok((vec![], condition))
             }
             TypedPattern::Wildcard(span) => Ok((vec![], TypedExpr::Bool(true, *span))),
-        }
-    }
-
-    fn eval_cast(
-        &mut self,
-        expr_id: ParsedExpressionId,
-        scope_id: ScopeId,
-    ) -> TyperResult<TypedExpr> {
-        let cast = self.ast.expressions.get(expr_id).expect_cast();
-        let cast = cast.clone();
-        let base_expr = self.eval_expr(cast.base_expr, scope_id, None)?;
-        let base_expr_type = base_expr.get_type();
-        let target_type = self.eval_type_expr(cast.dest_type, scope_id)?;
-        let (cast_type, output_type) = match self.types.get(base_expr_type) {
-            Type::Integer(from_integer_type) => match self.types.get(target_type) {
-                Type::Integer(to_integer_type) => {
-                    let cast_type = match from_integer_type.width().cmp(&to_integer_type.width()) {
-                        Ordering::Less => CastType::IntegerExtend,
-                        Ordering::Greater => CastType::IntegerTruncate,
-                        Ordering::Equal => CastType::KnownNoOp,
-                    };
-                    Ok((cast_type, target_type))
-                }
-                Type::Char(_) => {
-                    if from_integer_type.width() == NumericWidth::B8 {
-                        Ok((CastType::Integer8ToChar, target_type))
-                    } else {
-                        failf!(
-                            cast.span,
-                            "Cannot cast integer '{}' to char, must be 8 bits",
-                            from_integer_type
-                        )
-                    }
-                }
-                Type::Pointer(_) => {
-                    if *from_integer_type == IntegerType::U64 {
-                        Ok((CastType::IntToPointer, target_type))
+            TypedPattern::Array(array_pattern) => {
+                let span = array_pattern.span;
+                let binding_index = arm_block.statements.len();
+                let target_expr: TypedExpr = target_expr_variable_expr.into();
+                let target_length = TypedExpr::StructFieldAccess(FieldAccess {
+                    base: Box::new(target_expr.clone()),
+                    target_field: get_ident!(self, "len"),
+                    target_field_index: 0,
+                    ty: U64_TYPE_ID,
+                    span,
+                });
+                let element_count = TypedExpr::Integer(TypedIntegerExpr {
+                    value: TypedIntegerValue::U64(array_pattern.elements.len() as u64),
+                    span,
+                });
+                let length_check = TypedExpr::BinaryOp(BinaryOp {
+                    kind: if array_pattern.rest.is_some() {
+                        BinaryOpKind::GreaterEqual
                     } else {
-                        failf!(
-                            cast.span,
-                            "Cannot cast integer '{}' to Pointer (must be u64; one day usize)",
-                            from_integer_type
-                        )
-                    }
-                }
-                _ => failf!(
-                    cast.span,
-                    "Cannot cast integer '{}' to '{}'",
-                    from_integer_type,
-                    self.type_id_to_string(target_type).blue()
-                ),
+                        BinaryOpKind::Equals
+                    },
+                    ty: BOOL_TYPE_ID,
+                    lhs: Box::new(target_length.clone()),
+                    rhs: Box::new(element_count.clone()),
+                    span,
+                });
+                let mut stmts = vec![];
+                let mut conditions = vec![];
+                let element_ident = self.ast.identifiers.intern("elem");
+                for (index, element_pattern) in array_pattern.elements.iter().enumerate() {
+                    let element_ref = self.synth_function_call(
+                        qident!(self, span, ["Array"], "getRef"),
+                        span,
+                        arm_block.scope_id,
+                        Some((
+                            vec![array_pattern.element_type_id],
+                            vec![
+                                target_expr.clone(),
+                                TypedExpr::Integer(TypedIntegerExpr {
+                                    value: TypedIntegerValue::U64(index as u64),
+                                    span,
+                                }),
+                            ],
+                        )),
+                    )?;
+                    let element_value = TypedExpr::UnaryOp(UnaryOp {
+                        kind: UnaryOpKind::Dereference,
+                        type_id: array_pattern.element_type_id,
+                        expr: Box::new(element_ref),
+                        span,
+                    });
+                    let element_variable = self.synth_variable_defn(
+                        element_ident,
+                        element_value,
+                        false,
+                        false,
+                        arm_block.scope_id,
+                    );
+                    stmts.push(element_variable.defn_stmt);
+                    let (inner_stmts, condition) = self.eval_match_arm(
+                        element_pattern,
+                        element_variable.variable_expr.expect_variable(),
+                        arm_block,
+                        match_scope_id,
+                    )?;
+                    stmts.extend(inner_stmts);
+                    conditions.push(condition);
+                }
+                if let Some(rest_pattern) = array_pattern.rest.as_ref() {
+                    let rest_value = self.synth_function_call(
+                        qident!(self, span, ["Array"], "slice"),
+                        span,
+                        arm_block.scope_id,
+                        Some((
+                            vec![array_pattern.element_type_id],
+                            vec![target_expr, element_count, target_length],
+                        )),
+                    )?;
+                    let rest_ident = self.ast.identifiers.intern("rest");
+                    let rest_variable = self.synth_variable_defn(
+                        rest_ident,
+                        rest_value,
+                        false,
+                        false,
+                        arm_block.scope_id,
+                    );
+                    stmts.push(rest_variable.defn_stmt);
+                    let (inner_stmts, condition) = self.eval_match_arm(
+                        rest_pattern,
+                        rest_variable.variable_expr.expect_variable(),
+                        arm_block,
+                        match_scope_id,
+                    )?;
+                    stmts.extend(inner_stmts);
+                    conditions.push(condition);
+                }
+                let condition = self.synth_checked_pattern_condition(
+                    length_check,
+                    stmts,
+                    conditions,
+                    arm_block,
+                    binding_index,
+                    span,
+                );
+                Ok((vec![], condition))
+            }
+            TypedPattern::StringPrefix(prefix_pattern) => {
+                let span = prefix_pattern.span;
+                let binding_index = arm_block.statements.len();
+                let target_expr: TypedExpr = target_expr_variable_expr.into();
+                let prefix_check = self.synth_function_call(
+                    qident!(self, span, ["string"], "startsWith"),
+                    span,
+                    arm_block.scope_id,
+                    Some((
+                        vec![],
+                        vec![
+                            target_expr.clone(),
+                            TypedExpr::Str(prefix_pattern.prefix.clone(), span),
+                        ],
+                    )),
+                )?;
+                let target_length = TypedExpr::StructFieldAccess(FieldAccess {
+                    base: Box::new(target_expr.clone()),
+                    target_field: get_ident!(self, "len"),
+                    target_field_index: 0,
+                    ty: U64_TYPE_ID,
+                    span,
+                });
+                let rest_value = self.synth_function_call(
+                    qident!(self, span, ["string"], "slice"),
+                    span,
+                    arm_block.scope_id,
+                    Some((
+                        vec![],
+                        vec![
+                            target_expr,
+                            TypedExpr::Integer(TypedIntegerExpr {
+                                value: TypedIntegerValue::U64(prefix_pattern.prefix.len() as u64),
+                                span,
+                            }),
+                            target_length,
+                        ],
+                    )),
+                )?;
+                let rest_ident = self.ast.identifiers.intern("rest");
+                let rest_variable = self.synth_variable_defn(
+                    rest_ident,
+                    rest_value,
+                    false,
+                    false,
+                    arm_block.scope_id,
+                );
+                let mut stmts = vec![rest_variable.defn_stmt];
+                let (inner_stmts, condition) = self.eval_match_arm(
+                    &prefix_pattern.rest,
+                    rest_variable.variable_expr.expect_variable(),
+                    arm_block,
+                    match_scope_id,
+                )?;
+                stmts.extend(inner_stmts);
+                let condition = self.synth_checked_pattern_condition(
+                    prefix_check,
+                    stmts,
+                    vec![condition],
+                    arm_block,
+                    binding_index,
+                    span,
+                );
+                Ok((vec![], condition))
+            }
+            TypedPattern::Binding(binding_pattern) => {
+                let binding_variable = self.synth_variable_defn(
+                    binding_pattern.name,
+                    target_expr_variable_expr.clone().into(),
+                    true,
+                    false,
+                    arm_block.scope_id,
+                );
+                arm_block.push_stmt(binding_variable.defn_stmt);
+                self.eval_match_arm(
+                    &binding_pattern.inner,
+                    target_expr_variable_expr,
+                    arm_block,
+                    match_scope_id,
+                )
+            }
+            TypedPattern::Or(or_pattern) => {
+                let mut condition_statements = vec![];
+                let mut alternative_conditions = Vec::with_capacity(or_pattern.alternatives.len());
+                let mut alternative_blocks = Vec::with_capacity(or_pattern.alternatives.len());
+                for alternative in or_pattern.alternatives.iter() {
+                    // Each alternative binds into a throwaway block; the arm then binds each name
+                    // to the value from whichever alternative matched
+                    let mut alternative_block =
+                        self.synth_block(vec![], arm_block.scope_id, alternative.span_id());
+                    let (stmts, condition) = self.eval_match_arm(
+                        alternative,
+                        target_expr_variable_expr.clone(),
+                        &mut alternative_block,
+                        match_scope_id,
+                    )?;
+                    condition_statements.extend(stmts);
+                    alternative_conditions.push(condition);
+                    alternative_blocks.push(alternative_block);
+                }
+
+                let mut bound_names = Vec::new();
+                or_pattern.alternatives[0].collect_bound_names(&mut bound_names);
+                for alternative in or_pattern.alternatives.iter().skip(1) {
+                    let mut alternative_names = Vec::new();
+                    alternative.collect_bound_names(&mut alternative_names);
+                    if let Some(missing) =
+                        bound_names.iter().find(|n| !alternative_names.contains(n))
+                    {
+                        return failf!(
+                            alternative.span_id(),
+                            "Every alternative of an or-pattern must bind the same variables; this one does not bind '{}'",
+                            self.get_ident_str(*missing).blue()
+                        );
+                    }
+                    if let Some(extra) = alternative_names.iter().find(|n| !bound_names.contains(n))
+                    {
+                        return failf!(
+                            alternative.span_id(),
+                            "Every alternative of an or-pattern must bind the same variables; '{}' is not bound by the first alternative",
+                            self.get_ident_str(*extra).blue()
+                        );
+                    }
+                }
+                for name in bound_names.into_iter() {
+                    let mut values: Vec<TypedExpr> = Vec::with_capacity(alternative_blocks.len());
+                    let mut binding_type: Option<TypeId> = None;
+                    for (alternative, alternative_block) in
+                        or_pattern.alternatives.iter().zip(alternative_blocks.iter())
+                    {
+                        let binding = alternative_block
+                            .statements
+                            .iter()
+                            .filter_map(|stmt| match stmt {
+                                TypedStmt::ValDef(val_def) => Some(val_def),
+                                _ => None,
+                            })
+                            .find(|val_def| {
+                                self.variables.get_variable(val_def.variable_id).name == name
+                            })
+                            .expect("bound name has a binding");
+                        let binding_type = *binding_type.get_or_insert(binding.ty);
+                        if let Err(msg) =
+                            self.check_types(binding_type, binding.ty, arm_block.scope_id)
+                        {
+                            return failf!(
+                                alternative.span_id(),
+                                "Variable '{}' has a different type in this alternative: {}",
+                                self.get_ident_str(name).blue(),
+                                msg
+                            );
+                        }
+                        // The binding may refer to variables the alternative synthesized, so
+                        // the value brings all of them along
+                        let mut value_block = self.synth_block(
+                            alternative_block.statements.clone(),
+                            arm_block.scope_id,
+                            alternative.span_id(),
+                        );
+                        value_block.push_expr(TypedExpr::Variable(VariableExpr {
+                            variable_id: binding.variable_id,
+                            type_id: binding.ty,
+                            span: alternative.span_id(),
+                        }));
+                        values.push(TypedExpr::Block(value_block));
+                    }
+                    let binding_type = binding_type.unwrap();
+                    let mut value = values.pop().unwrap();
+                    while let Some(consequent) = values.pop() {
+                        let condition = alternative_conditions[values.len()].clone();
+                        value = TypedExpr::If(Box::new(TypedIf {
+                            span: or_pattern.span,
+                            condition,
+                            consequent,
+                            alternate: value,
+                            ty: binding_type,
+                        }));
+                    }
+                    let binding_variable =
+                        self.synth_variable_defn(name, value, true, false, arm_block.scope_id);
+                    arm_block.push_stmt(binding_variable.defn_stmt);
+                }
+
+                let final_condition = alternative_conditions
+                    .into_iter()
+                    .reduce(|acc, expr| {
+                        TypedExpr::BinaryOp(BinaryOp {
+                            kind: BinaryOpKind::Or,
+                            ty: BOOL_TYPE_ID,
+                            lhs: Box::new(acc),
+                            rhs: Box::new(expr),
+                            span: or_pattern.span,
+                        })
+                    })
+                    .unwrap();
+                Ok((condition_statements, final_condition))
+            }
+        }
+    }
+
+    /// Patterns like arrays can only look inside their target once a check has passed, so
+    /// their variables go into the condition behind that check. The arm can't see those
+    /// variables, so they're repeated at the top of the arm, ahead of the bindings that use them
+    fn synth_checked_pattern_condition(
+        &mut self,
+        check: TypedExpr,
+        stmts: Vec<TypedStmt>,
+        conditions: Vec<TypedExpr>,
+        arm_block: &mut TypedBlock,
+        binding_index: usize,
+        span: SpanId,
+    ) -> TypedExpr {
+        arm_block.statements.splice(binding_index..binding_index, stmts.iter().cloned());
+        let inner_condition = conditions
+            .into_iter()
+            .filter(|c| !matches!(c, TypedExpr::Bool(true, _)))
+            .reduce(|acc, expr| {
+                TypedExpr::BinaryOp(BinaryOp {
+                    kind: BinaryOpKind::And,
+                    ty: BOOL_TYPE_ID,
+                    lhs: Box::new(acc),
+                    rhs: Box::new(expr),
+                    span,
+                })
+            });
+        let Some(inner_condition) = inner_condition else {
+            return check;
+        };
+        let mut condition_block = self.synth_block(stmts, arm_block.scope_id, span);
+        condition_block.push_expr(inner_condition);
+        TypedExpr::BinaryOp(BinaryOp {
+            kind: BinaryOpKind::And,
+            ty: BOOL_TYPE_ID,
+            lhs: Box::new(check),
+            rhs: Box::new(TypedExpr::Block(condition_block)),
+            span,
+        })
+    }
+
+    fn eval_cast(
+        &mut self,
+        expr_id: ParsedExpressionId,
+        scope_id: ScopeId,
+    ) -> TyperResult<TypedExpr> {
+        let cast = self.ast.expressions.get(expr_id).expect_cast();
+        let cast = cast.clone();
+        let base_expr = self.eval_expr(cast.base_expr, scope_id, None)?;
+        let base_expr_type = base_expr.get_type();
+        let target_type = self.eval_type_expr(cast.dest_type, scope_id)?;
+        let (cast_type, output_type) = match self.types.get(base_expr_type) {
+            Type::Integer(from_integer_type) => match self.types.get(target_type) {
+                Type::Integer(to_integer_type) => {
+                    let cast_type = match from_integer_type.width().cmp(&to_integer_type.width()) {
+                        Ordering::Less => CastType::IntegerExtend,
+                        Ordering::Greater => CastType::IntegerTruncate,
+                        Ordering::Equal => CastType::KnownNoOp,
+                    };
+                    Ok((cast_type, target_type))
+                }
+                Type::Char(_) => {
+                    if from_integer_type.width() == NumericWidth::B8 {
+                        Ok((CastType::Integer8ToChar, target_type))
+                    } else {
+                        failf!(
+                            cast.span,
+                            "Cannot cast integer '{}' to char, must be 8 bits",
+                            from_integer_type
+                        )
+                    }
+                }
+                Type::Float(_) => Ok((CastType::IntegerToFloat, target_type)),
+                Type::Pointer(_) => {
+                    if *from_integer_type == IntegerType::U64 {
+                        Ok((CastType::IntToPointer, target_type))
+                    } else {
+                        failf!(
+                            cast.span,
+                            "Cannot cast integer '{}' to Pointer (must be u64; one day usize)",
+                            from_integer_type
+                        )
+                    }
+                }
+                _ => failf!(
+                    cast.span,
+                    "Cannot cast integer '{}' to '{}'",
+                    from_integer_type,
+                    self.type_id_to_string(target_type).blue()
+                ),
+            },
+            Type::Float(from_float_type) => match self.types.get(target_type) {
+                Type::Float(to_float_type) => {
+                    let cast_type = match from_float_type.size.cmp(&to_float_type.size) {
+                        Ordering::Less => CastType::FloatExtend,
+                        Ordering::Greater => CastType::FloatTruncate,
+                        Ordering::Equal => CastType::KnownNoOp,
+                    };
+                    Ok((cast_type, target_type))
+                }
+                Type::Integer(_) => Ok((CastType::FloatToInteger, target_type)),
+                _ => failf!(
+                    cast.span,
+                    "Cannot cast float '{}' to '{}'",
+                    self.type_id_to_string(base_expr_type),
+                    self.type_id_to_string(target_type).blue()
+                ),
             },
             Type::Char(_) => match self.types.get(target_type) {
                 Type::Integer(_to_integer_type) => {
@@ -3955,6 +5338,156 @@ impl TypedModule {
         }))
     }
 
+    fn eval_break(&mut self, brk: &ParsedBreak, scope_id: ScopeId) -> TyperResult<TypedExpr> {
+        let Some(loop_scope_id) = self.scopes.find_loop_scope(scope_id) else {
+            return failf!(brk.span, "break can only be used inside a loop");
+        };
+        if self.scopes.find_defer_scope(scope_id, Some(loop_scope_id)).is_some() {
+            return failf!(brk.span, "Cannot break out of a defer");
+        }
+        let value = match brk.value {
+            None => None,
+            Some(value) => {
+                let Some(has_break_values) = self.yield_loops.get_mut(&loop_scope_id) else {
+                    return failf!(
+                        brk.span,
+                        "Only a for ... yield loop can be broken out of with a value"
+                    );
+                };
+                *has_break_values = true;
+                let value = self.eval_expr(value, scope_id, None)?;
+                Some(Box::new(value))
+            }
+        };
+        Ok(TypedExpr::Break(TypedBreak { value, loop_scope_id, span: brk.span }))
+    }
+
+    /// `break value` in a `for ... yield` yields its value as the last element. We only learn
+    /// the element type once the whole body has been checked, so these breaks are rewritten into
+    /// a push followed by a plain break afterwards
+    fn lower_yield_breaks_in_block(
+        &mut self,
+        block: &mut TypedBlock,
+        lowering: &YieldBreakLowering,
+    ) -> TyperResult<()> {
+        for stmt in block.statements.iter_mut() {
+            match stmt {
+                TypedStmt::Expr(expr) => self.lower_yield_breaks(expr, lowering)?,
+                TypedStmt::ValDef(val_def) => {
+                    self.lower_yield_breaks(&mut val_def.initializer, lowering)?
+                }
+                TypedStmt::Assignment(assignment) => {
+                    self.lower_yield_breaks(&mut assignment.destination, lowering)?;
+                    self.lower_yield_breaks(&mut assignment.value, lowering)?;
+                }
+                TypedStmt::WhileLoop(while_loop) => {
+                    self.lower_yield_breaks(&mut while_loop.cond, lowering)?;
+                    self.lower_yield_breaks_in_block(&mut while_loop.block, lowering)?;
+                }
+                TypedStmt::Defer(defer) => self.lower_yield_breaks(&mut defer.expr, lowering)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn lower_yield_breaks(
+        &mut self,
+        expr: &mut TypedExpr,
+        lowering: &YieldBreakLowering,
+    ) -> TyperResult<()> {
+        match expr {
+            TypedExpr::Break(brk) if brk.loop_scope_id == lowering.loop_scope_id => {
+                let Some(value) = brk.value.take() else {
+                    return Ok(());
+                };
+                let span = brk.span;
+                if let Err(msg) = self.check_types(
+                    lowering.element_type,
+                    value.get_type(),
+                    lowering.loop_scope_id,
+                ) {
+                    return failf!(span, "break value does not match the yielded type: {}", msg);
+                }
+                let push_call = self.synth_function_call(
+                    qident!(self, span, ["Array"], "push"),
+                    span,
+                    lowering.loop_scope_id,
+                    Some((
+                        vec![lowering.element_type],
+                        vec![lowering.yielded_coll.clone(), *value],
+                    )),
+                )?;
+                let plain_break = TypedExpr::Break(TypedBreak {
+                    value: None,
+                    loop_scope_id: lowering.loop_scope_id,
+                    span,
+                });
+                let mut block = TypedBlock {
+                    expr_type: UNIT_TYPE_ID,
+                    scope_id: lowering.loop_scope_id,
+                    statements: Vec::with_capacity(2),
+                    span,
+                };
+                block.push_expr(push_call);
+                block.push_expr(plain_break);
+                *expr = TypedExpr::Block(block);
+                Ok(())
+            }
+            TypedExpr::Break(brk) => match &mut brk.value {
+                None => Ok(()),
+                Some(value) => self.lower_yield_breaks(value, lowering),
+            },
+            TypedExpr::Struct(struc) => {
+                for field in struc.fields.iter_mut() {
+                    self.lower_yield_breaks(&mut field.expr, lowering)?;
+                }
+                Ok(())
+            }
+            TypedExpr::StructFieldAccess(field_access) => {
+                self.lower_yield_breaks(&mut field_access.base, lowering)
+            }
+            TypedExpr::BinaryOp(binary_op) => {
+                self.lower_yield_breaks(&mut binary_op.lhs, lowering)?;
+                self.lower_yield_breaks(&mut binary_op.rhs, lowering)
+            }
+            TypedExpr::UnaryOp(unary_op) => self.lower_yield_breaks(&mut unary_op.expr, lowering),
+            TypedExpr::Block(block) => self.lower_yield_breaks_in_block(block, lowering),
+            TypedExpr::FunctionCall(call) => {
+                for arg in call.args.iter_mut() {
+                    self.lower_yield_breaks(arg, lowering)?;
+                }
+                Ok(())
+            }
+            TypedExpr::If(if_expr) => {
+                self.lower_yield_breaks(&mut if_expr.condition, lowering)?;
+                self.lower_yield_breaks(&mut if_expr.consequent, lowering)?;
+                self.lower_yield_breaks(&mut if_expr.alternate, lowering)
+            }
+            TypedExpr::EnumConstructor(enum_constructor) => match &mut enum_constructor.payload {
+                None => Ok(()),
+                Some(payload) => self.lower_yield_breaks(payload, lowering),
+            },
+            TypedExpr::EnumIsVariant(is_variant) => {
+                self.lower_yield_breaks(&mut is_variant.target_expr, lowering)
+            }
+            TypedExpr::EnumGetPayload(get_payload) => {
+                self.lower_yield_breaks(&mut get_payload.target_expr, lowering)
+            }
+            TypedExpr::Cast(cast) => self.lower_yield_breaks(&mut cast.base_expr, lowering),
+            TypedExpr::Return(ret) => self.lower_yield_breaks(&mut ret.value, lowering),
+            TypedExpr::Unit(_)
+            | TypedExpr::Char(_, _)
+            | TypedExpr::Bool(_, _)
+            | TypedExpr::Integer(_)
+            | TypedExpr::Float(_)
+            | TypedExpr::Str(_, _)
+            | TypedExpr::Variable(_)
+            | TypedExpr::FunctionReference(_)
+            | TypedExpr::Continue(_)
+            | TypedExpr::Error(_) => Ok(()),
+        }
+    }
+
     fn eval_for_expr(
         &mut self,
         for_expr: &ForExpr,
@@ -3965,19 +5498,53 @@ impl TypedModule {
         let iterable_expr = self.eval_expr(for_expr.iterable_expr, scope_id, None)?;
         let iteree_type = iterable_expr.get_type();
         let is_string_iteree = iteree_type == STRING_TYPE_ID;
+        let range_iteree = self.types.get(iteree_type).as_range_instance();
         let iterable_span = iterable_expr.get_span();
         let body_span = for_expr.body_block.span;
 
-        let Some(item_type) =
-            self.types.item_type_of_iterable(&self.ast.identifiers, &self.scopes, iteree_type)
-        else {
-            return make_fail_span(
-                format!(
-                    "Type {} is not iterable",
-                    self.type_id_to_string(iterable_expr.get_type())
-                ),
-                iterable_span,
-            );
+        // Arrays, strings, and ranges are looped over directly. Anything else is driven by its
+        // Iterator implementation, after asking for its iterator if it's Iterable
+        let direct_item_type = match &range_iteree {
+            Some(range_type) => Some(range_type.element_type),
+            None => {
+                self.types.item_type_of_iterable(&self.ast.identifiers, &self.scopes, iteree_type)
+            }
+        };
+        let (item_type, iteree_expr, next_function_id) = match direct_item_type {
+            Some(item_type) => (item_type, iterable_expr, None),
+            None => {
+                let iterator_expr = match self.find_iteration_function(
+                    ITERABLE_ABILITY_ID,
+                    iteree_type,
+                    iterable_span,
+                )? {
+                    None => iterable_expr,
+                    Some(iterator_function_id) => TypedExpr::FunctionCall(Call {
+                        callee: Callee::StaticFunction(iterator_function_id),
+                        args: vec![iterable_expr],
+                        type_args: Vec::new(),
+                        ret_type: self.get_function(iterator_function_id).ret_type,
+                        span: iterable_span,
+                    }),
+                };
+                let Some(next_function_id) = self.find_iteration_function(
+                    ITERATOR_ABILITY_ID,
+                    iterator_expr.get_type(),
+                    iterable_span,
+                )?
+                else {
+                    return make_fail_span(
+                        format!(
+                            "Type {} is not iterable; it must implement Iterable or Iterator",
+                            self.type_id_to_string(iteree_type)
+                        ),
+                        iterable_span,
+                    );
+                };
+                let next_ret_type = self.get_function(next_function_id).ret_type;
+                let item_type = self.types.get(next_ret_type).as_optional().unwrap().inner_type;
+                (item_type, iterator_expr, Some(next_function_id))
+            }
         };
 
         let is_do_block = for_expr.expr_type == ForExprType::Do;
@@ -3987,51 +5554,120 @@ impl TypedModule {
 
         let for_expr_scope = self.scopes.add_child_scope(scope_id, ScopeType::ForExpr, None, None);
 
-        let index_variable = self.synth_variable_defn(
-            get_ident!(self, "it_index"),
-            TypedExpr::Integer(TypedIntegerExpr {
-                value: TypedIntegerValue::U64(0),
-                span: for_expr.body_block.span,
-            }),
-            true,
-            true,
-            for_expr_scope,
-        );
+        // The index is advanced at the top of each iteration, so that `continue` doesn't skip it.
+        // A range loop is a plain counted loop, and has no index
+        let next_index_variable = if range_iteree.is_some() {
+            None
+        } else {
+            Some(self.synth_variable_defn(
+                get_ident!(self, "next_index"),
+                TypedExpr::Integer(TypedIntegerExpr {
+                    value: TypedIntegerValue::U64(0),
+                    span: for_expr.body_block.span,
+                }),
+                false,
+                true,
+                for_expr_scope,
+            ))
+        };
         let iteree_variable = self.synth_variable_defn(
             get_ident!(self, "iteree"),
-            iterable_expr,
+            iteree_expr,
             false,
             false,
             for_expr_scope,
         );
-        let iteree_length_call = if is_string_iteree {
-            TypedExpr::StructFieldAccess(FieldAccess {
-                base: Box::new(iteree_variable.variable_expr.clone()),
-                target_field: get_ident!(self, "len"),
-                target_field_index: 0,
-                ty: U64_TYPE_ID,
-                span: body_span,
-            })
-        } else {
-            TypedExpr::StructFieldAccess(FieldAccess {
-                base: Box::new(iteree_variable.variable_expr.clone()),
-                target_field: get_ident!(self, "len"),
-                target_field_index: 0,
-                ty: U64_TYPE_ID,
-                span: body_span,
-            })
+        // An iterator's next item is only pulled by the loop condition, right before the
+        // iteration that uses it, so that breaking out leaves the rest in the iterator
+        let cursor = match (next_function_id, range_iteree.is_some()) {
+            (Some(next_function_id), _) => {
+                let no_item = self.synth_optional_none(
+                    item_type,
+                    for_expr.iterable_expr.into(),
+                    iterable_span,
+                );
+                let next_item = self.synth_variable_defn(
+                    get_ident!(self, "next_item"),
+                    no_item,
+                    false,
+                    true,
+                    for_expr_scope,
+                );
+                ForLoopCursor::Iterator { next_function_id, next_item }
+            }
+            (None, true) => {
+                let range_start = self.synth_range_field(&iteree_variable.variable_expr, 0);
+                let next_value = self.synth_variable_defn(
+                    get_ident!(self, "next_value"),
+                    range_start,
+                    false,
+                    true,
+                    for_expr_scope,
+                );
+                // start < end or (inclusive and start == end)
+                let compare_to_end = |kind| {
+                    TypedExpr::BinaryOp(BinaryOp {
+                        kind,
+                        ty: BOOL_TYPE_ID,
+                        lhs: Box::new(next_value.variable_expr.clone()),
+                        rhs: Box::new(self.synth_range_field(&iteree_variable.variable_expr, 1)),
+                        span: iterable_span,
+                    })
+                };
+                let at_inclusive_end = TypedExpr::BinaryOp(BinaryOp {
+                    kind: BinaryOpKind::And,
+                    ty: BOOL_TYPE_ID,
+                    lhs: Box::new(self.synth_range_field(&iteree_variable.variable_expr, 3)),
+                    rhs: Box::new(compare_to_end(BinaryOpKind::Equals)),
+                    span: iterable_span,
+                });
+                let is_non_empty = TypedExpr::BinaryOp(BinaryOp {
+                    kind: BinaryOpKind::Or,
+                    ty: BOOL_TYPE_ID,
+                    lhs: Box::new(compare_to_end(BinaryOpKind::Less)),
+                    rhs: Box::new(at_inclusive_end),
+                    span: iterable_span,
+                });
+                let has_next = self.synth_variable_defn(
+                    get_ident!(self, "has_next"),
+                    is_non_empty,
+                    false,
+                    true,
+                    for_expr_scope,
+                );
+                ForLoopCursor::Range { next_value, has_next }
+            }
+            (None, false) => {
+                let iteree_length_call = TypedExpr::StructFieldAccess(FieldAccess {
+                    base: Box::new(iteree_variable.variable_expr.clone()),
+                    target_field: get_ident!(self, "len"),
+                    target_field_index: 0,
+                    ty: U64_TYPE_ID,
+                    span: body_span,
+                });
+                let iteree_length_ident = get_ident!(self, "iteree_length");
+                let length = self.synth_variable_defn(
+                    iteree_length_ident,
+                    iteree_length_call,
+                    false,
+                    false,
+                    for_expr_scope,
+                );
+                ForLoopCursor::Index { length }
+            }
         };
-        let iteree_length_ident = get_ident!(self, "iteree_length");
-        let iteree_length_variable = self.synth_variable_defn(
-            iteree_length_ident,
-            iteree_length_call,
-            false,
-            false,
-            for_expr_scope,
-        );
 
         let while_scope_id =
             self.scopes.add_child_scope(for_expr_scope, ScopeType::WhileBody, None, None);
+        let index_variable = next_index_variable.as_ref().map(|next_index_variable| {
+            self.synth_variable_defn(
+                get_ident!(self, "it_index"),
+                next_index_variable.variable_expr.clone(),
+                true,
+                false,
+                while_scope_id,
+            )
+        });
         let binding_variable_id = self.variables.add_variable(Variable {
             name: binding_ident,
             type_id: item_type,
@@ -4039,44 +5675,56 @@ impl TypedModule {
             owner_scope: while_scope_id,
         });
         self.scopes.add_variable(while_scope_id, binding_ident, binding_variable_id);
+        let element_initializer = if let ForLoopCursor::Iterator { next_item, .. } = &cursor {
+            let some_variant = self.optional_some_variant(next_item.variable_expr.get_type());
+            TypedExpr::EnumGetPayload(GetEnumPayload {
+                target_expr: Box::new(next_item.variable_expr.clone()),
+                payload_type_id: item_type,
+                variant_name: some_variant.name,
+                variant_index: some_variant.index,
+                span: body_span,
+            })
+        } else if let ForLoopCursor::Range { next_value, .. } = &cursor {
+            next_value.variable_expr.clone()
+        } else if is_string_iteree {
+            self.synth_function_call(
+                NamespacedIdentifier {
+                    namespaces: vec![get_ident!(self, "string")],
+                    name: get_ident!(self, "get"),
+                    span: body_span,
+                },
+                body_span,
+                while_scope_id,
+                Some((
+                    vec![],
+                    vec![
+                        iteree_variable.variable_expr.clone(),
+                        index_variable.as_ref().unwrap().variable_expr.clone(),
+                    ],
+                )),
+            )?
+        } else {
+            self.synth_function_call(
+                NamespacedIdentifier {
+                    namespaces: vec![get_ident!(self, "Array")],
+                    name: get_ident!(self, "get"),
+                    span: body_span,
+                },
+                body_span,
+                while_scope_id,
+                Some((
+                    vec![item_type],
+                    vec![
+                        iteree_variable.variable_expr.clone(),
+                        index_variable.as_ref().unwrap().variable_expr.clone(),
+                    ],
+                )),
+            )?
+        };
         let iteration_element_val_def = TypedStmt::ValDef(Box::new(ValDef {
             variable_id: binding_variable_id,
             ty: item_type,
-            initializer: if is_string_iteree {
-                self.synth_function_call(
-                    NamespacedIdentifier {
-                        namespaces: vec![get_ident!(self, "string")],
-                        name: get_ident!(self, "get"),
-                        span: body_span,
-                    },
-                    body_span,
-                    while_scope_id,
-                    Some((
-                        vec![],
-                        vec![
-                            iteree_variable.variable_expr.clone(),
-                            index_variable.variable_expr.clone(),
-                        ],
-                    )),
-                )?
-            } else {
-                self.synth_function_call(
-                    NamespacedIdentifier {
-                        namespaces: vec![get_ident!(self, "Array")],
-                        name: get_ident!(self, "get"),
-                        span: body_span,
-                    },
-                    body_span,
-                    while_scope_id,
-                    Some((
-                        vec![item_type],
-                        vec![
-                            iteree_variable.variable_expr.clone(),
-                            index_variable.variable_expr.clone(),
-                        ],
-                    )),
-                )?
-            },
+            initializer: element_initializer,
             span: body_span,
         }));
 
@@ -4087,7 +5735,10 @@ impl TypedModule {
                 None => None,
                 Some(array_type) => Some(array_type.element_type),
             };
-        let body_block =
+        if !is_do_block {
+            self.yield_loops.insert(while_scope_id, false);
+        }
+        let mut body_block =
             self.eval_block(&for_expr.body_block, body_scope_id, expected_block_type)?;
         let body_block_result_type = body_block.expr_type;
 
@@ -4101,14 +5752,19 @@ impl TypedModule {
             )
         };
         let yielded_coll_variable = if !is_do_block {
+            // An iterator's length isn't known up front, so its array starts empty
+            let initial_capacity = match &cursor {
+                ForLoopCursor::Index { length } => length.variable_expr.clone(),
+                _ => TypedExpr::Integer(TypedIntegerExpr {
+                    value: TypedIntegerValue::U64(0),
+                    span: body_span,
+                }),
+            };
             let synth_function_call = self.synth_function_call(
                 qident!(self, body_span, ["Array"], "new"),
                 body_span,
                 for_expr_scope,
-                Some((
-                    vec![body_block_result_type],
-                    vec![iteree_length_variable.variable_expr.clone()],
-                )),
+                Some((vec![body_block_result_type], vec![initial_capacity])),
             );
             let yield_initializer = self.synth_reference(synth_function_call?);
             Some(self.synth_variable_defn(
@@ -4121,110 +5777,406 @@ impl TypedModule {
         } else {
             None
         };
+        let has_break_values = self.yield_loops.remove(&while_scope_id).unwrap_or(false);
+        if let (true, Some(yielded_coll_variable)) = (has_break_values, &yielded_coll_variable) {
+            let lowering = YieldBreakLowering {
+                loop_scope_id: while_scope_id,
+                element_type: body_block_result_type,
+                yielded_coll: yielded_coll_variable.variable_expr.clone(),
+            };
+            self.lower_yield_breaks_in_block(&mut body_block, &lowering)?;
+        }
         let mut while_block = TypedBlock {
             expr_type: UNIT_TYPE_ID,
             scope_id: while_scope_id,
             statements: Vec::new(),
             span: body_span,
         };
-        // Prepend the val def to the body block
-        while_block.statements.push(iteration_element_val_def);
-        let user_block_variable = self.synth_variable_defn(
-            get_ident!(self, "block_expr_val"),
-            TypedExpr::Block(body_block),
-            false,
-            false,
-            while_scope_id,
-        );
-        while_block.statements.push(user_block_variable.defn_stmt);
-
-        // Assign element to yielded array
-        if let Some(yielded_coll_variable) = &yielded_coll_variable {
-            let element_assign = TypedStmt::Expr(Box::new(self.synth_function_call(
-                qident!(self, body_span, ["Array"], "push"),
-                body_span,
-                for_expr_scope,
-                Some((
-                    vec![body_block_result_type],
-                    vec![
-                        yielded_coll_variable.variable_expr.clone(),
-                        user_block_variable.variable_expr,
-                    ],
-                )),
-            )?));
-            while_block.statements.push(element_assign);
-        }
-
-        // Append the index increment to the body block
-        let index_increment_statement = TypedStmt::Assignment(Box::new(Assignment {
-            destination: Box::new(index_variable.variable_expr.clone()),
-            value: Box::new(TypedExpr::BinaryOp(BinaryOp {
-                kind: BinaryOpKind::Add,
-                ty: U64_TYPE_ID,
-                lhs: Box::new(index_variable.variable_expr.clone()),
-                rhs: Box::new(TypedExpr::Integer(TypedIntegerExpr {
-                    value: TypedIntegerValue::U64(1),
+        // Prepend the index, its increment, and the element's val def to the body block
+        if let (Some(index_variable), Some(next_index_variable)) =
+            (index_variable, &next_index_variable)
+        {
+            while_block.statements.push(index_variable.defn_stmt);
+            let index_increment_statement = TypedStmt::Assignment(Box::new(Assignment {
+                destination: Box::new(next_index_variable.variable_expr.clone()),
+                value: Box::new(TypedExpr::BinaryOp(BinaryOp {
+                    kind: BinaryOpKind::Add,
+                    ty: U64_TYPE_ID,
+                    lhs: Box::new(next_index_variable.variable_expr.clone()),
+                    rhs: Box::new(TypedExpr::Integer(TypedIntegerExpr {
+                        value: TypedIntegerValue::U64(1),
+                        span: iterable_span,
+                    })),
                     span: iterable_span,
                 })),
                 span: iterable_span,
-            })),
-            span: iterable_span,
+            }));
+            while_block.statements.push(index_increment_statement);
+        }
+        while_block.statements.push(iteration_element_val_def);
+        if let ForLoopCursor::Range { next_value, has_next } = &cursor {
+            let range_advance =
+                self.synth_range_advance(next_value, has_next, &iteree_variable, while_scope_id);
+            while_block.statements.extend(range_advance);
+        }
+        if body_block_result_type == NEVER_TYPE_ID {
+            // Every iteration ends in a break, continue, or return, so there's no element
+            while_block.push_expr(TypedExpr::Block(body_block));
+        } else {
+            let user_block_variable = self.synth_variable_defn(
+                get_ident!(self, "block_expr_val"),
+                TypedExpr::Block(body_block),
+                false,
+                false,
+                while_scope_id,
+            );
+            while_block.statements.push(user_block_variable.defn_stmt);
+
+            // Assign element to yielded array
+            if let Some(yielded_coll_variable) = &yielded_coll_variable {
+                let element_assign = TypedStmt::Expr(Box::new(self.synth_function_call(
+                    qident!(self, body_span, ["Array"], "push"),
+                    body_span,
+                    for_expr_scope,
+                    Some((
+                        vec![body_block_result_type],
+                        vec![
+                            yielded_coll_variable.variable_expr.clone(),
+                            user_block_variable.variable_expr,
+                        ],
+                    )),
+                )?));
+                while_block.statements.push(element_assign);
+            }
+        }
+
+        let while_cond = match &cursor {
+            ForLoopCursor::Index { length } => TypedExpr::BinaryOp(BinaryOp {
+                kind: BinaryOpKind::Less,
+                ty: BOOL_TYPE_ID,
+                lhs: Box::new(next_index_variable.as_ref().unwrap().variable_expr.clone()),
+                rhs: Box::new(length.variable_expr.clone()),
+                span: iterable_span,
+            }),
+            ForLoopCursor::Range { has_next, .. } => has_next.variable_expr.clone(),
+            ForLoopCursor::Iterator { next_function_id, next_item } => {
+                // { next_item = next(iteree); next_item is .Some }
+                let next_call =
+                    self.synth_next_call(*next_function_id, &iteree_variable.variable_expr);
+                let advance = TypedStmt::Assignment(Box::new(Assignment {
+                    destination: Box::new(next_item.variable_expr.clone()),
+                    value: Box::new(next_call),
+                    span: iterable_span,
+                }));
+                let some_variant = self.optional_some_variant(next_item.variable_expr.get_type());
+                let mut cond_block = self.synth_block(vec![advance], for_expr_scope, iterable_span);
+                cond_block.push_expr(TypedExpr::EnumIsVariant(TypedEnumIsVariantExpr {
+                    target_expr: Box::new(next_item.variable_expr.clone()),
+                    variant_name: some_variant.name,
+                    variant_index: some_variant.index,
+                    span: iterable_span,
+                }));
+                TypedExpr::Block(cond_block)
+            }
+        };
+        let while_stmt = TypedStmt::WhileLoop(Box::new(TypedWhileLoop {
+            cond: while_cond,
+            block: while_block,
+            span: for_expr.span,
         }));
-        while_block.statements.push(index_increment_statement);
 
-        let while_stmt = TypedStmt::WhileLoop(Box::new(TypedWhileLoop {
-            cond: TypedExpr::BinaryOp(BinaryOp {
-                kind: BinaryOpKind::Less,
-                ty: BOOL_TYPE_ID,
-                lhs: Box::new(index_variable.variable_expr.clone()),
-                rhs: Box::new(iteree_length_variable.variable_expr),
-                span: iterable_span,
-            }),
-            block: while_block,
-            span: for_expr.span,
-        }));
+        let mut for_expr_initial_statements = Vec::with_capacity(5);
+        if let Some(next_index_variable) = next_index_variable {
+            for_expr_initial_statements.push(next_index_variable.defn_stmt);
+        }
+        let range_step_check = match &cursor {
+            ForLoopCursor::Range { .. } => Some(self.synth_range_step_check(
+                &iteree_variable,
+                for_expr_scope,
+                iterable_span,
+            )?),
+            _ => None,
+        };
+        for_expr_initial_statements.push(iteree_variable.defn_stmt);
+        for_expr_initial_statements.extend(range_step_check);
+        match cursor {
+            ForLoopCursor::Index { length } => for_expr_initial_statements.push(length.defn_stmt),
+            ForLoopCursor::Range { next_value, has_next } => {
+                for_expr_initial_statements.push(next_value.defn_stmt);
+                for_expr_initial_statements.push(has_next.defn_stmt);
+            }
+            ForLoopCursor::Iterator { next_item, .. } => {
+                for_expr_initial_statements.push(next_item.defn_stmt)
+            }
+        }
+        if let Some(yielded_coll_variable) = &yielded_coll_variable {
+            for_expr_initial_statements.push(yielded_coll_variable.defn_stmt.clone());
+        }
+        let mut for_expr_block = TypedBlock {
+            expr_type: resulting_type,
+            scope_id: for_expr_scope,
+            statements: for_expr_initial_statements,
+            span: for_expr.body_block.span,
+        };
+
+        for_expr_block.statements.push(while_stmt);
+        if let Some(yielded_coll_variable) = yielded_coll_variable {
+            let yield_expr = self.synth_dereference(yielded_coll_variable.variable_expr);
+            for_expr_block.push_expr(yield_expr);
+        }
+
+        let final_expr = TypedExpr::Block(for_expr_block);
+        Ok(final_expr)
+    }
+
+    /// Finds the single function of the Iterable or Iterator implementation for `type_id`,
+    /// specializing a blanket implementation if that's what applies
+    fn find_iteration_function(
+        &mut self,
+        ability_id: AbilityId,
+        type_id: TypeId,
+        span: SpanId,
+    ) -> TyperResult<Option<FunctionId>> {
+        let function_name = self.get_ability(ability_id).functions[0].function_name;
+        let Some(function_id) = self.find_ability_implementation(
+            function_name,
+            type_id,
+            Some(ability_id),
+            None,
+            None,
+            span,
+        )?
+        else {
+            return Ok(None);
+        };
+        match self.find_blanket_ability_impl(ability_id, &[], type_id) {
+            Some((imp, solved_params)) if imp.function_at_index(0) == function_id => {
+                let intrinsic_type = self.get_function(function_id).intrinsic_type;
+                let specialized_function_id =
+                    self.get_specialized_function(&solved_params, function_id, intrinsic_type)?;
+                Ok(Some(specialized_function_id))
+            }
+            _ => Ok(Some(function_id)),
+        }
+    }
+
+    fn find_ability_impl(
+        &self,
+        ability_id: AbilityId,
+        type_args: &[TypeId],
+        type_id: TypeId,
+    ) -> Option<&TypedAbilityImpl> {
+        self.ability_impls.iter().find(|imple| {
+            imple.ability_id == ability_id
+                && imple.type_id == type_id
+                && imple.type_args.as_slice() == type_args
+        })
+    }
+
+    /// Structural unification for matching ability implementations against each other or
+    /// against a concrete type. Only the type variables in `vars` can be bound
+    fn unify_impl_types(
+        &self,
+        a: TypeId,
+        b: TypeId,
+        vars: &[TypeId],
+        bindings: &mut Vec<(TypeId, TypeId)>,
+    ) -> bool {
+        let resolve = |mut type_id: TypeId, bindings: &Vec<(TypeId, TypeId)>| {
+            while let Some((_, bound)) = bindings.iter().find(|(var, _)| *var == type_id) {
+                type_id = *bound;
+            }
+            type_id
+        };
+        let a = resolve(a, bindings);
+        let b = resolve(b, bindings);
+        if a == b {
+            return true;
+        }
+        if vars.contains(&a) {
+            bindings.push((a, b));
+            return true;
+        }
+        if vars.contains(&b) {
+            bindings.push((b, a));
+            return true;
+        }
+        if let (Some(a_info), Some(b_info)) =
+            (self.types.get_generic_instance_info(a), self.types.get_generic_instance_info(b))
+        {
+            return a_info.generic_parent == b_info.generic_parent
+                && a_info.param_values.iter().zip(b_info.param_values.iter()).all(
+                    |(a_param, b_param)| self.unify_impl_types(*a_param, *b_param, vars, bindings),
+                );
+        }
+        match (self.types.get(a), self.types.get(b)) {
+            (Type::Reference(a_ref), Type::Reference(b_ref)) => {
+                self.unify_impl_types(a_ref.inner_type, b_ref.inner_type, vars, bindings)
+            }
+            _ => false,
+        }
+    }
+
+    /// If the blanket implementation `imp` applies to `type_id`, returns the solutions for
+    /// its type params. Empty `type_args` matches any type arguments
+    fn blanket_impl_applies(
+        &self,
+        imp: &TypedAbilityImpl,
+        type_args: &[TypeId],
+        type_id: TypeId,
+    ) -> Option<Vec<TypeParam>> {
+        self.blanket_impl_applies_inner(imp, type_args, type_id, &mut Vec::new())
+    }
 
-        let mut for_expr_initial_statements = Vec::with_capacity(4);
-        for_expr_initial_statements.push(index_variable.defn_stmt);
-        for_expr_initial_statements.push(iteree_variable.defn_stmt);
-        for_expr_initial_statements.push(iteree_length_variable.defn_stmt);
-        if let Some(yielded_coll_variable) = &yielded_coll_variable {
-            for_expr_initial_statements.push(yielded_coll_variable.defn_stmt.clone());
+    /// `in_progress` holds the (ability, type) pairs whose implementations are being looked for
+    /// further up, since a blanket implementation's constraints can lead back to the ability
+    /// it implements, as in `impl[T] Foo for T where T: Foo`
+    fn blanket_impl_applies_inner(
+        &self,
+        imp: &TypedAbilityImpl,
+        type_args: &[TypeId],
+        type_id: TypeId,
+        in_progress: &mut Vec<(AbilityId, TypeId)>,
+    ) -> Option<Vec<TypeParam>> {
+        let vars: Vec<TypeId> = imp.type_params.iter().map(|tp| tp.type_param.type_id).collect();
+        let mut bindings = Vec::new();
+        if !self.unify_impl_types(imp.type_id, type_id, &vars, &mut bindings) {
+            return None;
         }
-        let mut for_expr_block = TypedBlock {
-            expr_type: resulting_type,
-            scope_id: for_expr_scope,
-            statements: for_expr_initial_statements,
-            span: for_expr.body_block.span,
-        };
+        for (impl_type_arg, type_arg) in imp.type_args.iter().zip(type_args.iter()) {
+            if !self.unify_impl_types(*impl_type_arg, *type_arg, &vars, &mut bindings) {
+                return None;
+            }
+        }
+        let mut solved = Vec::with_capacity(imp.type_params.len());
+        for type_param in imp.type_params.iter() {
+            let (_, solution) =
+                bindings.iter().find(|(var, _)| *var == type_param.type_param.type_id)?;
+            let satisfies_constraints = type_param.ability_constraints.iter().all(|ability_id| {
+                self.type_implements_ability_inner(*solution, *ability_id, in_progress)
+            });
+            if !satisfies_constraints {
+                return None;
+            }
+            solved.push(TypeParam { ident: type_param.type_param.ident, type_id: *solution });
+        }
+        Some(solved)
+    }
 
-        for_expr_block.statements.push(while_stmt);
-        if let Some(yielded_coll_variable) = yielded_coll_variable {
-            let yield_expr = self.synth_dereference(yielded_coll_variable.variable_expr);
-            for_expr_block.push_expr(yield_expr);
+    fn find_blanket_ability_impl(
+        &self,
+        ability_id: AbilityId,
+        type_args: &[TypeId],
+        type_id: TypeId,
+    ) -> Option<(&TypedAbilityImpl, Vec<TypeParam>)> {
+        self.ability_impls
+            .iter()
+            .filter(|imp| imp.ability_id == ability_id && !imp.type_params.is_empty())
+            .find_map(|imp| self.blanket_impl_applies(imp, type_args, type_id).map(|s| (imp, s)))
+    }
+
+    fn type_implements_ability(&self, type_id: TypeId, ability_id: AbilityId) -> bool {
+        self.type_implements_ability_inner(type_id, ability_id, &mut Vec::new())
+    }
+
+    fn type_implements_ability_inner(
+        &self,
+        type_id: TypeId,
+        ability_id: AbilityId,
+        in_progress: &mut Vec<(AbilityId, TypeId)>,
+    ) -> bool {
+        if let Type::TypeVariable(tv) = self.types.get(type_id) {
+            if tv.ability_impls.contains(&ability_id) {
+                return true;
+            }
+        }
+        if self
+            .ability_impls
+            .iter()
+            .any(|imp| imp.ability_id == ability_id && imp.type_id == type_id)
+        {
+            return true;
         }
+        // Already being looked for further up, so a blanket implementation can't be what
+        // provides it: that would be circular
+        if in_progress.contains(&(ability_id, type_id)) {
+            return false;
+        }
+        in_progress.push((ability_id, type_id));
+        let blanket_applies = self.ability_impls.iter().any(|imp| {
+            imp.ability_id == ability_id
+                && !imp.type_params.is_empty()
+                && self.blanket_impl_applies_inner(imp, &[], type_id, in_progress).is_some()
+        });
+        in_progress.pop();
+        blanket_applies || self.type_derives_ability(type_id, ability_id)
+    }
 
-        let final_expr = TypedExpr::Block(for_expr_block);
-        Ok(final_expr)
+    /// Resolves the function at `function_index` of a non-generic ability for `type_id`,
+    /// specializing a blanket implementation if that's what applies
+    fn find_ability_impl_function(
+        &mut self,
+        ability_id: AbilityId,
+        type_id: TypeId,
+        function_index: usize,
+    ) -> TyperResult<Option<FunctionId>> {
+        if let Type::TypeVariable(tv) = self.types.get(type_id) {
+            if tv.ability_impls.contains(&ability_id) {
+                let ability = self.get_ability(ability_id);
+                return Ok(Some(ability.functions[function_index].function_id));
+            }
+        }
+        self.ensure_derived_impl(ability_id, type_id)?;
+        if let Some(imp) = self.find_ability_impl(ability_id, &[], type_id) {
+            return Ok(Some(imp.function_at_index(function_index)));
+        }
+        let Some((imp, solved_params)) = self.find_blanket_ability_impl(ability_id, &[], type_id)
+        else {
+            return Ok(None);
+        };
+        let generic_function_id = imp.function_at_index(function_index);
+        let intrinsic_type = self.get_function(generic_function_id).intrinsic_type;
+        let specialized_function_id =
+            self.get_specialized_function(&solved_params, generic_function_id, intrinsic_type)?;
+        Ok(Some(specialized_function_id))
     }
 
-    fn expect_ability_implementation(
+    fn ability_with_type_args_to_string(
         &self,
         ability_id: AbilityId,
+        type_args: &[TypeId],
+    ) -> String {
+        let mut s = self.get_ident_str(self.get_ability(ability_id).name).to_string();
+        if !type_args.is_empty() {
+            s.push('[');
+            for (index, type_arg) in type_args.iter().enumerate() {
+                if index > 0 {
+                    s.push_str(", ");
+                }
+                s.push_str(&self.type_id_to_string(*type_arg));
+            }
+            s.push(']');
+        }
+        s
+    }
+
+    fn expect_ability_impl_function(
+        &mut self,
+        ability_id: AbilityId,
         type_id: TypeId,
+        function_name: Identifier,
         span_for_error: SpanId,
-    ) -> TyperResult<&TypedAbilityImpl> {
-        self.ability_impls
-            .iter()
-            .find(|imple| imple.type_id == type_id && imple.ability_id == ability_id)
-            .ok_or(make_error(
-                format!(
-                    "Missing ability '{}' implementation for '{}'",
-                    self.ast.identifiers.get_name(self.get_ability(ability_id).name),
-                    self.type_id_to_string(type_id)
-                ),
-                span_for_error,
-            ))
+    ) -> TyperResult<FunctionId> {
+        let ability = self.get_ability(ability_id);
+        let function_index = ability.find_function_by_name(function_name).unwrap().0;
+        self.find_ability_impl_function(ability_id, type_id, function_index)?.ok_or(make_error(
+            format!(
+                "Missing ability '{}' implementation for '{}'",
+                self.ast.identifiers.get_name(self.get_ability(ability_id).name),
+                self.type_id_to_string(type_id)
+            ),
+            span_for_error,
+        ))
     }
 
     fn eval_binary_op(
@@ -4237,7 +6189,7 @@ impl TypedModule {
             match type_id {
                 UNIT_TYPE_ID | CHAR_TYPE_ID | U8_TYPE_ID | U16_TYPE_ID | U32_TYPE_ID
                 | U64_TYPE_ID | I8_TYPE_ID | I16_TYPE_ID | I32_TYPE_ID | I64_TYPE_ID
-                | BOOL_TYPE_ID => true,
+                | BOOL_TYPE_ID | F32_TYPE_ID | F64_TYPE_ID => true,
                 _other => false,
             }
         }
@@ -4258,6 +6210,9 @@ impl TypedModule {
                     return self.eval_equality_expr(lhs, &binary_op, scope_id, expected_type);
                 }
             }
+            BinaryOpKind::Range | BinaryOpKind::RangeInclusive => {
+                return self.eval_range_expr(binary_op, scope_id, expected_type);
+            }
             BinaryOpKind::OptionalElse => {
                 // LHS must be an optional and RHS must be its contained type
                 let lhs = self.eval_expr(binary_op.lhs, scope_id, None)?;
@@ -4319,9 +6274,21 @@ impl TypedModule {
         // FIXME: We could figure out better hinting here so that the following would compile to u64 on the rhs:
         // assert(sizeOf[Text]() == 16 + 32);
         //                          ^^^^^^^
-        let lhs = self.eval_expr(binary_op.lhs, scope_id, None)?;
+        let lhs = self.eval_expr_or_error(binary_op.lhs, scope_id, None);
         let kind = binary_op.op_kind;
         let lhs_type_id = lhs.get_type();
+        if lhs_type_id == ERROR_TYPE_ID {
+            // We can't know what the operator means, but the rhs can still have its own errors
+            self.eval_expr_or_error(binary_op.rhs, scope_id, None);
+            return Ok(TypedExpr::Error(binary_op.span));
+        }
+        let lhs_is_primitive = matches!(
+            self.types.get(lhs_type_id),
+            Type::Integer(_) | Type::Float(_) | Type::Bool(_) | Type::Char(_) | Type::Unit(_)
+        );
+        if !lhs_is_primitive && Self::operator_ability(kind).is_some() {
+            return self.eval_operator_ability_call(lhs, binary_op, scope_id);
+        }
         let result_type = match self.types.get(lhs_type_id) {
             Type::Integer(_integer_type) => match kind {
                 BinaryOpKind::Add => Ok(lhs_type_id),
@@ -4337,7 +6304,29 @@ impl TypedModule {
                 BinaryOpKind::Or => failf!(binary_op.span, "Invalid left-hand side for or"),
                 BinaryOpKind::Equals => Ok(BOOL_TYPE_ID),
                 BinaryOpKind::NotEquals => Ok(BOOL_TYPE_ID),
-                BinaryOpKind::OptionalElse | BinaryOpKind::Pipe => unreachable!(),
+                BinaryOpKind::OptionalElse
+                | BinaryOpKind::Pipe
+                | BinaryOpKind::Range
+                | BinaryOpKind::RangeInclusive => unreachable!(),
+            },
+            Type::Float(_float_type) => match kind {
+                BinaryOpKind::Add => Ok(lhs_type_id),
+                BinaryOpKind::Subtract => Ok(lhs_type_id),
+                BinaryOpKind::Multiply => Ok(lhs_type_id),
+                BinaryOpKind::Divide => Ok(lhs_type_id),
+                BinaryOpKind::Rem => Ok(lhs_type_id),
+                BinaryOpKind::Less => Ok(BOOL_TYPE_ID),
+                BinaryOpKind::LessEqual => Ok(BOOL_TYPE_ID),
+                BinaryOpKind::Greater => Ok(BOOL_TYPE_ID),
+                BinaryOpKind::GreaterEqual => Ok(BOOL_TYPE_ID),
+                BinaryOpKind::And => failf!(binary_op.span, "Invalid left-hand side for and"),
+                BinaryOpKind::Or => failf!(binary_op.span, "Invalid left-hand side for or"),
+                BinaryOpKind::Equals => Ok(BOOL_TYPE_ID),
+                BinaryOpKind::NotEquals => Ok(BOOL_TYPE_ID),
+                BinaryOpKind::OptionalElse
+                | BinaryOpKind::Pipe
+                | BinaryOpKind::Range
+                | BinaryOpKind::RangeInclusive => unreachable!(),
             },
             Type::Bool(_) => match kind {
                 BinaryOpKind::Add
@@ -4355,7 +6344,10 @@ impl TypedModule {
                 BinaryOpKind::Or => Ok(BOOL_TYPE_ID),
                 BinaryOpKind::Equals => Ok(BOOL_TYPE_ID),
                 BinaryOpKind::NotEquals => Ok(BOOL_TYPE_ID),
-                BinaryOpKind::OptionalElse | BinaryOpKind::Pipe => unreachable!(),
+                BinaryOpKind::OptionalElse
+                | BinaryOpKind::Pipe
+                | BinaryOpKind::Range
+                | BinaryOpKind::RangeInclusive => unreachable!(),
             },
             Type::Char(_) => match kind {
                 BinaryOpKind::Add
@@ -4371,7 +6363,10 @@ impl TypedModule {
                 | BinaryOpKind::Or => failf!(binary_op.span, "Invalid operation on char: {}", kind),
                 BinaryOpKind::Equals => Ok(BOOL_TYPE_ID),
                 BinaryOpKind::NotEquals => Ok(BOOL_TYPE_ID),
-                BinaryOpKind::OptionalElse | BinaryOpKind::Pipe => unreachable!(),
+                BinaryOpKind::OptionalElse
+                | BinaryOpKind::Pipe
+                | BinaryOpKind::Range
+                | BinaryOpKind::RangeInclusive => unreachable!(),
             },
             Type::Unit(_) => match kind {
                 BinaryOpKind::Equals => Ok(BOOL_TYPE_ID),
@@ -4386,7 +6381,7 @@ impl TypedModule {
         // At this point I think all operations are symmetric but we'll leave this here
         // to signal that invariant and in case things change
         let expected_rhs_type = if kind.is_symmetric_binop() { Some(lhs.get_type()) } else { None };
-        let rhs = self.eval_expr(binary_op.rhs, scope_id, expected_rhs_type)?;
+        let rhs = self.eval_expr_or_error(binary_op.rhs, scope_id, expected_rhs_type);
 
         if kind.is_symmetric_binop() {
             // We already confirmed that the LHS is valid for this operation, and
@@ -4395,17 +6390,447 @@ impl TypedModule {
                 return failf!(binary_op.span, "operand types did not match: {msg}");
             }
         } else {
-            panic!("Unexpected asymmetric binop down here {}", kind)
+            panic!("Unexpected asymmetric binop down here {}", kind)
+        }
+
+        let expr = TypedExpr::BinaryOp(BinaryOp {
+            kind,
+            ty: result_type,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+            span: binary_op.span,
+        });
+        Ok(expr)
+    }
+
+    /// `start..end` and `start..=end` construct a Range that counts up by one
+    fn eval_range_expr(
+        &mut self,
+        binary_op: &parse::BinaryOp,
+        scope_id: ScopeId,
+        expected_type: Option<TypeId>,
+    ) -> TyperResult<TypedExpr> {
+        let span = binary_op.span;
+        let expected_element_type = expected_type
+            .and_then(|t| self.types.get(t).as_range_instance())
+            .map(|range_type| range_type.element_type);
+        // In `0..n`, the literal start takes its type from the end
+        let start_is_literal = matches!(
+            self.ast.expressions.get(binary_op.lhs),
+            ParsedExpression::Literal(Literal::Numeric(_))
+        );
+        let (start, end) = if expected_element_type.is_none() && start_is_literal {
+            let end = self.eval_expr(binary_op.rhs, scope_id, None)?;
+            let start = self.eval_expr(binary_op.lhs, scope_id, Some(end.get_type()))?;
+            (start, end)
+        } else {
+            let start = self.eval_expr(binary_op.lhs, scope_id, expected_element_type)?;
+            let end = self.eval_expr(binary_op.rhs, scope_id, Some(start.get_type()))?;
+            (start, end)
+        };
+        let element_type = start.get_type();
+        let Type::Integer(integer_type) = self.types.get(element_type) else {
+            return failf!(
+                span,
+                "Range bounds must be integers; got '{}'",
+                self.type_id_to_string(element_type)
+            );
+        };
+        let one = match integer_type {
+            IntegerType::U8 => TypedIntegerValue::U8(1),
+            IntegerType::U16 => TypedIntegerValue::U16(1),
+            IntegerType::U32 => TypedIntegerValue::U32(1),
+            IntegerType::U64 => TypedIntegerValue::U64(1),
+            IntegerType::I8 => TypedIntegerValue::I8(1),
+            IntegerType::I16 => TypedIntegerValue::I16(1),
+            IntegerType::I32 => TypedIntegerValue::I32(1),
+            IntegerType::I64 => TypedIntegerValue::I64(1),
+        };
+        if let Err(msg) = self.check_types(element_type, end.get_type(), scope_id) {
+            return failf!(span, "Range bounds did not match: {msg}");
+        }
+        let range_type_id =
+            self.instantiate_generic_type(RANGE_TYPE_ID, vec![element_type], binary_op.lhs.into());
+        let field_names =
+            ["start", "end", "step", "inclusive"].map(|name| self.ast.identifiers.intern(name));
+        let field_values = [
+            start,
+            end,
+            TypedExpr::Integer(TypedIntegerExpr { value: one, span }),
+            TypedExpr::Bool(binary_op.op_kind == BinaryOpKind::RangeInclusive, span),
+        ];
+        let fields = field_names
+            .into_iter()
+            .zip(field_values)
+            .map(|(name, expr)| StructField { name, expr })
+            .collect();
+        Ok(TypedExpr::Struct(Struct { fields, type_id: range_type_id, span }))
+    }
+
+    /// The return type of the function that a `return` or `?` in `scope_id` returns from. A
+    /// lambda without a return type annotation only gets its return type from its body, so
+    /// an early return inside of it has nothing to check against
+    fn enclosing_return_type(
+        &self,
+        scope_id: ScopeId,
+        what: &str,
+        span: SpanId,
+    ) -> TyperResult<TypeId> {
+        let enclosing_function = self.get_function(self.scopes.nearest_parent_function(scope_id));
+        if let TypedFunctionKind::Lambda { return_type_pending: true } = enclosing_function.kind {
+            return failf!(
+                span,
+                "Cannot use {} in a lambda whose return type is inferred; give the lambda a return type annotation",
+                what
+            );
+        }
+        Ok(enclosing_function.ret_type)
+    }
+
+    /// `base?` unwraps an Ok or Some; on Err or None it returns early from the enclosing
+    /// function, which must return a Result with the same error type, or an optional.
+    /// Lowers to:
+    /// { val try_value = base; if try_value is .Err { return(.Err(<payload>)) } else { <payload> } }
+    fn eval_try(&mut self, try_expr: &ParsedTry, scope_id: ScopeId) -> TyperResult<TypedExpr> {
+        let span = try_expr.span;
+        let base = self.eval_expr(try_expr.base, scope_id, None)?;
+        let base_type_id = base.get_type();
+        let base_result = self.types.get(base_type_id).as_result();
+        let base_is_optional = self.types.get(base_type_id).as_optional().is_some();
+        let (success_name, failure_name) = match &base_result {
+            Some(_) => ("Ok", "Err"),
+            None if base_is_optional => ("Some", "None"),
+            None => {
+                return failf!(
+                    span,
+                    "'?' can only be used on a Result or an optional; type was '{}'",
+                    self.type_id_to_string(base_type_id)
+                );
+            }
+        };
+        if self.scopes.find_defer_scope(scope_id, None).is_some() {
+            return failf!(span, "Cannot use '?' inside a defer, since it may return");
+        }
+        let return_type_id = self.enclosing_return_type(scope_id, "'?'", span)?;
+        match &base_result {
+            Some(base_result) => {
+                let return_err_type =
+                    self.types.get(return_type_id).as_result().map(|r| r.err_type);
+                let compatible = return_err_type.is_some_and(|return_err_type| {
+                    self.check_types(return_err_type, base_result.err_type, scope_id).is_ok()
+                });
+                if !compatible {
+                    return failf!(
+                        span,
+                        "'?' on '{}' requires the enclosing function to return a Result with error type '{}'; it returns '{}'",
+                        self.type_id_to_string(base_type_id),
+                        self.type_id_to_string(base_result.err_type),
+                        self.type_id_to_string(return_type_id)
+                    );
+                }
+            }
+            None => {
+                if self.types.get(return_type_id).as_optional().is_none() {
+                    return failf!(
+                        span,
+                        "'?' on '{}' requires the enclosing function to return an optional; it returns '{}'",
+                        self.type_id_to_string(base_type_id),
+                        self.type_id_to_string(return_type_id)
+                    );
+                }
+            }
+        }
+        let success_name = self.ast.identifiers.intern(success_name);
+        let failure_name = self.ast.identifiers.intern(failure_name);
+        let base_enum = self.types.get(base_type_id).expect_enum();
+        let success_variant = base_enum.variant_by_name(success_name).unwrap().clone();
+        let failure_variant = base_enum.variant_by_name(failure_name).unwrap().clone();
+        let return_failure_variant = self
+            .types
+            .get(return_type_id)
+            .expect_enum()
+            .variant_by_name(failure_name)
+            .unwrap()
+            .clone();
+
+        let mut try_block = self.synth_block(vec![], scope_id, span);
+        let try_value_name = get_ident!(self, "try_value");
+        let try_variable =
+            self.synth_variable_defn(try_value_name, base, false, false, try_block.scope_id);
+        let failure_payload = failure_variant.payload.map(|payload_type_id| {
+            Box::new(TypedExpr::EnumGetPayload(GetEnumPayload {
+                target_expr: Box::new(try_variable.variable_expr.clone()),
+                payload_type_id,
+                variant_name: failure_variant.name,
+                variant_index: failure_variant.index,
+                span,
+            }))
+        });
+        let early_return = TypedExpr::Return(TypedReturn {
+            value: Box::new(TypedExpr::EnumConstructor(TypedEnumConstructor {
+                type_id: return_failure_variant.enum_type_id,
+                variant_name: return_failure_variant.name,
+                variant_index: return_failure_variant.index,
+                payload: failure_payload,
+                span,
+            })),
+            span,
+        });
+        let is_failure = TypedExpr::EnumIsVariant(TypedEnumIsVariantExpr {
+            target_expr: Box::new(try_variable.variable_expr.clone()),
+            variant_name: failure_variant.name,
+            variant_index: failure_variant.index,
+            span,
+        });
+        let success_type = success_variant.payload.unwrap();
+        let success_payload = TypedExpr::EnumGetPayload(GetEnumPayload {
+            target_expr: Box::new(try_variable.variable_expr),
+            payload_type_id: success_type,
+            variant_name: success_variant.name,
+            variant_index: success_variant.index,
+            span,
+        });
+        try_block.push_stmt(try_variable.defn_stmt);
+        try_block.push_expr(TypedExpr::If(Box::new(TypedIf {
+            condition: is_failure,
+            consequent: early_return,
+            alternate: success_payload,
+            ty: success_type,
+            span,
+        })));
+        Ok(TypedExpr::Block(try_block))
+    }
+
+    /// `base?.access` evaluates `access` against the payload of `base` when it has a value.
+    /// An access that is itself optional is not wrapped again, so that chains stay flat.
+    /// Lowers to:
+    /// { val optchain_base = base; if optchain_base is .Some { val <optchain_value> = <payload>; .Some(<access>) } else { .None } }
+    fn eval_optional_chain(
+        &mut self,
+        chain: &ParsedOptionalChain,
+        scope_id: ScopeId,
+    ) -> TyperResult<TypedExpr> {
+        let span = chain.span;
+        let base = self.eval_expr(chain.base, scope_id, None)?;
+        let base_type_id = base.get_type();
+        let Some(base_optional) = self.types.get(base_type_id).as_optional() else {
+            if self.types.get(base_type_id).as_result().is_some() {
+                return failf!(
+                    span,
+                    "'?.' can only be used on an optional, and '{}' is a Result; to propagate its error first, write '(x?).y'",
+                    self.type_id_to_string(base_type_id)
+                );
+            }
+            return failf!(
+                span,
+                "'?.' can only be used on an optional; type was '{}'",
+                self.type_id_to_string(base_type_id)
+            );
+        };
+        let some_variant = self.optional_some_variant(base_type_id);
+
+        let mut chain_block = self.synth_block(vec![], scope_id, span);
+        let optchain_base_name = get_ident!(self, "optchain_base");
+        let base_variable =
+            self.synth_variable_defn(optchain_base_name, base, false, false, chain_block.scope_id);
+
+        let mut some_block = self.synth_block(vec![], chain_block.scope_id, span);
+        let payload = TypedExpr::EnumGetPayload(GetEnumPayload {
+            target_expr: Box::new(base_variable.variable_expr.clone()),
+            payload_type_id: base_optional.inner_type,
+            variant_name: some_variant.name,
+            variant_index: some_variant.index,
+            span,
+        });
+        // Not mangled, since the parsed access refers to it by name
+        let payload_variable =
+            self.synth_variable_defn(chain.binding, payload, true, false, some_block.scope_id);
+        let access = self.eval_expr(chain.access, some_block.scope_id, None)?;
+        let access_value = if self.types.get(access.get_type()).as_optional().is_some() {
+            access
+        } else {
+            self.synth_optional_some(chain.access.into(), access)
+        };
+        let result_type_id = access_value.get_type();
+        let result_inner_type = self.types.get(result_type_id).as_optional().unwrap().inner_type;
+        some_block.push_stmt(payload_variable.defn_stmt);
+        some_block.push_expr(access_value);
+
+        let none_value = self.synth_optional_none(result_inner_type, chain.access.into(), span);
+        let has_value = TypedExpr::EnumIsVariant(TypedEnumIsVariantExpr {
+            target_expr: Box::new(base_variable.variable_expr),
+            variant_name: some_variant.name,
+            variant_index: some_variant.index,
+            span,
+        });
+        chain_block.push_stmt(base_variable.defn_stmt);
+        chain_block.push_expr(TypedExpr::If(Box::new(TypedIf {
+            condition: has_value,
+            consequent: TypedExpr::Block(some_block),
+            alternate: none_value,
+            ty: result_type_id,
+            span,
+        })));
+        Ok(TypedExpr::Block(chain_block))
+    }
+
+    /// Negating a signed integer or a float is built in; any other type must implement Neg
+    fn eval_negation(
+        &mut self,
+        operand: ParsedExpressionId,
+        span: SpanId,
+        scope_id: ScopeId,
+        expected_type: Option<TypeId>,
+    ) -> TyperResult<TypedExpr> {
+        let base_expr = self.eval_expr(operand, scope_id, expected_type)?;
+        let type_id = base_expr.get_type();
+        if matches!(self.types.get(type_id), Type::Integer(_) | Type::Float(_)) {
+            return self.synth_primitive_negation(base_expr, span);
+        }
+        let Ok(function_id) = self.expect_ability_impl_function(
+            NEG_ABILITY_ID,
+            type_id,
+            get_ident!(self, "neg"),
+            span,
+        ) else {
+            return failf!(
+                span,
+                "Operator - is not defined for '{}'; it requires an implementation of ability 'Neg'",
+                self.type_id_to_string(type_id)
+            );
+        };
+        Ok(TypedExpr::FunctionCall(Call {
+            callee: Callee::StaticFunction(function_id),
+            args: vec![base_expr],
+            type_args: Vec::new(),
+            ret_type: type_id,
+            span,
+        }))
+    }
+
+    /// Negating a signed integer lowers to `0 - x`, and a float gets a real negation so that
+    /// `-0.0` keeps its sign
+    fn synth_primitive_negation(
+        &self,
+        base_expr: TypedExpr,
+        span: SpanId,
+    ) -> TyperResult<TypedExpr> {
+        let type_id = base_expr.get_type();
+        match self.types.get(type_id) {
+            Type::Integer(int_type) => {
+                let value = match int_type {
+                    IntegerType::I8 => TypedIntegerValue::I8(0),
+                    IntegerType::I16 => TypedIntegerValue::I16(0),
+                    IntegerType::I32 => TypedIntegerValue::I32(0),
+                    IntegerType::I64 => TypedIntegerValue::I64(0),
+                    unsigned => {
+                        return failf!(span, "Cannot negate unsigned integer type {}", unsigned)
+                    }
+                };
+                let zero = TypedExpr::Integer(TypedIntegerExpr { value, span });
+                Ok(TypedExpr::BinaryOp(BinaryOp {
+                    kind: BinaryOpKind::Subtract,
+                    ty: type_id,
+                    lhs: Box::new(zero),
+                    rhs: Box::new(base_expr),
+                    span,
+                }))
+            }
+            Type::Float(_) => Ok(TypedExpr::UnaryOp(UnaryOp {
+                kind: UnaryOpKind::FloatNegation,
+                type_id,
+                expr: Box::new(base_expr),
+                span,
+            })),
+            _ => unreachable!("Only integers and floats have a built-in negation"),
+        }
+    }
+
+    /// The ability and function that an arithmetic or comparison operator dispatches to
+    /// when its left-hand side is not a primitive
+    fn operator_ability(kind: BinaryOpKind) -> Option<(AbilityId, &'static str)> {
+        match kind {
+            BinaryOpKind::Add => Some((ADD_ABILITY_ID, "add")),
+            BinaryOpKind::Subtract => Some((SUB_ABILITY_ID, "sub")),
+            BinaryOpKind::Multiply => Some((MUL_ABILITY_ID, "mul")),
+            BinaryOpKind::Divide => Some((DIV_ABILITY_ID, "div")),
+            BinaryOpKind::Rem => Some((REM_ABILITY_ID, "rem")),
+            BinaryOpKind::Less
+            | BinaryOpKind::LessEqual
+            | BinaryOpKind::Greater
+            | BinaryOpKind::GreaterEqual => Some((COMPARE_ABILITY_ID, "compare")),
+            _ => None,
+        }
+    }
+
+    /// `a + b` becomes `a.add(b)`; `a < b` becomes `a.compare(b) is .Less`, and
+    /// `a <= b` becomes `not (a.compare(b) is .Greater)`
+    fn eval_operator_ability_call(
+        &mut self,
+        lhs: TypedExpr,
+        binary_op: &parse::BinaryOp,
+        scope_id: ScopeId,
+    ) -> TyperResult<TypedExpr> {
+        let kind = binary_op.op_kind;
+        let span = binary_op.span;
+        let (ability_id, function_name) = Self::operator_ability(kind).unwrap();
+        let lhs_type_id = lhs.get_type();
+        let rhs = self.eval_expr(binary_op.rhs, scope_id, Some(lhs_type_id))?;
+        if let Err(msg) = self.check_types(lhs_type_id, rhs.get_type(), scope_id) {
+            return failf!(span, "operand types did not match: {msg}");
+        }
+        let function_name = self.ast.identifiers.intern(function_name);
+        let Ok(function_id) =
+            self.expect_ability_impl_function(ability_id, lhs_type_id, function_name, span)
+        else {
+            return failf!(
+                span,
+                "Operator {} is not defined for '{}'; it requires an implementation of ability '{}'",
+                kind,
+                self.type_id_to_string(lhs_type_id),
+                self.get_ident_str(self.get_ability(ability_id).name)
+            );
+        };
+        let ret_type = if ability_id == COMPARE_ABILITY_ID {
+            self.get_function(function_id).ret_type
+        } else {
+            lhs_type_id
+        };
+        let call = TypedExpr::FunctionCall(Call {
+            callee: Callee::StaticFunction(function_id),
+            args: vec![lhs, rhs],
+            type_args: Vec::new(),
+            ret_type,
+            span,
+        });
+        if ability_id != COMPARE_ABILITY_ID {
+            return Ok(call);
         }
-
-        let expr = TypedExpr::BinaryOp(BinaryOp {
-            kind,
-            ty: result_type,
-            lhs: Box::new(lhs),
-            rhs: Box::new(rhs),
-            span: binary_op.span,
+        let (variant_name, negated) = match kind {
+            BinaryOpKind::Less => ("Less", false),
+            BinaryOpKind::Greater => ("Greater", false),
+            BinaryOpKind::LessEqual => ("Greater", true),
+            BinaryOpKind::GreaterEqual => ("Less", true),
+            _ => unreachable!(),
+        };
+        let variant_name = self.ast.identifiers.intern(variant_name);
+        let variant = self.types.get(ret_type).expect_enum().variant_by_name(variant_name).unwrap();
+        let is_variant = TypedExpr::EnumIsVariant(TypedEnumIsVariantExpr {
+            target_expr: Box::new(call),
+            variant_name: variant.name,
+            variant_index: variant.index,
+            span,
         });
-        Ok(expr)
+        if negated {
+            Ok(TypedExpr::UnaryOp(UnaryOp {
+                kind: UnaryOpKind::BooleanNegation,
+                type_id: BOOL_TYPE_ID,
+                expr: Box::new(is_variant),
+                span,
+            }))
+        } else {
+            Ok(is_variant)
+        }
     }
 
     fn eval_equality_expr(
@@ -4509,18 +6934,19 @@ impl TypedModule {
     }
 
     fn synth_equals_call(
-        &self,
+        &mut self,
         lhs: TypedExpr,
         rhs: TypedExpr,
         span: SpanId,
     ) -> TyperResult<TypedExpr> {
-        let implementation =
-            self.expect_ability_implementation(EQUALS_ABILITY_ID, lhs.get_type(), span)?;
-        let ability = self.get_ability(EQUALS_ABILITY_ID);
-        let equals_index = ability.find_function_by_name(get_ident!(self, "equals")).unwrap().0;
-        let equals_implementation_function_id = implementation.function_at_index(equals_index);
+        let equals_implementation_function_id = self.expect_ability_impl_function(
+            EQUALS_ABILITY_ID,
+            lhs.get_type(),
+            get_ident!(self, "equals"),
+            span,
+        )?;
         let call_expr = TypedExpr::FunctionCall(Call {
-            callee_function_id: equals_implementation_function_id,
+            callee: Callee::StaticFunction(equals_implementation_function_id),
             args: vec![lhs, rhs],
             type_args: Vec::new(),
             ret_type: BOOL_TYPE_ID,
@@ -4529,6 +6955,67 @@ impl TypedModule {
         Ok(call_expr)
     }
 
+    /// Strings are shown as themselves, so they don't need an implementation
+    fn synth_show_call(&mut self, expr: TypedExpr) -> TyperResult<TypedExpr> {
+        let type_id = expr.get_type();
+        if type_id == STRING_TYPE_ID {
+            return Ok(expr);
+        }
+        let span = expr.get_span();
+        let Ok(show_function_id) = self.expect_ability_impl_function(
+            SHOW_ABILITY_ID,
+            type_id,
+            get_ident!(self, "show"),
+            span,
+        ) else {
+            return failf!(
+                span,
+                "Cannot interpolate a value of type '{}' because it does not implement Show",
+                self.type_id_to_string(type_id)
+            );
+        };
+        let call_expr = TypedExpr::FunctionCall(Call {
+            callee: Callee::StaticFunction(show_function_id),
+            args: vec![expr],
+            type_args: Vec::new(),
+            ret_type: STRING_TYPE_ID,
+            span,
+        });
+        Ok(call_expr)
+    }
+
+    /// Desugars `"a{x}b"` into `string::concatAll(["a", x.show(), "b"])`, which builds the
+    /// result with a single allocation
+    fn eval_interpolated_string(
+        &mut self,
+        interpolated: &parse::InterpolatedString,
+        scope_id: ScopeId,
+    ) -> TyperResult<TypedExpr> {
+        let span = interpolated.span;
+        let mut parts = Vec::with_capacity(interpolated.parts.len());
+        for part in interpolated.parts.iter() {
+            match part {
+                parse::InterpolatedStringPart::String(s, span) => {
+                    parts.push(TypedExpr::Str(s.clone(), *span))
+                }
+                parse::InterpolatedStringPart::Expr(expr_id) => {
+                    let expr = self.eval_expr(*expr_id, scope_id, None)?;
+                    parts.push(self.synth_show_call(expr)?);
+                }
+            }
+        }
+        if parts.len() == 1 {
+            return Ok(parts.pop().unwrap());
+        }
+        let parts_array = self.synth_array_literal(parts, STRING_TYPE_ID, scope_id, span)?;
+        self.synth_function_call(
+            qident!(self, span, ["string"], "concatAll"),
+            span,
+            scope_id,
+            Some((vec![], vec![parts_array])),
+        )
+    }
+
     fn eval_if_expr(
         &mut self,
         if_expr: &IfExpr,
@@ -4553,8 +7040,16 @@ impl TypedModule {
             let wildcard_pattern_id =
                 self.ast.patterns.add_pattern(parse::ParsedPattern::Wildcard(if_expr.span));
             let cases = vec![
-                parse::ParsedMatchCase { pattern: cond_pattern_id, expression: if_expr.cons },
-                parse::ParsedMatchCase { pattern: wildcard_pattern_id, expression: alternate_expr },
+                parse::ParsedMatchCase {
+                    pattern: cond_pattern_id,
+                    guard: None,
+                    expression: if_expr.cons,
+                },
+                parse::ParsedMatchCase {
+                    pattern: wildcard_pattern_id,
+                    guard: None,
+                    expression: alternate_expr,
+                },
             ];
             let match_expr = parse::ParsedMatchExpression {
                 target_expression: cond_expr_id,
@@ -4668,8 +7163,11 @@ impl TypedModule {
                         fn_call.span,
                     );
                 }
-                let enclosing_function = self.scopes.nearest_parent_function(calling_scope);
-                let expected_return_type = self.get_function(enclosing_function).ret_type;
+                if self.scopes.find_defer_scope(calling_scope, None).is_some() {
+                    return failf!(fn_call.span, "Cannot return from inside a defer");
+                }
+                let expected_return_type =
+                    self.enclosing_return_type(calling_scope, "return", fn_call.span)?;
                 let return_value = self.eval_expr(
                     fn_call.args[0].value,
                     calling_scope,
@@ -4683,6 +7181,7 @@ impl TypedModule {
                         fn_call.span,
                     );
                 }
+                self.check_closure_does_not_escape(&return_value)?;
                 return Ok(Either::Left(TypedExpr::Return(TypedReturn {
                     value: Box::new(return_value),
                     span: fn_call.span,
@@ -4748,6 +7247,13 @@ impl TypedModule {
             Some(base_expr) => {
                 // Resolve a method call
                 let type_id = base_expr.get_type();
+                if type_id == ERROR_TYPE_ID {
+                    // There's no method to find, but the other arguments can still be checked
+                    for arg in fn_call.args.iter().skip(1) {
+                        self.eval_expr_or_error(arg.value, calling_scope, None);
+                    }
+                    return Err(TyperError::cascade(fn_call.span));
+                }
                 let base_for_method_derefed =
                     self.types.get_type_id_dereferenced(base_expr.get_type());
                 let method_id = match self.types.get(base_for_method_derefed) {
@@ -4855,7 +7361,17 @@ impl TypedModule {
                                 Ok(None)
                             }
                         }
-                        _ => self.find_ability_implementation(fn_name, type_id, None, fn_call.span),
+                        _ => {
+                            self.ensure_all_derived_impls(type_id)?;
+                            self.find_ability_implementation(
+                                fn_name,
+                                type_id,
+                                None,
+                                Some((fn_call, calling_scope)),
+                                expected_type,
+                                fn_call.span,
+                            )
+                        }
                     }?
                 } else {
                     None
@@ -4899,10 +7415,13 @@ impl TypedModule {
                         fn_call.span,
                     ))?;
                     let base_expr = self.eval_expr(first_arg.value, calling_scope, None)?;
+                    self.ensure_derived_impl(ability_id, base_expr.get_type())?;
                     let function_id = self.find_ability_implementation(
                         fn_name,
                         base_expr.get_type(),
                         Some(ability_id),
+                        Some((fn_call, calling_scope)),
+                        expected_type,
                         fn_call.span,
                     )?;
                     function_id.ok_or(errf!(
@@ -4919,37 +7438,200 @@ impl TypedModule {
         return Ok(Either::Right(function_id));
     }
 
+    /// Since a type can implement a generic ability more than once, with different type
+    /// arguments, a call with more than one candidate is resolved by solving the ability's type
+    /// arguments from the call's argument types and expected type, then looking the
+    /// implementation up by (ability, type args, Self)
     fn find_ability_implementation(
-        &self,
+        &mut self,
         function_name: Identifier,
         type_id: TypeId,
         only_ability_id: Option<AbilityId>,
+        call: Option<(&FnCall, ScopeId)>,
+        expected_type: Option<TypeId>,
         span: SpanId,
     ) -> TyperResult<Option<FunctionId>> {
-        let impls = self.ability_impls.iter().filter(|imp| {
-            imp.type_id == type_id
-            // Inlined Option.is_none_or
-            && match only_ability_id {
-                    None => true,
-                    Some(x) => x == imp.ability_id,
-                }
-        });
-        let mut matching_fns = Vec::new();
-        for imp in impls {
+        // Blanket impls resolve to their generic functions, which get specialized like any
+        // other generic function call
+        let mut candidates: Vec<(usize, usize)> = Vec::new();
+        for (impl_index, imp) in self.ability_impls.iter().enumerate() {
+            let ability_matches = match only_ability_id {
+                None => true,
+                Some(x) => x == imp.ability_id,
+            };
+            let applies = imp.type_id == type_id
+                || (!imp.type_params.is_empty()
+                    && self.blanket_impl_applies(imp, &[], type_id).is_some());
+            if !ability_matches || !applies {
+                continue;
+            }
             let ability = self.get_ability(imp.ability_id);
-            let matching = ability.find_function_by_name(function_name);
-            if let Some((matching_index, _generic_fn)) = matching {
-                let impl_fn_id = imp.function_at_index(matching_index);
-                matching_fns.push(impl_fn_id);
+            if let Some((function_index, _generic_fn)) =
+                ability.find_function_by_name(function_name)
+            {
+                candidates.push((impl_index, function_index));
+            }
+        }
+        if candidates.len() == 0 {
+            return Ok(None);
+        }
+        if candidates.len() == 1 {
+            let (impl_index, function_index) = candidates[0];
+            return Ok(Some(self.ability_impls[impl_index].function_at_index(function_index)));
+        }
+
+        let arg_types = match call {
+            Some((fn_call, scope_id)) => self.speculative_arg_types(fn_call, scope_id),
+            None => Vec::new(),
+        };
+        let mut abilities: Vec<(AbilityId, usize)> = Vec::new();
+        for (impl_index, function_index) in candidates.iter() {
+            let ability_id = self.ability_impls[*impl_index].ability_id;
+            if !abilities.iter().any(|(a, _)| *a == ability_id) {
+                abilities.push((ability_id, *function_index));
+            }
+        }
+        let mut matching: Vec<(FunctionId, &TypedAbilityImpl)> = Vec::new();
+        for (ability_id, function_index) in abilities {
+            let known_type_args = self.solve_ability_type_args(
+                ability_id,
+                function_index,
+                type_id,
+                &arg_types,
+                expected_type,
+            );
+            let keyed_impl = match known_type_args.iter().copied().collect::<Option<Vec<_>>>() {
+                Some(type_args) => {
+                    self.find_ability_impl(ability_id, &type_args, type_id).or_else(|| {
+                        self.find_blanket_ability_impl(ability_id, &type_args, type_id)
+                            .map(|(imp, _)| imp)
+                    })
+                }
+                None => None,
+            };
+            match keyed_impl {
+                Some(imp) => matching.push((imp.function_at_index(function_index), imp)),
+                None => {
+                    // Not every type argument is known, so keep the implementations that agree
+                    // with the ones that are
+                    for (impl_index, function_index) in candidates.iter() {
+                        let imp = &self.ability_impls[*impl_index];
+                        if imp.ability_id == ability_id
+                            && self.impl_agrees_with_type_args(imp, &known_type_args)
+                        {
+                            matching.push((imp.function_at_index(*function_index), imp));
+                        }
+                    }
+                }
             }
         }
-        if matching_fns.len() == 0 {
-            Ok(None)
-        } else if matching_fns.len() > 1 {
-            failf!(span, "Ambiguous ability")
+
+        if matching.len() == 1 {
+            Ok(Some(matching[0].0))
         } else {
-            Ok(Some(matching_fns[0]))
+            let mut error = errf!(
+                span,
+                "Ambiguous ability function '{}' for type {}; add a type annotation to choose an implementation",
+                self.get_ident_str(function_name).blue(),
+                self.type_id_to_string(type_id).blue()
+            );
+            let reported: Vec<&TypedAbilityImpl> = if matching.is_empty() {
+                candidates.iter().map(|(impl_index, _)| &self.ability_impls[*impl_index]).collect()
+            } else {
+                matching.iter().map(|(_, imp)| *imp).collect()
+            };
+            for imp in reported {
+                error = error.with_label(
+                    imp.span,
+                    format!(
+                        "candidate: {}",
+                        self.ability_with_type_args_to_string(imp.ability_id, &imp.type_args)
+                    ),
+                );
+            }
+            Err(error)
+        }
+    }
+
+    /// Solves an ability's type arguments from a call to one of its functions on `self_type_id`,
+    /// using whichever argument types are known and the expected return type. Type arguments
+    /// the call says nothing about are None
+    fn solve_ability_type_args(
+        &self,
+        ability_id: AbilityId,
+        function_index: usize,
+        self_type_id: TypeId,
+        arg_types: &[Option<TypeId>],
+        expected_type: Option<TypeId>,
+    ) -> Vec<Option<TypeId>> {
+        let ability = self.get_ability(ability_id);
+        let generic_function = self.get_function(ability.functions[function_index].function_id);
+        let self_type_variable = generic_function.type_params[0].type_param.type_id;
+        let mut vars: Vec<TypeId> = ability.type_params.iter().map(|tp| tp.type_id).collect();
+        vars.push(self_type_variable);
+        let mut bindings = vec![(self_type_variable, self_type_id)];
+        let param_types = generic_function.params.iter().skip(1).map(|p| p.type_id);
+        let constraints = param_types
+            .zip(arg_types.iter().copied())
+            .chain(std::iter::once((generic_function.ret_type, expected_type)));
+        for (generic_type, actual_type) in constraints {
+            let Some(actual_type) = actual_type else { continue };
+            // A constraint that doesn't fit is left for the call's own typechecking to report
+            let mut attempt = bindings.clone();
+            if self.unify_impl_types(generic_type, actual_type, &vars, &mut attempt) {
+                bindings = attempt;
+            }
+        }
+        ability
+            .type_params
+            .iter()
+            .map(|tp| bindings.iter().find(|(var, _)| *var == tp.type_id).map(|(_, t)| *t))
+            .collect()
+    }
+
+    fn impl_agrees_with_type_args(
+        &self,
+        imp: &TypedAbilityImpl,
+        known_type_args: &[Option<TypeId>],
+    ) -> bool {
+        let vars: Vec<TypeId> = imp.type_params.iter().map(|tp| tp.type_param.type_id).collect();
+        let mut bindings = Vec::new();
+        imp.type_args.iter().zip(known_type_args.iter()).all(|(impl_type_arg, known)| match known {
+            None => true,
+            Some(known) => self.unify_impl_types(*impl_type_arg, *known, &vars, &mut bindings),
+        })
+    }
+
+    /// The types of a call's arguments after `self`, used to choose between ability
+    /// implementations. This evaluation is speculative: an argument that fails is left unknown,
+    /// and its diagnostics are left for the call's own typechecking to report. An argument that
+    /// succeeds is kept in `speculative_call_args` for the call to reuse
+    fn speculative_arg_types(
+        &mut self,
+        fn_call: &FnCall,
+        scope_id: ScopeId,
+    ) -> Vec<Option<TypeId>> {
+        let mut arg_types = Vec::with_capacity(fn_call.args.len());
+        for arg in fn_call.args.iter().skip(1) {
+            let errors_before = self.errors.len();
+            let warnings_before = self.warnings.len();
+            let speculative_expr = self.eval_expr(arg.value, scope_id, None);
+            let had_recovered_errors = self.errors.len() > errors_before;
+            let arg_type = match (speculative_expr, had_recovered_errors) {
+                (Ok(expr), false) => {
+                    let type_id = expr.get_type();
+                    self.speculative_call_args.insert(arg.value, expr);
+                    Some(type_id)
+                }
+                _ => {
+                    self.errors.truncate(errors_before);
+                    self.warnings.truncate(warnings_before);
+                    None
+                }
+            };
+            arg_types.push(arg_type);
         }
+        arg_types
     }
 
     fn handle_enum_constructor(
@@ -5114,6 +7796,7 @@ impl TypedModule {
         fn_call: &FnCall,
         params: &Vec<FnArgDefn>,
         pre_evaled_params: Option<Vec<TypedExpr>>,
+        mut speculated_args: HashMap<ParsedExpressionId, TypedExpr>,
         calling_scope: ScopeId,
     ) -> TyperResult<Vec<TypedExpr>> {
         debug!("typecheck_call_arguments {}", self.get_ident_str(fn_call.name.name));
@@ -5154,21 +7837,34 @@ impl TypedModule {
                             fn_call.span,
                         );
                     };
-                    let expected_type_for_param = Some(fn_param.type_id);
-                    let expr =
-                        self.eval_expr(param.value, calling_scope, expected_type_for_param)?;
-                    expr
+                    // An argument evaluated without an expected type may not have the type it
+                    // would get with one, as with integer literals, so it is only reused if it fits
+                    let speculated = speculated_args.remove(&param.value).filter(|expr| {
+                        self.check_types(fn_param.type_id, expr.get_type(), calling_scope).is_ok()
+                    });
+                    match speculated {
+                        Some(expr) => expr,
+                        None => {
+                            let expected_type_for_param = Some(fn_param.type_id);
+                            self.eval_expr_or_error(
+                                param.value,
+                                calling_scope,
+                                expected_type_for_param,
+                            )
+                        }
+                    }
                 }
             };
+            // A bad argument is reported on its own so that the rest are still checked
             if let Err(e) = self.check_types(fn_param.type_id, expr.get_type(), calling_scope) {
-                return make_fail_span(
+                self.report_error(TyperError::make(
                     format!(
                         "Invalid parameter type passed to function {}: {}",
                         &*self.ast.identifiers.get_name(fn_call.name.name),
                         e
                     ),
                     expr.get_span(),
-                );
+                ));
             }
             final_args.push(expr);
         }
@@ -5186,11 +7882,28 @@ impl TypedModule {
             fn_call.args.is_empty() || known_args.is_none(),
             "cannot pass both typed value args and parsed value args to eval_function_call"
         );
-        let function_id =
-            match self.resolve_parsed_function_call(fn_call, scope_id, expected_type_id)? {
-                Either::Left(expr) => return Ok(expr),
-                Either::Right(function_id) => function_id,
-            };
+        let is_synthesized = known_args.is_some();
+        if !fn_call.is_method && fn_call.name.namespaces.is_empty() && known_args.is_none() {
+            if let Some(variable_id) = self.scopes.find_variable(scope_id, fn_call.name.name) {
+                let variable_type = self.variables.get_variable(variable_id).type_id;
+                if let Type::Function(_) = self.types.get(variable_type) {
+                    return self.eval_dynamic_function_call(fn_call, scope_id);
+                }
+            }
+        }
+        let resolved = self.resolve_parsed_function_call(fn_call, scope_id, expected_type_id);
+        // Taken out whether or not resolution succeeded, so that nothing is left behind for
+        // another evaluation of the same expressions, such as in a different specialization
+        let speculated_args: HashMap<ParsedExpressionId, TypedExpr> = fn_call
+            .args
+            .iter()
+            .filter_map(|arg| self.speculative_call_args.remove_entry(&arg.value))
+            .collect();
+        let function_id = match resolved? {
+            Either::Left(expr) => return Ok(expr),
+            Either::Right(function_id) => function_id,
+        };
+        self.called_functions.insert(function_id);
 
         let span = fn_call.span;
 
@@ -5206,6 +7919,7 @@ impl TypedModule {
                         fn_call,
                         &original_params,
                         known_args.map(|ka| ka.1),
+                        speculated_args,
                         scope_id,
                     )?,
                     Vec::new(),
@@ -5241,14 +7955,27 @@ impl TypedModule {
                         intrinsic_type,
                         scope_id,
                         known_args.map(|ka| ka.1),
+                        speculated_args,
                     )?;
                     (function_id, args, type_args)
                 }
             };
 
+        // Checked against the declared parameters, since a specialization's `T` could be a
+        // function type itself. A method's receiver is what it operates on, as in
+        // `closures.get(0)`, and synthesized calls, like an array literal's pushes, track their
+        // own results
+        if !is_synthesized {
+            let skip = if fn_call.is_method { 1 } else { 0 };
+            for (param, arg) in original_params.iter().zip(typechecked_arguments.iter()).skip(skip)
+            {
+                self.check_closure_argument_does_not_escape(param.type_id, arg)?;
+            }
+        }
+
         let function_ret_type = self.get_function(function_to_call).ret_type;
         let call = Call {
-            callee_function_id: function_to_call,
+            callee: Callee::StaticFunction(function_to_call),
             args: typechecked_arguments,
             type_args,
             ret_type: function_ret_type,
@@ -5257,6 +7984,52 @@ impl TypedModule {
         Ok(TypedExpr::FunctionCall(call))
     }
 
+    fn eval_dynamic_function_call(
+        &mut self,
+        fn_call: &FnCall,
+        scope_id: ScopeId,
+    ) -> TyperResult<TypedExpr> {
+        let callee = self
+            .eval_variable(&parse::Variable { name: fn_call.name.clone() }, scope_id, false)?
+            .expect_variable();
+        let Type::Function(function_type) = self.types.get(callee.type_id) else {
+            panic!("Expected a function-typed variable for a dynamic call")
+        };
+        let function_type = function_type.clone();
+        if !fn_call.type_args.is_empty() {
+            return failf!(fn_call.span, "Cannot pass type arguments to a function value");
+        }
+        if fn_call.args.len() != function_type.params.len() {
+            return failf!(
+                fn_call.span,
+                "Incorrect number of arguments: expected {}, got {}",
+                function_type.params.len(),
+                fn_call.args.len()
+            );
+        }
+        let mut args = Vec::with_capacity(fn_call.args.len());
+        for (arg, param) in fn_call.args.iter().zip(function_type.params.iter()) {
+            let expr = self.eval_expr(arg.value, scope_id, Some(param.type_id))?;
+            if let Err(msg) = self.check_types(param.type_id, expr.get_type(), scope_id) {
+                return failf!(
+                    expr.get_span(),
+                    "Invalid parameter type passed to function {}: {}",
+                    self.get_ident_str(fn_call.name.name),
+                    msg
+                );
+            }
+            self.check_closure_argument_does_not_escape(param.type_id, &expr)?;
+            args.push(expr);
+        }
+        Ok(TypedExpr::FunctionCall(Call {
+            callee: Callee::DynamicFunction(callee.variable_id),
+            args,
+            type_args: Vec::new(),
+            ret_type: function_type.return_type,
+            span: fn_call.span,
+        }))
+    }
+
     fn infer_and_check_call_type_args(
         &mut self,
         fn_call: &FnCall,
@@ -5313,11 +8086,45 @@ impl TypedModule {
                     // 1) We hope that other arguments will have more luck
                     // 2) We will evaluate these expressions again when we actually
                     //    do typechecking, if we manage to solve the generics
-                    if let Ok(expr) = self.eval_expr(
+                    // Errors recovered from while speculating are discarded along with the result
+                    // A lambda argument needs its parameter types up front, so substitute
+                    // whatever we have solved so far: fn(T) -> U becomes fn(int) -> U
+                    let expected_arg_type = match self.types.get(gen_param.type_id) {
+                        Type::Function(_) if !solved_params.is_empty() => {
+                            let (solved_generic_params, solutions): (Vec<_>, Vec<_>) =
+                                generic_type_params
+                                    .iter()
+                                    .filter_map(|type_param| {
+                                        let ident = type_param.type_param.ident;
+                                        let solution =
+                                            solved_params.iter().find(|p| p.ident == ident)?;
+                                        let generic_param = GenericTypeParam {
+                                            name: ident,
+                                            type_id: type_param.type_param.type_id,
+                                        };
+                                        Some((generic_param, solution.type_id))
+                                    })
+                                    .unzip();
+                            self.instantiate_generic_type_subst(
+                                gen_param.type_id,
+                                None,
+                                None,
+                                &solutions,
+                                &solved_generic_params,
+                                matching_argument.value.into(),
+                            )
+                        }
+                        _ => gen_param.type_id,
+                    };
+                    let errors_before = self.errors.len();
+                    let speculative_expr = self.eval_expr(
                         matching_argument.value,
                         calling_scope,
-                        Some(gen_param.type_id),
-                    ) {
+                        Some(expected_arg_type),
+                    );
+                    let had_recovered_errors = self.errors.len() > errors_before;
+                    self.errors.truncate(errors_before);
+                    if let (Ok(expr), false) = (speculative_expr, had_recovered_errors) {
                         self.solve_generic_params(
                             &mut solved_params,
                             expr.get_type(),
@@ -5367,10 +8174,7 @@ impl TypedModule {
 
         for (param_defn, param_given) in generic_type_params.iter().zip(type_params.iter()) {
             for constrained_ability_id in param_defn.ability_constraints.iter() {
-                let implementation = self.ability_impls.iter().find(|imp| {
-                    imp.type_id == param_given.type_id && imp.ability_id == *constrained_ability_id
-                });
-                if !implementation.is_some() {
+                if !self.type_implements_ability(param_given.type_id, *constrained_ability_id) {
                     return failf!(
                         fn_call.span,
                         "Cannot invoke function '{}' with type parameter {} = {}; does not satisfy ability constraint {}",
@@ -5471,6 +8275,30 @@ impl TypedModule {
                 }
                 Ok(())
             }
+            (Type::Function(passed_fun), Type::Function(fun)) => {
+                // fn map[T, U](self: Array[T], f: fn(T) -> U): Array[U]
+                // map(\x -> x + 1)
+                // passed: fn(int) -> int, arg: fn(T) -> U
+                if passed_fun.params.len() != fun.params.len() {
+                    return Ok(());
+                }
+                for (passed_param, param) in passed_fun.params.iter().zip(fun.params.iter()) {
+                    self.solve_generic_params(
+                        solved_params,
+                        passed_param.type_id,
+                        param.type_id,
+                        scope_id,
+                        span,
+                    )?;
+                }
+                self.solve_generic_params(
+                    solved_params,
+                    passed_fun.return_type,
+                    fun.return_type,
+                    scope_id,
+                    span,
+                )
+            }
             (Type::Reference(passed_refer), Type::Reference(refer)) => self.solve_generic_params(
                 solved_params,
                 passed_refer.inner_type,
@@ -5563,15 +8391,40 @@ impl TypedModule {
         intrinsic_type: Option<IntrinsicFunction>,
         calling_scope: ScopeId,
         pre_evaled_value_args: Option<Vec<TypedExpr>>,
+        speculated_args: HashMap<ParsedExpressionId, TypedExpr>,
     ) -> TyperResult<(FunctionId, Vec<TypedExpr>)> {
+        let specialized_function_id = self.get_specialized_function(
+            inferred_or_passed_type_args,
+            generic_function_id,
+            intrinsic_type,
+        )?;
+        let specialized_params = self.get_function(specialized_function_id).params.clone();
+        let typechecked_exprs = self.check_call_arguments(
+            fn_call,
+            &specialized_params,
+            pre_evaled_value_args,
+            speculated_args,
+            calling_scope,
+        )?;
+        Ok((specialized_function_id, typechecked_exprs))
+    }
+
+    fn get_specialized_function(
+        &mut self,
+        inferred_or_passed_type_args: &[TypeParam],
+        generic_function_id: FunctionId,
+        intrinsic_type: Option<IntrinsicFunction>,
+    ) -> TyperResult<FunctionId> {
         let generic_function = self.get_function(generic_function_id);
         let generic_function_parent_scope = self
             .scopes
             .get_scope(generic_function.scope)
             .parent
             .expect("No function scope should be a root scope");
-        let generic_function_ast_id = generic_function.parsed_function_id;
+        let generic_function_ast_id = generic_function.parsed_id.expect_function();
         let generic_function_span = generic_function.span;
+        let generic_function_kind = generic_function.kind;
+        let generic_type_params = generic_function.type_params.clone();
         let specializations = generic_function.specializations.clone();
         let name = String::from(self.get_ident_str(generic_function.name));
         // drop(generic_function);
@@ -5594,13 +8447,7 @@ impl TypedModule {
                     "Found existing specialization for function {} with types: {}",
                     name, types_stringified
                 );
-                let exprs = self.check_call_arguments(
-                    fn_call,
-                    &existing_specialization.specialized_params,
-                    pre_evaled_value_args,
-                    calling_scope,
-                )?;
-                return Ok((existing_specialization.specialized_function_id, exprs));
+                return Ok(existing_specialization.specialized_function_id);
             }
         }
 
@@ -5638,6 +8485,30 @@ impl TypedModule {
                 );
             }
         }
+        // Functions of a blanket ability impl may refer to Self, which is generic in the impl's
+        // type params, so it needs the same substitution
+        if let TypedFunctionKind::AbilityImpl(_, impl_self_type) = generic_function_kind {
+            if self.types.does_type_reference_type_variables(impl_self_type) {
+                let bindings: Vec<(TypeId, TypeId)> = generic_type_params
+                    .iter()
+                    .filter_map(|generic_param| {
+                        let ident = generic_param.type_param.ident;
+                        let solution =
+                            inferred_or_passed_type_args.iter().find(|p| p.ident == ident)?;
+                        Some((generic_param.type_param.type_id, solution.type_id))
+                    })
+                    .collect();
+                let specialized_self_type = self.substitute_type_variables(
+                    impl_self_type,
+                    &bindings,
+                    ParsedId::Function(generic_function_ast_id),
+                );
+                let _ = self
+                    .scopes
+                    .get_scope_mut(spec_fn_scope_id)
+                    .add_type(get_ident!(self, "Self"), specialized_self_type);
+            }
+        }
         new_name.push_str("_spec_");
         new_name.push_str(
             &type_ids.iter().map(|type_id| type_id.to_string()).collect::<Vec<_>>().join("_"),
@@ -5665,16 +8536,39 @@ impl TypedModule {
         self.get_function_mut(generic_function_id).specializations.push(SpecializationStruct {
             specialized_function_id,
             specialized_type_params: type_ids,
-            specialized_params: specialized_params.clone(),
+            specialized_params,
         });
+        Ok(specialized_function_id)
+    }
 
-        let typechecked_exprs = self.check_call_arguments(
-            fn_call,
-            &specialized_params,
-            pre_evaled_value_args,
-            calling_scope,
-        )?;
-        Ok((specialized_function_id, typechecked_exprs))
+    fn substitute_type_variables(
+        &mut self,
+        type_id: TypeId,
+        bindings: &[(TypeId, TypeId)],
+        parsed_id: ParsedId,
+    ) -> TypeId {
+        if let Some((_, solution)) = bindings.iter().find(|(var, _)| *var == type_id) {
+            return *solution;
+        }
+        if let Some(info) = self.types.get_generic_instance_info(type_id).cloned() {
+            let mut param_values = Vec::with_capacity(info.param_values.len());
+            for param_value in info.param_values.iter() {
+                param_values.push(self.substitute_type_variables(
+                    *param_value,
+                    bindings,
+                    parsed_id,
+                ));
+            }
+            return self.instantiate_generic_type(info.generic_parent, param_values, parsed_id);
+        }
+        match self.types.get(type_id) {
+            Type::Reference(reference) => {
+                let inner_type =
+                    self.substitute_type_variables(reference.inner_type, bindings, parsed_id);
+                self.types.add_type(Type::Reference(ReferenceType { inner_type }))
+            }
+            _ => type_id,
+        }
     }
 
     pub fn should_codegen_function(&self, function: &TypedFunction) -> bool {
@@ -5682,6 +8576,9 @@ impl TypedModule {
             Some(intrinsic) if intrinsic.is_inlined() => false,
             _ => match function.kind {
                 TypedFunctionKind::AbilityDefn(_) => false,
+                // Lambdas are generated where they are referenced, since they may live inside
+                // generic code, and they may need their enclosing function's variables
+                TypedFunctionKind::Lambda { .. } => false,
                 TypedFunctionKind::Standard | TypedFunctionKind::AbilityImpl { .. } => {
                     let is_generic =
                         self.types.does_type_reference_type_variables(function.ret_type)
@@ -5727,6 +8624,9 @@ impl TypedModule {
                     type_id: variable_type,
                     owner_scope: scope_id,
                 });
+                if let Some(captured_variable_id) = self.find_reference_capture(&value_expr) {
+                    self.reference_closure_variables.insert(variable_id, captured_variable_id);
+                }
                 let val_def_stmt = TypedStmt::ValDef(Box::new(ValDef {
                     ty: variable_type,
                     variable_id,
@@ -5734,6 +8634,10 @@ impl TypedModule {
                     span: val_def.span,
                 }));
                 self.scopes.add_variable(scope_id, val_def.name, variable_id);
+                self.local_usages.insert(
+                    variable_id,
+                    LocalUsage { span: val_def.span, read: false, reassigned: false },
+                );
                 Ok(val_def_stmt)
             }
             ParsedStmt::Assignment(assignment) => {
@@ -5746,6 +8650,7 @@ impl TypedModule {
                         assignment.span,
                     );
                 }
+                self.check_closure_does_not_escape(&rhs)?;
                 let expr = TypedStmt::Assignment(Box::new(Assignment {
                     destination: Box::new(lhs),
                     value: Box::new(rhs),
@@ -5757,6 +8662,12 @@ impl TypedModule {
                 let expr = self.eval_expr(*expression, scope_id, expected_type)?;
                 Ok(TypedStmt::Expr(Box::new(expr)))
             }
+            ParsedStmt::Defer(defer) => {
+                let defer_scope_id =
+                    self.scopes.add_child_scope(scope_id, ScopeType::DeferBody, None, None);
+                let expr = self.eval_expr(defer.expr, defer_scope_id, None)?;
+                Ok(TypedStmt::Defer(Box::new(TypedDefer { expr, span: defer.span })))
+            }
             ParsedStmt::While(while_stmt) => {
                 let cond = self.eval_expr(while_stmt.cond, scope_id, Some(BOOL_TYPE_ID))?;
                 if let Err(e) = self.check_types(BOOL_TYPE_ID, cond.get_type(), scope_id) {
@@ -5765,7 +8676,9 @@ impl TypedModule {
                         cond.get_span(),
                     );
                 }
-                let block = self.eval_block(&while_stmt.block, scope_id, None)?;
+                let while_scope_id =
+                    self.scopes.add_child_scope(scope_id, ScopeType::WhileBody, None, None);
+                let block = self.eval_block(&while_stmt.block, while_scope_id, None)?;
                 Ok(TypedStmt::WhileLoop(Box::new(TypedWhileLoop {
                     cond,
                     block,
@@ -5782,19 +8695,43 @@ impl TypedModule {
     ) -> TyperResult<TypedBlock> {
         let mut statements = Vec::with_capacity(block.stmts.len());
         let mut last_expr_type: TypeId = UNIT_TYPE_ID;
+        let mut is_unreachable = false;
         for (index, stmt) in block.stmts.iter().enumerate() {
-            if last_expr_type == NEVER_TYPE_ID {
-                return make_fail_span(
-                    "Dead code following divergent statement",
-                    self.ast.get_stmt_span(stmt),
+            if last_expr_type == NEVER_TYPE_ID && !is_unreachable {
+                is_unreachable = true;
+                let divergent_span = self.ast.get_stmt_span(&block.stmts[index - 1]);
+                self.report_warning(
+                    Diagnostic::warning(
+                        Lint::UnreachableCode,
+                        "Unreachable statement",
+                        self.ast.get_stmt_span(stmt),
+                    )
+                    .with_label(
+                        divergent_span,
+                        "any code following this expression is unreachable",
+                    ),
                 );
             }
             let is_last = index == block.stmts.len() - 1;
             let expected_type = if is_last { expected_type } else { None };
 
-            let stmt = self.eval_block_stmt(stmt, scope_id, expected_type)?;
-            last_expr_type = stmt.get_type();
-            statements.push(stmt);
+            // A statement that fails to typecheck is reported and dropped, so that we can keep
+            // checking the rest of the block
+            match self.eval_block_stmt(stmt, scope_id, expected_type) {
+                // Unreachable statements are still checked, but there's nothing to codegen
+                Ok(_) if is_unreachable => {}
+                Ok(stmt) => {
+                    last_expr_type = stmt.get_type();
+                    statements.push(stmt);
+                }
+                Err(e) => {
+                    self.report_error(e);
+                    if let ParsedStmt::ValDef(val_def) = stmt {
+                        self.bind_error_variable(val_def, scope_id);
+                    }
+                    last_expr_type = ERROR_TYPE_ID;
+                }
+            }
         }
 
         let typed_block =
@@ -5802,6 +8739,47 @@ impl TypedModule {
         Ok(typed_block)
     }
 
+    /// Binds the name from a val definition that failed to typecheck, so that later uses of it
+    /// are not reported as undefined. If the val has a valid type annotation we can keep
+    /// checking its uses against that; otherwise they are silenced by the error type
+    fn bind_error_variable(&mut self, val_def: &parse::ValDef, scope_id: ScopeId) {
+        let type_id = val_def
+            .type_expr
+            .and_then(|type_expr| self.eval_type_expr(type_expr, scope_id).ok())
+            .unwrap_or(ERROR_TYPE_ID);
+        let variable_id = self.variables.add_variable(Variable {
+            is_mutable: val_def.is_mutable,
+            name: val_def.name,
+            type_id,
+            owner_scope: scope_id,
+        });
+        self.scopes.add_variable(scope_id, val_def.name, variable_id);
+    }
+
+    /// Warnings are deduplicated like errors, because the same code can be checked more than once
+    fn report_warning(&mut self, warning: Diagnostic) {
+        let is_duplicate = self
+            .warnings
+            .iter()
+            .any(|w| w.primary.span == warning.primary.span && w.message == warning.message);
+        if !is_duplicate {
+            self.warnings.push(warning);
+        }
+    }
+
+    /// Records an error and carries on. Cascading errors are dropped, as are exact duplicates,
+    /// which happen when the same code is checked more than once, for example when specializing
+    fn report_error(&mut self, error: TyperError) {
+        if error.is_cascade {
+            return;
+        }
+        let is_duplicate =
+            self.errors.iter().any(|e| e.span == error.span && e.message == error.message);
+        if !is_duplicate {
+            self.errors.push(error);
+        }
+    }
+
     fn resolve_intrinsic_function_type(
         &self,
         fn_name: Identifier,
@@ -5923,6 +8901,7 @@ impl TypedModule {
                     {
                         return failf!(span, "Enum payload type mismatch: {}", msg);
                     }
+                    self.check_closure_does_not_escape(&payload_value)?;
                     Ok(Some(Box::new(payload_value)))
                 } else {
                     failf!(
@@ -5957,6 +8936,8 @@ impl TypedModule {
         ability_id: Option<AbilityId>,
         // ALSO IGNORED WHEN SPECIALIZING
         ability_impl_type: Option<TypeId>,
+        // ALSO IGNORED WHEN SPECIALIZING; the type params of a blanket ability impl
+        impl_type_params: &[FunctionTypeParam],
         namespace_id: NamespaceId,
     ) -> TyperResult<FunctionId> {
         let namespace = self.namespaces.get(namespace_id);
@@ -6012,6 +8993,7 @@ impl TypedModule {
                     ability_constraints: vec![],
                 })
             }
+            type_params.extend(impl_type_params.iter().cloned());
             for type_parameter in parsed_function_type_args.iter() {
                 let mut checked_constraints = Vec::new();
                 for parsed_constraint in type_parameter.constraints.iter() {
@@ -6209,9 +9191,10 @@ impl TypedModule {
             intrinsic_type,
             linkage: parsed_function_linkage,
             specializations: Vec::new(),
-            parsed_function_id,
+            parsed_id: parsed_function_id.into(),
             is_method_of,
             kind,
+            closure_env: None,
             span: parsed_function_span,
         };
         let function_id = self.add_function(function);
@@ -6220,7 +9203,14 @@ impl TypedModule {
         // So don't add them to any scope.
         if !specialize {
             if !self.scopes.add_function(parent_scope_id, parsed_function_name, function_id) {
-                return make_fail_span(&format!("Function name is taken"), parsed_function_span);
+                let mut error = make_error("Function name is taken", parsed_function_span);
+                if let Some(existing) =
+                    self.scopes.get_scope(parent_scope_id).find_function(parsed_function_name)
+                {
+                    error = error
+                        .with_label(self.get_function(existing).span, "previous definition here");
+                }
+                return Err(error);
             }
 
             let defn_info = TypeDefnInfo {
@@ -6245,7 +9235,11 @@ impl TypedModule {
             }
         }
 
-        if !specialize {
+        // An ability's default implementations are declared again for each impl that uses them,
+        // but the AST function stays mapped to the ability's own declaration
+        let is_default_impl = ability_impl_type.is_some()
+            && self.function_ast_mappings.contains_key(&parsed_function_id);
+        if !specialize && !is_default_impl {
             let existed =
                 self.function_ast_mappings.insert(parsed_function_id, function_id).is_some();
             debug_assert!(!existed)
@@ -6266,7 +9260,7 @@ impl TypedModule {
         let fn_scope_id = function.scope;
         let ret_type = function.ret_type;
         let is_extern = function.linkage == Linkage::External;
-        let ast_id = function.parsed_function_id;
+        let ast_id = function.parsed_id.expect_function();
         let is_intrinsic = function.intrinsic_type.is_some();
         let is_ability_defn = matches!(function.kind, TypedFunctionKind::AbilityDefn(_));
 
@@ -6292,9 +9286,11 @@ impl TypedModule {
                         ),
                         function_span,
                     );
-                } else {
-                    Some(block)
                 }
+                if let Some(TypedStmt::Expr(last_expr)) = block.statements.last() {
+                    self.check_closure_does_not_escape(last_expr)?;
+                }
+                Some(block)
             }
             None if is_intrinsic || is_extern || is_ability_defn => None,
             None => return make_fail_span("function is missing implementation", function_span),
@@ -6318,6 +9314,7 @@ impl TypedModule {
             Some(specialization_params),
             None,
             None,
+            &[],
             self.get_root_namespace_id(),
         )?;
         self.eval_function_body(specialized_function_id)?;
@@ -6354,6 +9351,27 @@ impl TypedModule {
         if !self.scopes.get_scope_mut(ability_scope_id).add_type(self_ident_id, self_type_id) {
             panic!("Self already exists in ability scope?")
         };
+        let mut type_params: Vec<TypeParam> = Vec::with_capacity(parsed_ability.type_params.len());
+        for type_param in parsed_ability.type_params.iter() {
+            let type_variable_id = self.types.add_type(Type::TypeVariable(TypeVariable {
+                name: type_param.ident,
+                scope_id: ability_scope_id,
+                ability_impls: vec![],
+                span: type_param.span,
+            }));
+            type_params.push(TypeParam { ident: type_param.ident, type_id: type_variable_id });
+            let added = self
+                .scopes
+                .get_scope_mut(ability_scope_id)
+                .add_type(type_param.ident, type_variable_id);
+            if !added {
+                return failf!(
+                    type_param.span,
+                    "Type variable name '{}' is taken",
+                    self.get_ident_str(type_param.ident).blue()
+                );
+            }
+        }
 
         // Make a namespace for the ability
         let ability_namespace = Namespace {
@@ -6376,6 +9394,7 @@ impl TypedModule {
 
         let typed_ability = TypedAbility {
             name: parsed_ability.name,
+            type_params,
             functions: Vec::new(),
             scope_id: ability_scope_id,
             ast_id: parsed_ability.id,
@@ -6393,6 +9412,11 @@ impl TypedModule {
             );
         }
         self.scopes.set_scope_owner_id(ability_scope_id, ScopeOwnerId::Ability(ability_id));
+        // Inside the ability, Self implements it, so that default implementations can call
+        // the ability's other functions
+        if let Type::TypeVariable(self_tvar) = self.types.get_mut(self_type_id) {
+            self_tvar.ability_impls.push(ability_id);
+        }
 
         let mut typed_functions: Vec<TypedAbilityFunctionRef> =
             Vec::with_capacity(parsed_ability.functions.len());
@@ -6403,6 +9427,7 @@ impl TypedModule {
                 None,
                 Some(ability_id),
                 None,
+                &[],
                 namespace_id,
             )?;
             let function_name = self.ast.get_function(*parsed_function_id).name;
@@ -6421,6 +9446,10 @@ impl TypedModule {
         let span = parsed_ability_impl.span;
         let ability_name = parsed_ability_impl.ability_name;
         let parsed_functions = parsed_ability_impl.functions.clone();
+        let parsed_type_args = parsed_ability_impl.ability_type_args.clone();
+        let parsed_type_params = parsed_ability_impl.type_params.clone();
+        let parsed_target_type = parsed_ability_impl.target_type;
+        let auto = parsed_ability_impl.auto;
         // FIXME: Search from current scope. I'm just not sure how we want to do ability scoping
         let Some(ability_id) = self.scopes.get_root_scope().find_ability(ability_name) else {
             return make_fail_span(
@@ -6428,31 +9457,139 @@ impl TypedModule {
                 span,
             );
         };
-        let target_type = self.eval_type_expr(parsed_ability_impl.target_type, scope_id)?;
 
-        // Scoping / orphan / coherence: For now, let's globally allow only one implementation per (Ability, Target Type) pair
+        // A blanket impl gets a scope for its type params, so that they're visible when we
+        // evaluate the target type and the ability's type arguments
+        let (type_params, impl_types_scope_id) = if parsed_type_params.is_empty() {
+            (Vec::new(), scope_id)
+        } else {
+            let impl_types_scope_id =
+                self.scopes.add_child_scope(scope_id, ScopeType::AbilityImpl, None, None);
+            let mut type_params: Vec<FunctionTypeParam> =
+                Vec::with_capacity(parsed_type_params.len());
+            for type_param in parsed_type_params.iter() {
+                let mut ability_constraints = Vec::new();
+                for parsed_constraint in type_param.constraints.iter() {
+                    let constraint_ability_id = self
+                        .scopes
+                        .find_ability_namespaced(
+                            scope_id,
+                            &parsed_constraint.ability_name,
+                            &self.namespaces,
+                            &self.ast.identifiers,
+                        )?
+                        .ok_or(errf!(
+                            parsed_constraint.ability_name.span,
+                            "Failed to resolve ability {}",
+                            self.get_ident_str(parsed_constraint.ability_name.name)
+                        ))?;
+                    ability_constraints.push(constraint_ability_id);
+                }
+                let type_variable_id = self.types.add_type(Type::TypeVariable(TypeVariable {
+                    name: type_param.ident,
+                    scope_id: impl_types_scope_id,
+                    ability_impls: ability_constraints.clone(),
+                    span: type_param.span,
+                }));
+                if !self
+                    .scopes
+                    .get_scope_mut(impl_types_scope_id)
+                    .add_type(type_param.ident, type_variable_id)
+                {
+                    return failf!(
+                        type_param.span,
+                        "Type variable name '{}' is taken",
+                        self.get_ident_str(type_param.ident).blue()
+                    );
+                }
+                type_params.push(FunctionTypeParam {
+                    type_param: TypeParam { ident: type_param.ident, type_id: type_variable_id },
+                    ability_constraints,
+                });
+            }
+            (type_params, impl_types_scope_id)
+        };
+
+        let target_type = self.eval_type_expr(parsed_target_type, impl_types_scope_id)?;
+        let ability = self.get_ability(ability_id).clone();
+        if parsed_type_args.len() != ability.type_params.len() {
+            return failf!(
+                span,
+                "Ability '{}' expects {} type arguments, got {}",
+                self.get_ident_str(ability_name).blue(),
+                ability.type_params.len(),
+                parsed_type_args.len()
+            );
+        }
+        let mut type_args: Vec<TypeId> = Vec::with_capacity(parsed_type_args.len());
+        for parsed_type_arg in parsed_type_args.iter() {
+            type_args.push(self.eval_type_expr(*parsed_type_arg, impl_types_scope_id)?);
+        }
+
+        // `impl Equals for Point auto` asks for the same implementation as `deriving(Equals)`
+        if auto {
+            if !type_params.is_empty() || !type_args.is_empty() {
+                return failf!(span, "Only implementations without type parameters can be 'auto'");
+            }
+            let typed_impl_id = self.derive_ability_impl(
+                ability_id,
+                target_type,
+                ParsedId::AbilityImpl(parsed_id),
+                span,
+            )?;
+            self.pending_derived_impls.push(typed_impl_id);
+            self.ability_impl_ast_mappings.insert(parsed_id, typed_impl_id);
+            return Ok(typed_impl_id);
+        }
+
+        // Scoping / orphan / coherence: For now, let's globally allow only one implementation per (Ability, Type Args, Target Type)
         // Check for existing implementation
-        for existing_impl in &self.ability_impls {
-            if existing_impl.ability_id == ability_id && existing_impl.type_id == target_type {
-                return make_fail_span(
-                    format!(
-                        "Ability '{}' already implemented for type: {}",
-                        &*self.get_ident_str(ability_name).blue(),
-                        self.type_id_to_string(target_type).blue()
-                    ),
+        if let Some(existing_impl) = self.find_ability_impl(ability_id, &type_args, target_type) {
+            return Err(errf!(
+                span,
+                "Ability '{}' already implemented for type: {}",
+                self.ability_with_type_args_to_string(ability_id, &type_args).blue(),
+                self.type_id_to_string(target_type).blue()
+            )
+            .with_label(existing_impl.span, "previous implementation here"));
+        }
+        // Blanket impls can also overlap with each other and with concrete impls, if there's
+        // any type that both of them would apply to
+        let type_param_ids: Vec<TypeId> =
+            type_params.iter().map(|tp| tp.type_param.type_id).collect();
+        for existing_impl in self.ability_impls.iter() {
+            if existing_impl.ability_id != ability_id {
+                continue;
+            }
+            let mut vars = type_param_ids.clone();
+            vars.extend(existing_impl.type_params.iter().map(|tp| tp.type_param.type_id));
+            let mut bindings = Vec::new();
+            let overlaps =
+                self.unify_impl_types(target_type, existing_impl.type_id, &vars, &mut bindings)
+                    && type_args
+                        .iter()
+                        .zip(existing_impl.type_args.iter())
+                        .all(|(a, b)| self.unify_impl_types(*a, *b, &vars, &mut bindings));
+            if overlaps {
+                return Err(errf!(
                     span,
-                );
+                    "Implementation of '{}' for {} overlaps with an existing implementation for {}",
+                    self.ability_with_type_args_to_string(ability_id, &type_args).blue(),
+                    self.type_id_to_string(target_type).blue(),
+                    self.type_id_to_string(existing_impl.type_id).blue()
+                )
+                .with_label(existing_impl.span, "previous implementation here"));
             }
         }
 
-        let ability = self.get_ability(ability_id).clone();
-        let ability_name = self.get_ability(ability_id).name;
+        let ability_name = ability.name;
         let ability_scope = ability.scope_id;
-        let impl_scope_name = self.ast.identifiers.intern(format!(
-            "{}_impl_{}",
-            self.get_ident_str(ability_name),
-            target_type
-        ));
+        let mut impl_scope_name = format!("{}", self.get_ident_str(ability_name));
+        for type_arg in type_args.iter() {
+            impl_scope_name.push_str(&format!("_{}", type_arg));
+        }
+        let impl_scope_name =
+            self.ast.identifiers.intern(format!("{}_impl_{}", impl_scope_name, target_type));
         let impl_scope_id = self.scopes.add_child_scope(
             ability_scope,
             ScopeType::AbilityImpl,
@@ -6467,12 +9604,44 @@ impl TypedModule {
             .scopes
             .get_scope_mut(impl_scope_id)
             .add_type(get_ident!(self, "Self"), target_type);
+        // Bind the blanket impl's type params
+        for type_param in type_params.iter() {
+            let _ = self
+                .scopes
+                .get_scope_mut(impl_scope_id)
+                .add_type(type_param.type_param.ident, type_param.type_param.type_id);
+        }
+        // Bind the ability's type parameters to the provided type arguments
+        for (type_param, type_arg) in ability.type_params.iter().zip(type_args.iter()) {
+            let impl_scope = self.scopes.get_scope_mut(impl_scope_id);
+            if !impl_scope.add_type(type_param.ident, *type_arg)
+                && impl_scope.find_type(type_param.ident) != Some(*type_arg)
+            {
+                return failf!(
+                    span,
+                    "Type parameter '{}' clashes with a type parameter of ability '{}'",
+                    self.get_ident_str(type_param.ident).blue(),
+                    self.get_ident_str(ability_name).blue()
+                );
+            }
+        }
 
         // Note(clone): TypedAbilityFunctionRef is super cheap to clone
 
         for ability_function_ref in &ability.functions {
-            let Some((parsed_impl_function_id, impl_function_span)) =
-                parsed_functions.iter().find_map(|&fn_id| {
+            let ability_parsed_function_id =
+                self.get_function(ability_function_ref.function_id).parsed_id.expect_function();
+            let ability_parsed_function = self.ast.get_function(ability_parsed_function_id);
+            let default_impl = if ability_parsed_function.block.is_some() {
+                Some((ability_parsed_function_id, ability_parsed_function.span))
+            } else {
+                None
+            };
+            // A function the impl leaves out gets the ability's default implementation, if it
+            // has one. Since it's declared in the impl scope, it sees Self as the target type
+            let Some((parsed_impl_function_id, impl_function_span)) = parsed_functions
+                .iter()
+                .find_map(|&fn_id| {
                     let the_fn = self.ast.get_function(fn_id);
                     if the_fn.name == ability_function_ref.function_name {
                         Some((fn_id, the_fn.span))
@@ -6480,6 +9649,7 @@ impl TypedModule {
                         None
                     }
                 })
+                .or(default_impl)
             else {
                 return make_fail_span(
                     format!(
@@ -6497,6 +9667,7 @@ impl TypedModule {
                 None,
                 Some(ability_id),
                 Some(target_type),
+                &type_params,
                 self.get_root_namespace_id(),
             )?;
 
@@ -6551,6 +9722,8 @@ impl TypedModule {
         let typed_impl_id = self.add_ability_impl(TypedAbilityImpl {
             type_id: target_type,
             ability_id,
+            type_args,
+            type_params,
             functions: typed_functions,
             span,
         });
@@ -6566,11 +9739,17 @@ impl TypedModule {
         parsed_ability_impl_id: ParsedAbilityImplId,
         _scope_id: ScopeId,
     ) -> TyperResult<()> {
+        if self.ast.get_ability_impl(parsed_ability_impl_id).auto {
+            // Derived, so its bodies were synthesized after the declaration phase
+            return Ok(());
+        }
         let ability_impl_id = *self.ability_impl_ast_mappings.get(&parsed_ability_impl_id).unwrap();
         let ability_impl = self.get_ability_impl(ability_impl_id);
 
         for impl_fn in ability_impl.functions.clone().iter() {
-            self.eval_function_body(*impl_fn)?
+            if let Err(e) = self.eval_function_body(*impl_fn) {
+                self.report_error(e);
+            }
         }
 
         Ok(())
@@ -6579,7 +9758,9 @@ impl TypedModule {
     fn eval_definition(&mut self, def: ParsedId, scope_id: ScopeId) -> TyperResult<()> {
         match def {
             ParsedId::Namespace(namespace) => {
-                self.eval_namespace(namespace)?;
+                if self.namespace_ast_mappings.contains_key(&namespace) {
+                    self.eval_namespace(namespace)?;
+                }
                 Ok(())
             }
             ParsedId::Constant(_const_val) => {
@@ -6587,23 +9768,40 @@ impl TypedModule {
                 Ok(())
             }
             ParsedId::Function(parsed_function_id) => {
-                let function_declaration_id = self
-                    .function_ast_mappings
-                    .get(&parsed_function_id)
-                    .expect("function predecl lookup failed");
-                self.eval_function_body(*function_declaration_id)?;
+                // If there's no mapping, the declaration failed and its error has been reported
+                if let Some(&function_declaration_id) =
+                    self.function_ast_mappings.get(&parsed_function_id)
+                {
+                    self.eval_function_body(function_declaration_id)?;
+                }
                 Ok(())
             }
             ParsedId::TypeDefn(_type_defn_id) => {
                 // Done in prior phase
                 Ok(())
             }
-            ParsedId::Ability(_ability) => {
-                // Nothing to do in this phase for an ability
+            ParsedId::Ability(parsed_ability_id) => {
+                // Typecheck the default implementations, generically
+                let Some(ability) = self.abilities.iter().find(|a| a.ast_id == parsed_ability_id)
+                else {
+                    return Ok(());
+                };
+                for ability_function_ref in ability.functions.clone().iter() {
+                    let function = self.get_function(ability_function_ref.function_id);
+                    let has_default =
+                        self.ast.get_function(function.parsed_id.expect_function()).block.is_some();
+                    if has_default {
+                        if let Err(e) = self.eval_function_body(ability_function_ref.function_id) {
+                            self.report_error(e);
+                        }
+                    }
+                }
                 Ok(())
             }
             ParsedId::AbilityImpl(ability_impl) => {
-                self.eval_ability_impl(ability_impl, scope_id)?;
+                if self.ability_impl_ast_mappings.contains_key(&ability_impl) {
+                    self.eval_ability_impl(ability_impl, scope_id)?;
+                }
                 Ok(())
             }
             other_id => {
@@ -6616,7 +9814,10 @@ impl TypedModule {
         &mut self,
         parsed_namespace_id: ParsedNamespaceId,
     ) -> TyperResult<()> {
-        let namespace_id = *self.namespace_ast_mappings.get(&parsed_namespace_id).unwrap();
+        let Some(&namespace_id) = self.namespace_ast_mappings.get(&parsed_namespace_id) else {
+            // Namespace failed to be created; its error has been reported
+            return Ok(());
+        };
         let namespace_scope_id = self.namespaces.get(namespace_id).scope_id;
         for &parsed_definition_id in
             self.ast.get_namespace(parsed_namespace_id).definitions.clone().iter()
@@ -6628,11 +9829,11 @@ impl TypedModule {
                     .get_scope_mut(namespace_scope_id)
                     .add_pending_type_defn(parsed_type_defn.name, type_defn_id);
                 if !added {
-                    return failf!(
+                    self.report_error(errf!(
                         parsed_type_defn.span,
                         "Type {} exists",
                         self.get_ident_str(parsed_type_defn.name)
-                    );
+                    ));
                 }
             }
             if let ParsedId::Namespace(namespace_id) = parsed_definition_id {
@@ -6646,14 +9847,18 @@ impl TypedModule {
         &mut self,
         parsed_namespace_id: ParsedNamespaceId,
     ) -> TyperResult<()> {
-        let namespace_id = self.namespace_ast_mappings.get(&parsed_namespace_id).unwrap();
+        let Some(namespace_id) = self.namespace_ast_mappings.get(&parsed_namespace_id) else {
+            return Ok(());
+        };
         let namespace = self.namespaces.get(*namespace_id);
         let namespace_scope_id = namespace.scope_id;
         let parsed_namespace = self.ast.get_namespace(parsed_namespace_id);
 
         for parsed_defn_id in parsed_namespace.definitions.clone().iter() {
             if let ParsedId::TypeDefn(type_defn_id) = parsed_defn_id {
-                self.eval_type_defn(*type_defn_id, namespace_scope_id)?;
+                if let Err(e) = self.eval_type_defn(*type_defn_id, namespace_scope_id) {
+                    self.report_error(e);
+                }
             }
             if let ParsedId::Namespace(namespace_id) = parsed_defn_id {
                 self.eval_namespace_type_eval_phase(*namespace_id)?;
@@ -6667,11 +9872,17 @@ impl TypedModule {
         parsed_namespace_id: ParsedNamespaceId,
     ) -> TyperResult<()> {
         let parsed_namespace = self.ast.get_namespace(parsed_namespace_id);
-        let namespace_id = *self.namespace_ast_mappings.get(&parsed_namespace_id).unwrap();
+        let Some(&namespace_id) = self.namespace_ast_mappings.get(&parsed_namespace_id) else {
+            return Ok(());
+        };
         let namespace = self.namespaces.get(namespace_id);
         let namespace_scope_id = namespace.scope_id;
         for defn in &parsed_namespace.definitions.clone() {
-            self.eval_definition_declaration_phase(*defn, namespace_scope_id, namespace_id)?;
+            if let Err(e) =
+                self.eval_definition_declaration_phase(*defn, namespace_scope_id, namespace_id)
+            {
+                self.report_error(e);
+            }
         }
         Ok(())
     }
@@ -6681,7 +9892,9 @@ impl TypedModule {
         let namespace_id = *self.namespace_ast_mappings.get(&ast_namespace.id).unwrap();
         let ns_scope_id = self.namespaces.get(namespace_id).scope_id;
         for defn in &ast_namespace.definitions {
-            self.eval_definition(*defn, ns_scope_id)?;
+            if let Err(e) = self.eval_definition(*defn, ns_scope_id) {
+                self.report_error(e);
+            }
         }
         Ok(namespace_id)
     }
@@ -6708,6 +9921,7 @@ impl TypedModule {
                     None,
                     None,
                     None,
+                    &[],
                     namespace_id,
                 )?;
                 Ok(())
@@ -6812,59 +10026,48 @@ impl TypedModule {
 
         for defn in &ast_namespace.definitions {
             if let ParsedId::Namespace(namespace_id) = defn {
-                let _namespace_id =
-                    self.eval_namespace_ns_phase(*namespace_id, Some(namespace_scope_id))?;
+                if let Err(e) =
+                    self.eval_namespace_ns_phase(*namespace_id, Some(namespace_scope_id))
+                {
+                    self.report_error(e);
+                }
             }
         }
         Ok(namespace_id)
     }
 
+    /// Runs every phase to completion, even after errors, so that one compile reports every
+    /// independent type error
     pub fn run(&mut self) -> anyhow::Result<()> {
         let root_scope_id = self.scopes.get_root_scope_id();
 
         let root_namespace_id = self.ast.get_root_namespace().id;
 
         // Namespace phase
-        eprintln!("**** ns phase begin ****");
+        info!("**** ns phase begin ****");
         let ns_phase_res = self.eval_namespace_ns_phase(root_namespace_id, None);
         if let Err(e) = ns_phase_res {
-            print_error(&self.ast.spans, &self.ast.sources, &e.message, e.span);
-            self.errors.push(e);
-        }
-        eprintln!("**** ns phase end ****");
-
-        if !self.errors.is_empty() {
-            bail!(
-                "{} failed namespace declaration phase with {} errors",
-                self.name(),
-                self.errors.len()
-            )
+            self.report_error(e);
         }
+        info!("**** ns phase end ****");
 
         // Pending Type declaration phase
-        eprintln!("type **** defn phase begin ****");
+        info!("type **** defn phase begin ****");
         let type_defn_result = self.eval_namespace_type_defn_phase(root_namespace_id);
         if let Err(e) = type_defn_result {
-            print_error(&self.ast.spans, &self.ast.sources, &e.message, e.span);
-            self.errors.push(e);
+            self.report_error(e);
         }
-        if !self.errors.is_empty() {
-            bail!("{} failed type definition phase with {} errors", self.name(), self.errors.len())
-        }
-        eprintln!("**** type defn phase end ****");
+        info!("**** type defn phase end ****");
 
         // Type evaluation phase
-        eprintln!("type **** eval phase begin ****");
+        info!("type **** eval phase begin ****");
         let type_eval_result = self.eval_namespace_type_eval_phase(root_namespace_id);
         if let Err(e) = type_eval_result {
-            print_error(&self.ast.spans, &self.ast.sources, &e.message, e.span);
-            self.errors.push(e);
-        }
-        if !self.errors.is_empty() {
-            bail!("{} failed type evaluation phase with {} errors", self.name(), self.errors.len())
+            self.report_error(e);
         }
         let pendings = self.scopes.all_pending_type_defns_below(self.scopes.get_root_scope_id());
-        if !pendings.is_empty() {
+        // Type defns that failed to evaluate are left pending; that's only a bug if nothing failed
+        if !pendings.is_empty() && self.errors.is_empty() {
             for pending in pendings.iter() {
                 let defn = self.ast.get_type_defn(*pending);
                 dbg!(self.get_ident_str(defn.name));
@@ -6872,45 +10075,77 @@ impl TypedModule {
             panic!("Unevaluated type defns!!!")
         }
 
-        // This just ensures our ARRAY_TYPE_ID constant is correct
-        // Eventually we need a better way of doing this
-        {
-            let array_generic = self.types.get(ARRAY_TYPE_ID).expect_generic();
-            let array_struct = self.types.get(array_generic.inner).expect_struct();
-            debug_assert!(array_generic.type_defn_info.scope == self.scopes.get_root_scope_id());
-            debug_assert!(array_generic.type_defn_info.name == get_ident!(self, "Array"));
-            debug_assert!(array_struct.fields.len() == 3);
-        }
+        // If core types failed to evaluate, these checks are meaningless
+        if self.errors.is_empty() {
+            // This just ensures our ARRAY_TYPE_ID constant is correct
+            // Eventually we need a better way of doing this
+            {
+                let array_generic = self.types.get(ARRAY_TYPE_ID).expect_generic();
+                let array_struct = self.types.get(array_generic.inner).expect_struct();
+                debug_assert!(
+                    array_generic.type_defn_info.scope == self.scopes.get_root_scope_id()
+                );
+                debug_assert!(array_generic.type_defn_info.name == get_ident!(self, "Array"));
+                debug_assert!(array_struct.fields.len() == 3);
+            }
 
-        // This just ensures our STRING_TYPE_ID constant is correct
-        // Eventually we need a better way of doing this
-        {
-            let string_struct = self.types.get(STRING_TYPE_ID).expect_struct();
-            debug_assert!(
-                string_struct.type_defn_info.as_ref().unwrap().scope
-                    == self.scopes.get_root_scope_id()
-            );
-            debug_assert!(
-                string_struct.type_defn_info.as_ref().unwrap().name == get_ident!(self, "string")
-            );
-            debug_assert!(string_struct.fields.len() == 2);
-        }
+            // This just ensures our STRING_TYPE_ID constant is correct
+            // Eventually we need a better way of doing this
+            {
+                let string_struct = self.types.get(STRING_TYPE_ID).expect_struct();
+                debug_assert!(
+                    string_struct.type_defn_info.as_ref().unwrap().scope
+                        == self.scopes.get_root_scope_id()
+                );
+                debug_assert!(
+                    string_struct.type_defn_info.as_ref().unwrap().name
+                        == get_ident!(self, "string")
+                );
+                debug_assert!(string_struct.fields.len() == 2);
+            }
 
-        // This just ensures our OPTIONAL_TYPE_ID constant is correct
-        // Eventually we need a better way of doing this
-        {
-            let optional_generic = self.types.get(OPTIONAL_TYPE_ID).expect_generic();
-            let inner = self.types.get(optional_generic.inner);
-            debug_assert!(optional_generic.type_defn_info.scope == self.scopes.get_root_scope_id());
-            debug_assert!(optional_generic.type_defn_info.name == get_ident!(self, "Opt"));
-            debug_assert!(inner.as_enum().unwrap().variants.len() == 2);
+            // This just ensures our OPTIONAL_TYPE_ID constant is correct
+            // Eventually we need a better way of doing this
+            {
+                let optional_generic = self.types.get(OPTIONAL_TYPE_ID).expect_generic();
+                let inner = self.types.get(optional_generic.inner);
+                debug_assert!(
+                    optional_generic.type_defn_info.scope == self.scopes.get_root_scope_id()
+                );
+                debug_assert!(optional_generic.type_defn_info.name == get_ident!(self, "Opt"));
+                debug_assert!(inner.as_enum().unwrap().variants.len() == 2);
+            }
+
+            // This just ensures our RESULT_TYPE_ID constant is correct
+            // Eventually we need a better way of doing this
+            {
+                let result_generic = self.types.get(RESULT_TYPE_ID).expect_generic();
+                let inner = self.types.get(result_generic.inner);
+                debug_assert!(
+                    result_generic.type_defn_info.scope == self.scopes.get_root_scope_id()
+                );
+                debug_assert!(result_generic.type_defn_info.name == get_ident!(self, "Result"));
+                debug_assert!(inner.as_enum().unwrap().variants.len() == 2);
+            }
+
+            // This just ensures our RANGE_TYPE_ID constant is correct
+            // Eventually we need a better way of doing this
+            {
+                let range_generic = self.types.get(RANGE_TYPE_ID).expect_generic();
+                let range_struct = self.types.get(range_generic.inner).expect_struct();
+                debug_assert!(
+                    range_generic.type_defn_info.scope == self.scopes.get_root_scope_id()
+                );
+                debug_assert!(range_generic.type_defn_info.name == get_ident!(self, "Range"));
+                debug_assert!(range_struct.fields.len() == 4);
+            }
         }
 
-        eprintln!("**** type eval phase end ****");
+        info!("**** type eval phase end ****");
 
         // Everything else declaration phase
         let root_ns_id = NamespaceId(0);
-        eprintln!("**** declaration phase begin ****");
+        info!("**** declaration phase begin ****");
         for &parsed_definition_id in self.ast.get_root_namespace().definitions.clone().iter() {
             let result = self.eval_definition_declaration_phase(
                 parsed_definition_id,
@@ -6918,33 +10153,131 @@ impl TypedModule {
                 root_ns_id,
             );
             if let Err(e) = result {
-                print_error(&self.ast.spans, &self.ast.sources, &e.message, e.span);
-                self.errors.push(e);
+                self.report_error(e);
             }
         }
-        eprintln!("**** declaration phase end ****");
-        if !self.errors.is_empty() {
-            bail!("{} failed declaration phase with {} errors", self.name(), self.errors.len())
+        info!("**** declaration phase end ****");
+
+        if self.errors.is_empty() {
+            debug_assert!(self.get_ability(EQUALS_ABILITY_ID).name == get_ident!(self, "Equals"));
+            debug_assert!(
+                self.get_ability(ITERABLE_ABILITY_ID).name == get_ident!(self, "Iterable")
+            );
+            debug_assert!(self.get_ability(HASH_ABILITY_ID).name == get_ident!(self, "Hash"));
+            debug_assert!(self.get_ability(COMPARE_ABILITY_ID).name == get_ident!(self, "Compare"));
+            debug_assert!(self.get_ability(BITWISE_ABILITY_ID).name == get_ident!(self, "Bitwise"));
         }
 
-        debug_assert!(self.get_ability(EQUALS_ABILITY_ID).name == get_ident!(self, "Equals"));
-        debug_assert!(self.get_ability(BITWISE_ABILITY_ID).name == get_ident!(self, "Bitwise"));
+        // A `deriving` clause can name an ability defined after its type, so derived
+        // implementations are declared once every ability and implementation is. They can use
+        // any other implementation, so their bodies are synthesized after that
+        let type_defn_ids: Vec<ParsedTypeDefnId> =
+            self.ast.type_defns.iter().map(|type_defn| type_defn.id).collect();
+        for type_defn_id in type_defn_ids.into_iter() {
+            if let Err(e) = self.eval_type_defn_deriving(type_defn_id) {
+                self.report_error(e);
+            }
+        }
+        for ability_impl_id in std::mem::take(&mut self.pending_derived_impls) {
+            if let Err(e) = self.synth_derived_impl(ability_impl_id) {
+                self.report_error(e);
+            }
+        }
 
         // Everything else evaluation phase
         for &parsed_definition_id in self.ast.get_root_namespace().definitions.clone().iter() {
             let result = self.eval_definition(parsed_definition_id, root_scope_id);
             if let Err(e) = result {
-                print_error(&self.ast.spans, &self.ast.sources, &e.message, e.span);
-                self.errors.push(e);
+                self.report_error(e);
             }
         }
         if !self.errors.is_empty() {
             // debug!("{}", self);
             bail!("{} failed typechecking with {} errors", self.name(), self.errors.len())
         }
+        self.check_unused_variables();
+        self.check_unused_functions();
         Ok(())
     }
 
+    fn check_unused_variables(&mut self) {
+        // Generic function bodies are checked once per specialization, so one declaration can
+        // produce several variables; it only counts as unused if every copy is
+        let mut usages_by_span: BTreeMap<SpanId, (VariableId, LocalUsage)> = BTreeMap::new();
+        for (&variable_id, usage) in self.local_usages.iter() {
+            let entry = usages_by_span.entry(usage.span).or_insert((variable_id, *usage));
+            entry.1.read |= usage.read;
+            entry.1.reassigned |= usage.reassigned;
+        }
+        for (span, (variable_id, usage)) in usages_by_span.into_iter() {
+            if self.ast.sources.source_by_span(self.ast.spans.get(span)).is_library {
+                continue;
+            }
+            let variable = self.variables.get_variable(variable_id);
+            let name = self.get_ident_str(variable.name).to_string();
+            if name.starts_with('_') {
+                continue;
+            }
+            if !usage.read && !usage.reassigned {
+                self.report_warning(
+                    Diagnostic::warning(
+                        Lint::UnusedVariable,
+                        format!("Unused variable '{name}'"),
+                        span,
+                    )
+                    .with_help(format!("if this is intentional, name it '_{name}'")),
+                );
+            } else if variable.is_mutable && !usage.reassigned {
+                self.report_warning(
+                    Diagnostic::warning(
+                        Lint::UnusedMut,
+                        format!("Variable '{name}' is declared mut but never reassigned"),
+                        span,
+                    )
+                    .with_help("declare it with `val` instead"),
+                );
+            }
+        }
+    }
+
+    fn check_unused_functions(&mut self) {
+        let called: HashSet<ParsedFunctionId> = self
+            .called_functions
+            .iter()
+            .filter_map(|function_id| match self.get_function(*function_id).parsed_id {
+                ParsedId::Function(parsed_function_id) => Some(parsed_function_id),
+                _ => None,
+            })
+            .collect();
+        let mut unused = Vec::new();
+        for (&parsed_function_id, &function_id) in self.function_ast_mappings.iter() {
+            let function = self.get_function(function_id);
+            let is_user_function = matches!(function.kind, TypedFunctionKind::Standard)
+                && function.linkage == Linkage::Standard
+                && function.intrinsic_type.is_none();
+            if !is_user_function || called.contains(&parsed_function_id) {
+                continue;
+            }
+            let span = self.ast.spans.get(function.span);
+            if self.ast.sources.source_by_span(span).is_library {
+                continue;
+            }
+            let name = self.get_ident_str(function.name);
+            if name == "main" || name.starts_with('_') {
+                continue;
+            }
+            unused.push((function.span, name.to_string()));
+        }
+        unused.sort_by_key(|(span, _)| *span);
+        for (span, name) in unused.into_iter() {
+            self.report_warning(Diagnostic::warning(
+                Lint::UnusedFunction,
+                format!("Unused function '{name}'"),
+                span,
+            ));
+        }
+    }
+
     pub fn get_span_for_type_id(&self, type_id: TypeId) -> Option<SpanId> {
         let t = self.types.get(type_id);
         t.ast_node().map(|parsed_id| self.ast.get_span_for_id(parsed_id))
@@ -6971,14 +10304,59 @@ impl TypedModule {
         s
     }
 
+    /// `max_array_len` is the longest array pattern being checked; arrays get a constructor for
+    /// each length up to it, plus one for every longer array. `array_elements_elided` is set if
+    /// an array's elements had too many combinations to enumerate
     fn generate_constructors_for_type(
         &self,
         type_id: TypeId,
+        max_array_len: usize,
+        array_elements_elided: &mut bool,
         span_id: SpanId,
     ) -> Vec<PatternConstructor> {
         if type_id == STRING_TYPE_ID {
             return vec![PatternConstructor::String];
         }
+        if let Some(array_type) = self.types.get(type_id).as_array_instance() {
+            let mut element_ctors = self.generate_constructors_for_type(
+                array_type.element_type,
+                max_array_len,
+                array_elements_elided,
+                span_id,
+            );
+            // Every combination of element constructors at every length grows exponentially, so
+            // past a limit the elements are left for wildcards and bindings to cover
+            let combinations = element_ctors.len().checked_pow(max_array_len as u32);
+            if combinations.map_or(true, |c| c > MAX_ARRAY_PATTERN_CONSTRUCTORS) {
+                element_ctors = vec![PatternConstructor::TypeVariable];
+                *array_elements_elided = true;
+            }
+            let mut result = Vec::new();
+            let mut prefixes: Vec<Vec<PatternConstructor>> = vec![vec![]];
+            for len in 0..=max_array_len {
+                if len > 0 {
+                    let mut new_prefixes = Vec::new();
+                    for prefix in prefixes.iter() {
+                        for element_ctor in element_ctors.iter() {
+                            let mut new_prefix = prefix.clone();
+                            new_prefix.push(element_ctor.clone());
+                            new_prefixes.push(new_prefix);
+                        }
+                    }
+                    prefixes = new_prefixes;
+                }
+                for prefix in prefixes.iter() {
+                    result.push(PatternConstructor::Array {
+                        elements: prefix.clone(),
+                        has_more: false,
+                    });
+                }
+            }
+            for prefix in prefixes.into_iter() {
+                result.push(PatternConstructor::Array { elements: prefix, has_more: true });
+            }
+            return result;
+        }
         match self.types.get(type_id) {
             Type::Unit(_) => vec![PatternConstructor::Unit],
             Type::Char(_) => vec![PatternConstructor::Char],
@@ -6995,7 +10373,12 @@ impl TypedModule {
                         vec![PatternConstructor::Enum { variant_name: v.name, inner: None }]
                     }
                     Some(payload) => self
-                        .generate_constructors_for_type(*payload, span_id)
+                        .generate_constructors_for_type(
+                            *payload,
+                            max_array_len,
+                            array_elements_elided,
+                            span_id,
+                        )
                         .into_iter()
                         .map(|inner| PatternConstructor::Enum {
                             variant_name: v.name,
@@ -7009,7 +10392,12 @@ impl TypedModule {
                 let mut all_field_ctors: Vec<Vec<(Identifier, PatternConstructor)>> = vec![];
                 for field in struc.fields.iter() {
                     let field_ctors_iter = self
-                        .generate_constructors_for_type(field.type_id, span_id)
+                        .generate_constructors_for_type(
+                            field.type_id,
+                            max_array_len,
+                            array_elements_elided,
+                            span_id,
+                        )
                         .into_iter()
                         .map(|pat| (field.name, pat))
                         .collect::<Vec<_>>();
@@ -7034,7 +10422,7 @@ impl TypedModule {
                 result
             }
             _ => {
-                eprintln!("unhandled match type {}", self.type_id_to_string(type_id));
+                debug!("unhandled match type {}", self.type_id_to_string(type_id));
                 vec![]
             }
         }
@@ -7044,6 +10432,10 @@ impl TypedModule {
         match (pattern, ctor) {
             (TypedPattern::Wildcard(_), _) => true,
             (TypedPattern::Variable(_), _) => true,
+            (TypedPattern::Binding(binding), ctor) => self.pattern_matches(&binding.inner, ctor),
+            (TypedPattern::Or(or_pattern), ctor) => {
+                or_pattern.alternatives.iter().any(|alt| self.pattern_matches(alt, ctor))
+            }
             (TypedPattern::LiteralUnit(_), PatternConstructor::Unit) => true,
             (TypedPattern::LiteralBool(true, _), PatternConstructor::BoolTrue) => true,
             (TypedPattern::LiteralBool(false, _), PatternConstructor::BoolFalse) => true,
@@ -7058,6 +10450,18 @@ impl TypedModule {
                     false
                 }
             }
+            (TypedPattern::Array(array_pat), PatternConstructor::Array { elements, has_more }) => {
+                let length_matches = match array_pat.rest {
+                    None => !has_more && array_pat.elements.len() == elements.len(),
+                    Some(_) => array_pat.elements.len() <= elements.len(),
+                };
+                length_matches
+                    && array_pat.elements.iter().zip(elements.iter()).all(
+                        |(element_pat, element_ctor)| {
+                            self.pattern_matches(element_pat, element_ctor)
+                        },
+                    )
+            }
             (TypedPattern::Struct(struc), PatternConstructor::Struct { fields }) => {
                 // Because we treat all struct patterns as caring only about the fields they mention,
                 // an empty pattern already matches. So we iterate over the fields this pattern does
@@ -7087,6 +10491,167 @@ impl TypedModule {
     /// Synthesis of Typed nodes
     ////////////////////////////
 
+    fn synth_next_call(&self, next_function_id: FunctionId, iterator: &TypedExpr) -> TypedExpr {
+        TypedExpr::FunctionCall(Call {
+            callee: Callee::StaticFunction(next_function_id),
+            args: vec![iterator.clone()],
+            type_args: Vec::new(),
+            ret_type: self.get_function(next_function_id).ret_type,
+            span: iterator.get_span(),
+        })
+    }
+
+    /// `if range.step <= 0 { crash(...) }`, since a range that never counts up never ends
+    fn synth_range_step_check(
+        &mut self,
+        range_variable: &SynthedVariable,
+        scope_id: ScopeId,
+        span: SpanId,
+    ) -> TyperResult<TypedStmt> {
+        let step = self.synth_range_field(&range_variable.variable_expr, 2);
+        let step_type = step.get_type();
+        let Type::Integer(integer_type) = self.types.get(step_type) else {
+            panic!("Range step must be an integer")
+        };
+        let zero = TypedIntegerValue::zero(*integer_type);
+        let step_not_positive = TypedExpr::BinaryOp(BinaryOp {
+            kind: BinaryOpKind::LessEqual,
+            ty: BOOL_TYPE_ID,
+            lhs: Box::new(step),
+            rhs: Box::new(TypedExpr::Integer(TypedIntegerExpr { value: zero, span })),
+            span,
+        });
+        let crash_call = self.synth_function_call(
+            qident!(self, span, "crash"),
+            span,
+            scope_id,
+            Some((vec![], vec![TypedExpr::Str("Range step must be positive".to_string(), span)])),
+        )?;
+        Ok(TypedStmt::Expr(Box::new(TypedExpr::If(Box::new(TypedIf {
+            condition: step_not_positive,
+            consequent: crash_call,
+            alternate: TypedExpr::Unit(span),
+            ty: UNIT_TYPE_ID,
+            span,
+        })))))
+    }
+
+    /// Moves a range loop's `next_value` up by the step, unless that would pass the end.
+    /// Comparing the distance left to the step, rather than the stepped value to the end,
+    /// means we never step past the largest value of the type
+    ///
+    /// val remaining = range.end - next_value;
+    /// has_next = remaining < 0 or remaining > range.step or (range.inclusive and remaining == range.step);
+    /// if has_next { next_value = next_value + range.step };
+    fn synth_range_advance(
+        &mut self,
+        next_value: &SynthedVariable,
+        has_next: &SynthedVariable,
+        range_variable: &SynthedVariable,
+        scope_id: ScopeId,
+    ) -> Vec<TypedStmt> {
+        let span = range_variable.variable_expr.get_span();
+        let range = &range_variable.variable_expr;
+        let value_type = next_value.variable_expr.get_type();
+        let Type::Integer(integer_type) = self.types.get(value_type) else {
+            panic!("Range bounds must be integers")
+        };
+        let integer_type = *integer_type;
+        let binop = |kind, ty, lhs: TypedExpr, rhs: TypedExpr| {
+            TypedExpr::BinaryOp(BinaryOp { kind, ty, lhs: Box::new(lhs), rhs: Box::new(rhs), span })
+        };
+        let remaining = self.synth_variable_defn(
+            get_ident!(self, "range_remaining"),
+            binop(
+                BinaryOpKind::Subtract,
+                value_type,
+                self.synth_range_field(range, 1),
+                next_value.variable_expr.clone(),
+            ),
+            false,
+            false,
+            scope_id,
+        );
+        let remaining_expr = remaining.variable_expr.clone();
+        let steps_inside = binop(
+            BinaryOpKind::Greater,
+            BOOL_TYPE_ID,
+            remaining_expr.clone(),
+            self.synth_range_field(range, 2),
+        );
+        let steps_onto_inclusive_end = binop(
+            BinaryOpKind::And,
+            BOOL_TYPE_ID,
+            self.synth_range_field(range, 3),
+            binop(
+                BinaryOpKind::Equals,
+                BOOL_TYPE_ID,
+                remaining_expr.clone(),
+                self.synth_range_field(range, 2),
+            ),
+        );
+        let mut can_step =
+            binop(BinaryOpKind::Or, BOOL_TYPE_ID, steps_inside, steps_onto_inclusive_end);
+        // The subtraction wraps around to a negative only when the distance left is larger than
+        // any step could be
+        if integer_type.is_signed() {
+            let zero = TypedExpr::Integer(TypedIntegerExpr {
+                value: TypedIntegerValue::zero(integer_type),
+                span,
+            });
+            let wrapped = binop(BinaryOpKind::Less, BOOL_TYPE_ID, remaining_expr, zero);
+            can_step = binop(BinaryOpKind::Or, BOOL_TYPE_ID, wrapped, can_step);
+        }
+        let update_has_next = TypedStmt::Assignment(Box::new(Assignment {
+            destination: Box::new(has_next.variable_expr.clone()),
+            value: Box::new(can_step),
+            span,
+        }));
+        let step_value = TypedExpr::Block(self.synth_block(
+            vec![TypedStmt::Assignment(Box::new(Assignment {
+                destination: Box::new(next_value.variable_expr.clone()),
+                value: Box::new(binop(
+                    BinaryOpKind::Add,
+                    value_type,
+                    next_value.variable_expr.clone(),
+                    self.synth_range_field(range, 2),
+                )),
+                span,
+            }))],
+            scope_id,
+            span,
+        ));
+        let advance = TypedStmt::Expr(Box::new(TypedExpr::If(Box::new(TypedIf {
+            condition: has_next.variable_expr.clone(),
+            consequent: step_value,
+            alternate: TypedExpr::Unit(span),
+            ty: UNIT_TYPE_ID,
+            span,
+        }))));
+        vec![remaining.defn_stmt, update_has_next, advance]
+    }
+
+    /// `range.start`, `range.end`, `range.step`, or `range.inclusive`, by field index
+    fn synth_range_field(&self, range: &TypedExpr, field_index: usize) -> TypedExpr {
+        let field = &self.types.get(range.get_type()).expect_struct().fields[field_index];
+        TypedExpr::StructFieldAccess(FieldAccess {
+            base: Box::new(range.clone()),
+            target_field: field.name,
+            target_field_index: field.index,
+            ty: field.type_id,
+            span: range.get_span(),
+        })
+    }
+
+    fn optional_some_variant(&self, optional_type_id: TypeId) -> TypedEnumVariant {
+        self.types
+            .get(optional_type_id)
+            .expect_enum()
+            .variant_by_name(get_ident!(self, "Some"))
+            .unwrap()
+            .clone()
+    }
+
     fn synth_optional_some(&mut self, parsed_id: ParsedId, expression: TypedExpr) -> TypedExpr {
         let optional_type =
             self.instantiate_generic_type(OPTIONAL_TYPE_ID, vec![expression.get_type()], parsed_id);
@@ -7160,6 +10725,64 @@ impl TypedModule {
         })
     }
 
+    /// Desugars to `Array::new` and a `push` of each element
+    fn synth_array_literal(
+        &mut self,
+        elements: Vec<TypedExpr>,
+        element_type: TypeId,
+        scope_id: ScopeId,
+        span: SpanId,
+    ) -> TyperResult<TypedExpr> {
+        let element_count = elements.len();
+        let mut array_lit_block = self.synth_block(vec![], scope_id, span);
+        let array_lit_scope = array_lit_block.scope_id;
+        let array_new_fn_call = self.synth_function_call(
+            qident!(self, span, ["Array"], "new"),
+            span,
+            array_lit_scope,
+            Some((
+                vec![element_type],
+                vec![TypedExpr::Integer(TypedIntegerExpr {
+                    value: TypedIntegerValue::U64(element_count as u64),
+                    span,
+                })],
+            )),
+        )?;
+        let array_new_expr = self.synth_reference(array_new_fn_call);
+        let array_variable = self.synth_variable_defn(
+            get_ident!(self, "array_literal"),
+            array_new_expr,
+            false,
+            false,
+            array_lit_scope,
+        );
+        // The array escapes wherever its literal does, and takes any closure elements with it
+        if let Some(captured_variable_id) =
+            elements.iter().find_map(|element| self.find_reference_capture(element))
+        {
+            self.reference_closure_variables
+                .insert(array_variable.variable_id, captured_variable_id);
+        }
+        let mut set_elements = Vec::with_capacity(element_count);
+        for element_value_expr in elements.into_iter() {
+            let element_set = TypedStmt::Expr(Box::new(self.synth_function_call(
+                qident!(self, span, ["Array"], "push"),
+                span,
+                array_lit_scope,
+                Some((
+                    vec![element_type],
+                    vec![array_variable.variable_expr.clone(), element_value_expr],
+                )),
+            )?));
+            set_elements.push(element_set);
+        }
+        array_lit_block.statements.push(array_variable.defn_stmt);
+        array_lit_block.statements.extend(set_elements);
+        let dereference_array_literal = self.synth_dereference(array_variable.variable_expr);
+        array_lit_block.push_expr(dereference_array_literal);
+        Ok(TypedExpr::Block(array_lit_block))
+    }
+
     fn synth_block(
         &mut self,
         statements: Vec<TypedStmt>,
@@ -1,11 +1,802 @@
+use crate::{errf, failf};
+
 use super::*;
 
+/// The abilities that `deriving(...)` and `impl ... auto` know how to synthesize
+const DERIVABLE_ABILITIES: [AbilityId; 3] = [EQUALS_ABILITY_ID, SHOW_ABILITY_ID, HASH_ABILITY_ID];
+
+/// The operator abilities, which integers and floats implement with their built-in operators
+/// without anything written out in core
+const SCALAR_ABILITIES: [AbilityId; 7] = [
+    ADD_ABILITY_ID,
+    SUB_ABILITY_ID,
+    MUL_ABILITY_ID,
+    DIV_ABILITY_ID,
+    REM_ABILITY_ID,
+    NEG_ABILITY_ID,
+    COMPARE_ABILITY_ID,
+];
+
 impl TypedModule {
+    /// Declares a derived implementation of `ability_id` for `target_type_id`. The ability's
+    /// default functions are declared as usual, and the rest get synthesized bodies, field by
+    /// field for a struct and variant by variant for an enum, in `synth_derived_impl`
     pub fn derive_ability_impl(
         &mut self,
-        _ability_id: AbilityId,
-        _target_type_id: TypeId,
-    ) -> TyperResult<TypedAbilityImpl> {
-        unimplemented!("derive_ability_impl")
+        ability_id: AbilityId,
+        target_type_id: TypeId,
+        parsed_id: ParsedId,
+        span: SpanId,
+    ) -> TyperResult<AbilityImplId> {
+        let ability = self.get_ability(ability_id).clone();
+        if !DERIVABLE_ABILITIES.contains(&ability_id) {
+            return failf!(
+                span,
+                "Ability '{}' cannot be derived; only Equals, Show and Hash can",
+                self.get_ident_str(ability.name).blue()
+            );
+        }
+        if !matches!(self.types.get(target_type_id), Type::Struct(_) | Type::Enum(_)) {
+            return failf!(
+                span,
+                "Cannot derive '{}' for {}; only structs and enums can be derived",
+                self.get_ident_str(ability.name).blue(),
+                self.type_id_to_string(target_type_id).blue()
+            );
+        }
+        let existing_impl = self.find_ability_impl(ability_id, &[], target_type_id).or(self
+            .find_blanket_ability_impl(ability_id, &[], target_type_id)
+            .map(|(imp, _)| imp));
+        if let Some(existing_impl) = existing_impl {
+            return Err(errf!(
+                span,
+                "Ability '{}' already implemented for type: {}",
+                self.get_ident_str(ability.name).blue(),
+                self.type_id_to_string(target_type_id).blue()
+            )
+            .with_label(existing_impl.span, "previous implementation here"));
+        }
+        self.declare_derived_impl(ability_id, target_type_id, parsed_id, span)
+    }
+
+    fn declare_derived_impl(
+        &mut self,
+        ability_id: AbilityId,
+        target_type_id: TypeId,
+        parsed_id: ParsedId,
+        span: SpanId,
+    ) -> TyperResult<AbilityImplId> {
+        let ability = self.get_ability(ability_id).clone();
+        let impl_scope_name = self.ast.identifiers.intern(format!(
+            "{}_derived_{}",
+            self.get_ident_str(ability.name),
+            target_type_id
+        ));
+        let impl_scope_id = self.scopes.add_child_scope(
+            ability.scope_id,
+            ScopeType::AbilityImpl,
+            None,
+            Some(impl_scope_name),
+        );
+        let self_ident = self.ast.identifiers.intern("Self");
+        let _ = self.scopes.get_scope_mut(impl_scope_id).add_type(self_ident, target_type_id);
+
+        let mut functions = Vec::with_capacity(ability.functions.len());
+        for ability_function_ref in ability.functions.iter() {
+            let parsed_function_id =
+                self.get_function(ability_function_ref.function_id).parsed_id.expect_function();
+            let function_id = if self.ast.get_function(parsed_function_id).block.is_some() {
+                self.eval_function_predecl(
+                    parsed_function_id,
+                    impl_scope_id,
+                    None,
+                    Some(ability_id),
+                    Some(target_type_id),
+                    &[],
+                    self.get_root_namespace_id(),
+                )?
+            } else {
+                self.declare_derived_function(
+                    ability_id,
+                    ability_function_ref.function_id,
+                    target_type_id,
+                    impl_scope_id,
+                    parsed_id,
+                    span,
+                )
+            };
+            functions.push(function_id);
+        }
+
+        Ok(self.add_ability_impl(TypedAbilityImpl {
+            type_id: target_type_id,
+            ability_id,
+            type_args: Vec::new(),
+            type_params: Vec::new(),
+            functions,
+            span,
+        }))
+    }
+
+    /// Fills in the bodies of a derived implementation. The implementation must already be
+    /// registered, so that a type that contains itself, say through an `Array`, finds it
+    /// rather than deriving it again
+    pub fn synth_derived_impl(&mut self, ability_impl_id: AbilityImplId) -> TyperResult<()> {
+        let ability_impl = self.get_ability_impl(ability_impl_id);
+        let ability_id = ability_impl.ability_id;
+        for function_id in ability_impl.functions.clone().into_iter() {
+            if let ParsedId::Function(_) = self.get_function(function_id).parsed_id {
+                self.eval_function_body(function_id)?;
+            } else {
+                self.synth_derived_function_body(ability_id, function_id)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Derives `ability_id` for `type_id` the first time it's needed, if the type's definition
+    /// asks for it. Instances of generic types are only derived on demand, since there's no
+    /// AST to specialize the derived functions from
+    pub fn ensure_derived_impl(
+        &mut self,
+        ability_id: AbilityId,
+        type_id: TypeId,
+    ) -> TyperResult<()> {
+        if self.find_ability_impl(ability_id, &[], type_id).is_some()
+            || !self.type_derives_ability(type_id, ability_id)
+        {
+            return Ok(());
+        }
+        if self.is_scalar_impl(type_id, ability_id) {
+            let ability_ast_id = self.get_ability(ability_id).ast_id;
+            let span = self.ast.get_ability(ability_ast_id).span;
+            let ability_impl_id = self.declare_derived_impl(
+                ability_id,
+                type_id,
+                ParsedId::Ability(ability_ast_id),
+                span,
+            )?;
+            return self.synth_derived_impl(ability_impl_id);
+        }
+        let (type_defn_id, _) = self.deriving_type_defn(type_id).unwrap();
+        let span = self.ast.get_type_defn(type_defn_id).span;
+        let ability_impl_id =
+            self.derive_ability_impl(ability_id, type_id, ParsedId::TypeDefn(type_defn_id), span)?;
+        self.synth_derived_impl(ability_impl_id)
+    }
+
+    /// Derives everything in `type_id`'s `deriving` clause that isn't yet, for lookups that
+    /// don't know which ability they want
+    pub fn ensure_all_derived_impls(&mut self, type_id: TypeId) -> TyperResult<()> {
+        for ability_id in DERIVABLE_ABILITIES.into_iter().chain(SCALAR_ABILITIES) {
+            self.ensure_derived_impl(ability_id, type_id)?;
+        }
+        Ok(())
+    }
+
+    /// Whether `type_id` implements `ability_id` by way of a `deriving` clause. Like Rust's
+    /// derive, an instance of a generic type only does if its type arguments implement it too
+    pub fn type_derives_ability(&self, type_id: TypeId, ability_id: AbilityId) -> bool {
+        if self.is_scalar_impl(type_id, ability_id) {
+            return true;
+        }
+        let Some((type_defn_id, scope_id)) = self.deriving_type_defn(type_id) else {
+            return false;
+        };
+        let derives = self.ast.get_type_defn(type_defn_id).deriving.iter().any(|ability_name| {
+            let found = self.scopes.find_ability_namespaced(
+                scope_id,
+                ability_name,
+                &self.namespaces,
+                &self.ast.identifiers,
+            );
+            matches!(found, Ok(Some(found_id)) if found_id == ability_id)
+        });
+        derives
+            && match self.types.get_generic_instance_info(type_id) {
+                None => true,
+                Some(instance_info) => instance_info
+                    .param_values
+                    .iter()
+                    .all(|param| self.type_implements_ability(*param, ability_id)),
+            }
+    }
+
+    /// Every integer and float implements the operator abilities, except that only signed
+    /// integers can be negated. Every scalar implements Equals, and every one but the floats
+    /// implements Hash, so they satisfy `T: Equals` and `T: Hash`
+    fn is_scalar_impl(&self, type_id: TypeId, ability_id: AbilityId) -> bool {
+        let is_equals_or_hash = ability_id == EQUALS_ABILITY_ID || ability_id == HASH_ABILITY_ID;
+        match self.types.get(type_id) {
+            Type::Integer(int_type) => {
+                is_equals_or_hash
+                    || (SCALAR_ABILITIES.contains(&ability_id)
+                        && (ability_id != NEG_ABILITY_ID || int_type.is_signed()))
+            }
+            Type::Float(_) => {
+                ability_id == EQUALS_ABILITY_ID || SCALAR_ABILITIES.contains(&ability_id)
+            }
+            Type::Unit(_) | Type::Bool(_) | Type::Char(_) => is_equals_or_hash,
+            _ => false,
+        }
+    }
+
+    /// Declares the derived implementations requested by a type definition's `deriving`
+    /// clause. Generic types are checked here but derived per instance, on demand
+    pub fn eval_type_defn_deriving(&mut self, type_defn_id: ParsedTypeDefnId) -> TyperResult<()> {
+        let parsed_type_defn = self.ast.get_type_defn(type_defn_id);
+        let deriving = parsed_type_defn.deriving.clone();
+        let is_generic = !parsed_type_defn.type_params.is_empty();
+        let is_alias = parsed_type_defn.flags.is_alias();
+        let span = parsed_type_defn.span;
+        if deriving.is_empty() {
+            return Ok(());
+        }
+        if is_alias {
+            return failf!(span, "Cannot derive abilities for a type alias");
+        }
+        let Some(type_id) = self.types.find_type_defn_mapping(type_defn_id) else {
+            // The type failed to evaluate; its error has been reported
+            return Ok(());
+        };
+        let Some((_, scope_id)) = self.deriving_type_defn(type_id) else {
+            return failf!(
+                span,
+                "Cannot derive abilities for {}; only structs and enums can be derived",
+                self.type_id_to_string(type_id).blue()
+            );
+        };
+        for ability_name in deriving.iter() {
+            let ability_id = self
+                .scopes
+                .find_ability_namespaced(
+                    scope_id,
+                    ability_name,
+                    &self.namespaces,
+                    &self.ast.identifiers,
+                )?
+                .ok_or(errf!(
+                    ability_name.span,
+                    "Ability does not exist: {}",
+                    self.get_ident_str(ability_name.name)
+                ))?;
+            if !DERIVABLE_ABILITIES.contains(&ability_id) {
+                return failf!(
+                    ability_name.span,
+                    "Ability '{}' cannot be derived; only Equals, Show and Hash can",
+                    self.get_ident_str(ability_name.name).blue()
+                );
+            }
+            if !is_generic {
+                let ability_impl_id = self.derive_ability_impl(
+                    ability_id,
+                    type_id,
+                    ParsedId::TypeDefn(type_defn_id),
+                    span,
+                )?;
+                self.pending_derived_impls.push(ability_impl_id);
+            }
+        }
+        Ok(())
+    }
+
+    /// The type definition that holds `type_id`'s `deriving` clause, and the scope its
+    /// ability names resolve in. For an instance of a generic type, that's the generic type's
+    fn deriving_type_defn(&self, type_id: TypeId) -> Option<(ParsedTypeDefnId, ScopeId)> {
+        if let Some(instance_info) = self.types.get_generic_instance_info(type_id) {
+            let generic = self.types.get(instance_info.generic_parent).expect_generic();
+            return Some((generic.ast_id, generic.type_defn_info.scope));
+        }
+        let type_defn_info = match self.types.get(type_id) {
+            Type::Generic(generic) => {
+                return Some((generic.ast_id, generic.type_defn_info.scope));
+            }
+            Type::Struct(struct_type) => struct_type.type_defn_info.as_ref(),
+            Type::Enum(enum_type) => enum_type.type_defn_info.as_ref(),
+            _ => None,
+        }?;
+        match type_defn_info.ast_id {
+            ParsedId::TypeDefn(type_defn_id) => Some((type_defn_id, type_defn_info.scope)),
+            _ => None,
+        }
+    }
+
+    /// Declares one of an ability's functions for a derived implementation. Every function
+    /// of a derivable ability only takes `Self` parameters, so they are all `target_type_id`,
+    /// as is a `Self` return type
+    fn declare_derived_function(
+        &mut self,
+        ability_id: AbilityId,
+        ability_function_id: FunctionId,
+        target_type_id: TypeId,
+        impl_scope_id: ScopeId,
+        parsed_id: ParsedId,
+        span: SpanId,
+    ) -> FunctionId {
+        let ability_function = self.get_function(ability_function_id);
+        let function_name = ability_function.name;
+        let self_type_id = ability_function.type_params[0].type_param.type_id;
+        let ret_type = if ability_function.ret_type == self_type_id {
+            target_type_id
+        } else {
+            ability_function.ret_type
+        };
+        let param_names: Vec<Identifier> =
+            ability_function.params.iter().map(|param| param.name).collect();
+        let name = self.ast.identifiers.intern(format!(
+            "{}_impl_{}",
+            self.get_ident_str(function_name),
+            target_type_id
+        ));
+        let fn_scope_id =
+            self.scopes.add_child_scope(impl_scope_id, ScopeType::FunctionScope, None, Some(name));
+        let mut params = Vec::with_capacity(param_names.len());
+        for (position, param_name) in param_names.into_iter().enumerate() {
+            let variable_id = self.variables.add_variable(Variable {
+                name: param_name,
+                type_id: target_type_id,
+                is_mutable: false,
+                owner_scope: fn_scope_id,
+            });
+            params.push(FnArgDefn {
+                name: param_name,
+                variable_id,
+                position: position as u32,
+                type_id: target_type_id,
+                span,
+            });
+            self.scopes.add_variable(fn_scope_id, param_name, variable_id);
+        }
+        let function_id = self.add_function(TypedFunction {
+            name,
+            scope: fn_scope_id,
+            ret_type,
+            params,
+            type_params: Vec::new(),
+            block: None,
+            intrinsic_type: None,
+            linkage: Linkage::Standard,
+            specializations: Vec::new(),
+            parsed_id,
+            is_method_of: None,
+            kind: TypedFunctionKind::AbilityImpl(ability_id, target_type_id),
+            closure_env: None,
+            span,
+        });
+        let _ = self.scopes.add_function(impl_scope_id, function_name, function_id);
+        self.scopes.set_scope_owner_id(fn_scope_id, ScopeOwnerId::Function(function_id));
+        function_id
+    }
+
+    fn synth_derived_function_body(
+        &mut self,
+        ability_id: AbilityId,
+        function_id: FunctionId,
+    ) -> TyperResult<()> {
+        let function = self.get_function(function_id);
+        let span = function.span;
+        let fn_scope_id = function.scope;
+        let args: Vec<TypedExpr> = function
+            .params
+            .iter()
+            .map(|param| {
+                TypedExpr::Variable(VariableExpr {
+                    variable_id: param.variable_id,
+                    type_id: param.type_id,
+                    span,
+                })
+            })
+            .collect();
+        let mut block = self.synth_block(vec![], fn_scope_id, span);
+        let body_expr = match ability_id {
+            EQUALS_ABILITY_ID => self.derive_equals_expr(&args[0], &args[1], span)?,
+            SHOW_ABILITY_ID => self.derive_show_expr(&args[0], block.scope_id, span)?,
+            HASH_ABILITY_ID => self.derive_hash_expr(&args[0], span)?,
+            NEG_ABILITY_ID => self.synth_primitive_negation(args[0].clone(), span)?,
+            COMPARE_ABILITY_ID => {
+                let ordering_type_id = self.get_function(function_id).ret_type;
+                self.derive_scalar_compare_expr(&args[0], &args[1], ordering_type_id, span)
+            }
+            _ if SCALAR_ABILITIES.contains(&ability_id) => {
+                let kind = match ability_id {
+                    ADD_ABILITY_ID => BinaryOpKind::Add,
+                    SUB_ABILITY_ID => BinaryOpKind::Subtract,
+                    MUL_ABILITY_ID => BinaryOpKind::Multiply,
+                    DIV_ABILITY_ID => BinaryOpKind::Divide,
+                    _ => BinaryOpKind::Rem,
+                };
+                synth_binop(kind, args[0].get_type(), args[0].clone(), args[1].clone(), span)
+            }
+            _ => unreachable!("Not a derivable ability: {:?}", ability_id),
+        };
+        block.push_expr(body_expr);
+        self.get_function_mut(function_id).block = Some(block);
+        Ok(())
+    }
+
+    /// `self.a == other.a and self.b == other.b` for a struct. For an enum, checks `other`
+    /// for `self`'s variant, and then compares their payloads. Scalars use `==`
+    fn derive_equals_expr(
+        &mut self,
+        lhs: &TypedExpr,
+        rhs: &TypedExpr,
+        span: SpanId,
+    ) -> TyperResult<TypedExpr> {
+        match self.types.get(lhs.get_type()) {
+            Type::Unit(_) | Type::Char(_) | Type::Integer(_) | Type::Float(_) | Type::Bool(_) => {
+                Ok(self.synth_equals_binop(lhs.clone(), rhs.clone(), span))
+            }
+            Type::Struct(struct_type) => {
+                let fields = struct_type.fields.clone();
+                let mut result = None;
+                for field in fields.iter() {
+                    let lhs_field = synth_field_access(lhs, field, span);
+                    let rhs_field = synth_field_access(rhs, field, span);
+                    let field_equals = self.synth_derived_equals(lhs_field, rhs_field, span)?;
+                    result = Some(match result {
+                        None => field_equals,
+                        Some(acc) => synth_and(acc, field_equals, span),
+                    });
+                }
+                Ok(result.unwrap_or(TypedExpr::Bool(true, span)))
+            }
+            Type::Enum(enum_type) => {
+                let variants = enum_type.variants.clone();
+                let mut variant_exprs = Vec::with_capacity(variants.len());
+                for variant in variants.iter() {
+                    let rhs_is_variant = synth_is_variant(rhs, variant, span);
+                    let variant_equals = match variant.payload {
+                        None => rhs_is_variant,
+                        Some(payload_type_id) => {
+                            let lhs_payload =
+                                synth_get_payload(lhs, variant, payload_type_id, span);
+                            let rhs_payload =
+                                synth_get_payload(rhs, variant, payload_type_id, span);
+                            let payload_equals =
+                                self.synth_derived_equals(lhs_payload, rhs_payload, span)?;
+                            synth_and(rhs_is_variant, payload_equals, span)
+                        }
+                    };
+                    variant_exprs.push(variant_equals);
+                }
+                Ok(synth_variant_chain(lhs, &variants, variant_exprs, BOOL_TYPE_ID, span))
+            }
+            _ => unreachable!("Only structs, enums and scalars can be derived"),
+        }
+    }
+
+    /// `if self < other .Less else if self > other .Greater else .Equal`
+    fn derive_scalar_compare_expr(
+        &mut self,
+        lhs: &TypedExpr,
+        rhs: &TypedExpr,
+        ordering_type_id: TypeId,
+        span: SpanId,
+    ) -> TypedExpr {
+        let names = ["Less", "Equal", "Greater"].map(|name| self.ast.identifiers.intern(name));
+        let ordering = self.types.get(ordering_type_id).expect_enum();
+        let variant = |name: Identifier| {
+            let variant = ordering.variant_by_name(name).expect("Ordering has this variant");
+            TypedExpr::EnumConstructor(TypedEnumConstructor {
+                type_id: ordering_type_id,
+                variant_name: variant.name,
+                variant_index: variant.index,
+                payload: None,
+                span,
+            })
+        };
+        let greater = TypedExpr::If(Box::new(TypedIf {
+            condition: synth_binop(
+                BinaryOpKind::Greater,
+                BOOL_TYPE_ID,
+                lhs.clone(),
+                rhs.clone(),
+                span,
+            ),
+            consequent: variant(names[2]),
+            alternate: variant(names[1]),
+            ty: ordering_type_id,
+            span,
+        }));
+        TypedExpr::If(Box::new(TypedIf {
+            condition: synth_binop(
+                BinaryOpKind::Less,
+                BOOL_TYPE_ID,
+                lhs.clone(),
+                rhs.clone(),
+                span,
+            ),
+            consequent: variant(names[0]),
+            alternate: greater,
+            ty: ordering_type_id,
+            span,
+        }))
+    }
+
+    fn synth_derived_equals(
+        &mut self,
+        lhs: TypedExpr,
+        rhs: TypedExpr,
+        span: SpanId,
+    ) -> TyperResult<TypedExpr> {
+        match self.types.get(lhs.get_type()) {
+            Type::Unit(_)
+            | Type::Char(_)
+            | Type::Integer(_)
+            | Type::Float(_)
+            | Type::Bool(_)
+            | Type::TypeVariable(_) => Ok(self.synth_equals_binop(lhs, rhs, span)),
+            _ if self.is_anonymous_without_impl(lhs.get_type(), EQUALS_ABILITY_ID) => {
+                self.derive_equals_expr(&lhs, &rhs, span)
+            }
+            _ => self.synth_equals_call(lhs, rhs, span),
+        }
+    }
+
+    fn synth_derived_show(
+        &mut self,
+        value: TypedExpr,
+        scope_id: ScopeId,
+        span: SpanId,
+    ) -> TyperResult<TypedExpr> {
+        if self.is_anonymous_without_impl(value.get_type(), SHOW_ABILITY_ID) {
+            self.derive_show_expr(&value, scope_id, span)
+        } else {
+            self.synth_show_call(value)
+        }
+    }
+
+    /// Anonymous structs and enums, like `{ w: int, h: int }` in `enum Rect({ w: int, h: int })`,
+    /// have no definition to derive from, so a derived implementation recurses into them inline
+    fn is_anonymous_without_impl(&self, type_id: TypeId, ability_id: AbilityId) -> bool {
+        let is_anonymous = match self.types.get(type_id) {
+            Type::Struct(struct_type) => {
+                struct_type.type_defn_info.is_none() && struct_type.generic_instance_info.is_none()
+            }
+            Type::Enum(enum_type) => {
+                enum_type.type_defn_info.is_none() && enum_type.generic_instance_info.is_none()
+            }
+            _ => false,
+        };
+        is_anonymous && !self.type_implements_ability(type_id, ability_id)
+    }
+
+    /// `{ a: <a>, b: <b> }` for a struct, and `.Variant(<payload>)` for an enum; the same
+    /// syntax that constructs the value
+    fn derive_show_expr(
+        &mut self,
+        value: &TypedExpr,
+        scope_id: ScopeId,
+        span: SpanId,
+    ) -> TyperResult<TypedExpr> {
+        match self.types.get(value.get_type()) {
+            Type::Struct(struct_type) => {
+                let fields = struct_type.fields.clone();
+                if fields.is_empty() {
+                    return Ok(TypedExpr::Str("{}".to_string(), span));
+                }
+                let mut parts = Vec::with_capacity(fields.len() * 2 + 1);
+                for (index, field) in fields.iter().enumerate() {
+                    let separator = if index == 0 { "{ " } else { ", " };
+                    parts.push(TypedExpr::Str(
+                        format!("{}{}: ", separator, self.get_ident_str(field.name)),
+                        span,
+                    ));
+                    parts.push(self.synth_derived_show(
+                        synth_field_access(value, field, span),
+                        scope_id,
+                        span,
+                    )?);
+                }
+                parts.push(TypedExpr::Str(" }".to_string(), span));
+                self.synth_concat_all(parts, scope_id, span)
+            }
+            Type::Enum(enum_type) => {
+                let variants = enum_type.variants.clone();
+                let mut variant_exprs = Vec::with_capacity(variants.len());
+                for variant in variants.iter() {
+                    let variant_name = self.get_ident_str(variant.name).to_string();
+                    let variant_show = match variant.payload {
+                        None => TypedExpr::Str(format!(".{}", variant_name), span),
+                        Some(payload_type_id) => {
+                            let payload = synth_get_payload(value, variant, payload_type_id, span);
+                            let parts = vec![
+                                TypedExpr::Str(format!(".{}(", variant_name), span),
+                                self.synth_derived_show(payload, scope_id, span)?,
+                                TypedExpr::Str(")".to_string(), span),
+                            ];
+                            self.synth_concat_all(parts, scope_id, span)?
+                        }
+                    };
+                    variant_exprs.push(variant_show);
+                }
+                Ok(synth_variant_chain(value, &variants, variant_exprs, STRING_TYPE_ID, span))
+            }
+            _ => unreachable!("Only structs and enums can be derived"),
+        }
+    }
+
+    /// Combines the hashes of the fields of a struct, or of an enum's tag and payload, with
+    /// `h * 31 + x`. An integer or char is its own hash, as a u64
+    fn derive_hash_expr(&mut self, value: &TypedExpr, span: SpanId) -> TyperResult<TypedExpr> {
+        match self.types.get(value.get_type()) {
+            Type::Unit(_) => Ok(synth_u64(0, span)),
+            Type::Bool(_) => Ok(TypedExpr::If(Box::new(TypedIf {
+                condition: value.clone(),
+                consequent: synth_u64(1, span),
+                alternate: synth_u64(0, span),
+                ty: U64_TYPE_ID,
+                span,
+            }))),
+            Type::Char(_) => Ok(synth_cast_to_u64(value, CastType::IntegerExtendFromChar, span)),
+            Type::Integer(int_type) => {
+                let cast_type = match int_type.width() {
+                    NumericWidth::B64 => CastType::KnownNoOp,
+                    _ => CastType::IntegerExtend,
+                };
+                Ok(synth_cast_to_u64(value, cast_type, span))
+            }
+            Type::Struct(struct_type) => {
+                let fields = struct_type.fields.clone();
+                let mut result = synth_u64(7, span);
+                for field in fields.iter() {
+                    let field_hash =
+                        self.synth_derived_hash(synth_field_access(value, field, span), span)?;
+                    result = synth_hash_combine(result, field_hash, span);
+                }
+                Ok(result)
+            }
+            Type::Enum(enum_type) => {
+                let variants = enum_type.variants.clone();
+                let mut variant_exprs = Vec::with_capacity(variants.len());
+                for variant in variants.iter() {
+                    let tag_hash = synth_u64(variant.index as u64, span);
+                    let variant_hash = match variant.payload {
+                        None => tag_hash,
+                        Some(payload_type_id) => {
+                            let payload = synth_get_payload(value, variant, payload_type_id, span);
+                            let payload_hash = self.synth_derived_hash(payload, span)?;
+                            synth_hash_combine(tag_hash, payload_hash, span)
+                        }
+                    };
+                    variant_exprs.push(variant_hash);
+                }
+                Ok(synth_variant_chain(value, &variants, variant_exprs, U64_TYPE_ID, span))
+            }
+            _ => unreachable!("Only structs, enums and scalars can be derived"),
+        }
+    }
+
+    fn synth_derived_hash(&mut self, value: TypedExpr, span: SpanId) -> TyperResult<TypedExpr> {
+        if self.is_anonymous_without_impl(value.get_type(), HASH_ABILITY_ID) {
+            return self.derive_hash_expr(&value, span);
+        }
+        let hash_ident = self.ast.identifiers.intern("hash");
+        let hash_function_id =
+            self.expect_ability_impl_function(HASH_ABILITY_ID, value.get_type(), hash_ident, span)?;
+        Ok(TypedExpr::FunctionCall(Call {
+            callee: Callee::StaticFunction(hash_function_id),
+            args: vec![value],
+            type_args: Vec::new(),
+            ret_type: U64_TYPE_ID,
+            span,
+        }))
+    }
+
+    fn synth_concat_all(
+        &mut self,
+        parts: Vec<TypedExpr>,
+        scope_id: ScopeId,
+        span: SpanId,
+    ) -> TyperResult<TypedExpr> {
+        let parts_array = self.synth_array_literal(parts, STRING_TYPE_ID, scope_id, span)?;
+        let concat_all = NamespacedIdentifier {
+            namespaces: vec![self.ast.identifiers.intern("string")],
+            name: self.ast.identifiers.intern("concatAll"),
+            span,
+        };
+        self.synth_function_call(concat_all, span, scope_id, Some((vec![], vec![parts_array])))
+    }
+}
+
+fn synth_cast_to_u64(value: &TypedExpr, cast_type: CastType, span: SpanId) -> TypedExpr {
+    TypedExpr::Cast(TypedCast {
+        base_expr: Box::new(value.clone()),
+        target_type_id: U64_TYPE_ID,
+        cast_type,
+        span,
+    })
+}
+
+fn synth_field_access(base: &TypedExpr, field: &StructTypeField, span: SpanId) -> TypedExpr {
+    TypedExpr::StructFieldAccess(FieldAccess {
+        base: Box::new(base.clone()),
+        target_field: field.name,
+        target_field_index: field.index,
+        ty: field.type_id,
+        span,
+    })
+}
+
+fn synth_is_variant(base: &TypedExpr, variant: &TypedEnumVariant, span: SpanId) -> TypedExpr {
+    TypedExpr::EnumIsVariant(TypedEnumIsVariantExpr {
+        target_expr: Box::new(base.clone()),
+        variant_name: variant.name,
+        variant_index: variant.index,
+        span,
+    })
+}
+
+fn synth_get_payload(
+    base: &TypedExpr,
+    variant: &TypedEnumVariant,
+    payload_type_id: TypeId,
+    span: SpanId,
+) -> TypedExpr {
+    TypedExpr::EnumGetPayload(GetEnumPayload {
+        target_expr: Box::new(base.clone()),
+        payload_type_id,
+        variant_name: variant.name,
+        variant_index: variant.index,
+        span,
+    })
+}
+
+/// `if base is .A { <a> } else if base is .B { <b> } else { <c> }`; the last variant needs no
+/// check, since the chain covers every variant
+fn synth_variant_chain(
+    base: &TypedExpr,
+    variants: &[TypedEnumVariant],
+    mut variant_exprs: Vec<TypedExpr>,
+    ty: TypeId,
+    span: SpanId,
+) -> TypedExpr {
+    let mut result = variant_exprs.pop().expect("enums have at least one variant");
+    while let Some(consequent) = variant_exprs.pop() {
+        let variant = &variants[variant_exprs.len()];
+        result = TypedExpr::If(Box::new(TypedIf {
+            condition: synth_is_variant(base, variant, span),
+            consequent,
+            alternate: result,
+            ty,
+            span,
+        }));
     }
+    result
+}
+
+fn synth_and(lhs: TypedExpr, rhs: TypedExpr, span: SpanId) -> TypedExpr {
+    synth_binop(BinaryOpKind::And, BOOL_TYPE_ID, lhs, rhs, span)
+}
+
+fn synth_binop(
+    kind: BinaryOpKind,
+    ty: TypeId,
+    lhs: TypedExpr,
+    rhs: TypedExpr,
+    span: SpanId,
+) -> TypedExpr {
+    TypedExpr::BinaryOp(BinaryOp { kind, ty, lhs: Box::new(lhs), rhs: Box::new(rhs), span })
+}
+
+fn synth_u64(value: u64, span: SpanId) -> TypedExpr {
+    TypedExpr::Integer(TypedIntegerExpr { value: TypedIntegerValue::U64(value), span })
+}
+
+/// `hash * 31 + next`
+fn synth_hash_combine(hash: TypedExpr, next: TypedExpr, span: SpanId) -> TypedExpr {
+    let multiplied = TypedExpr::BinaryOp(BinaryOp {
+        kind: BinaryOpKind::Multiply,
+        ty: U64_TYPE_ID,
+        lhs: Box::new(hash),
+        rhs: Box::new(synth_u64(31, span)),
+        span,
+    });
+    TypedExpr::BinaryOp(BinaryOp {
+        kind: BinaryOpKind::Add,
+        ty: U64_TYPE_ID,
+        lhs: Box::new(multiplied),
+        rhs: Box::new(next),
+        span,
+    })
 }
@@ -244,6 +244,7 @@ impl TypedModule {
                 Ok(())
             }
             Type::Never(_) => writ.write_str("never"),
+            Type::Error => writ.write_str("{error}"),
             Type::OpaqueAlias(opaque) => {
                 writ.write_str(self.get_ident_str(opaque.type_defn_info.name))?;
                 writ.write_str("(")?;
@@ -407,6 +408,10 @@ impl TypedModule {
                 writ.write_str(" ")?;
                 self.display_block(&while_loop.block, writ, indentation + 1)
             }
+            TypedStmt::Defer(defer) => {
+                writ.write_str("defer ")?;
+                self.display_expr(&defer.expr, writ, indentation)
+            }
         }
     }
 
@@ -453,8 +458,16 @@ impl TypedModule {
                 writ.write_str(&self.get_ident_str(field_access.target_field))
             }
             TypedExpr::FunctionCall(fn_call) => {
-                let function = self.get_function(fn_call.callee_function_id);
-                writ.write_str(&self.get_ident_str(function.name))?;
+                match fn_call.callee {
+                    Callee::StaticFunction(function_id) => {
+                        let function = self.get_function(function_id);
+                        writ.write_str(&self.get_ident_str(function.name))?;
+                    }
+                    Callee::DynamicFunction(variable_id) => {
+                        let variable = self.variables.get_variable(variable_id);
+                        writ.write_str(&self.get_ident_str(variable.name))?;
+                    }
+                }
                 writ.write_str("(")?;
                 for (idx, arg) in fn_call.args.iter().enumerate() {
                     if idx > 0 {
@@ -515,6 +528,20 @@ impl TypedModule {
                 self.display_expr(&ret.value, writ, indentation)?;
                 writ.write_char(')')
             }
+            TypedExpr::FunctionReference(fun_ref) => {
+                let function = self.get_function(fun_ref.function_id);
+                writ.write_str(&self.get_ident_str(function.name))
+            }
+            TypedExpr::Break(brk) => {
+                writ.write_str("break")?;
+                if let Some(value) = &brk.value {
+                    writ.write_char(' ')?;
+                    self.display_expr(value, writ, indentation)?;
+                }
+                Ok(())
+            }
+            TypedExpr::Continue(_) => writ.write_str("continue"),
+            TypedExpr::Error(_) => writ.write_str("<error>"),
         }
     }
 
@@ -534,13 +561,14 @@ impl TypedModule {
             PatternConstructor::Unit => writ.write_str("()"),
             PatternConstructor::BoolTrue => writ.write_str("true"),
             PatternConstructor::BoolFalse => writ.write_str("false"),
-            PatternConstructor::None => writ.write_str("None"),
-            PatternConstructor::Char => writ.write_str("'<char>'"),
-            PatternConstructor::String => writ.write_str("\"<string>\""),
-            PatternConstructor::Int => writ.write_str("<int>"),
-            PatternConstructor::TypeVariable => writ.write_str("<tvar>"),
+            PatternConstructor::None => writ.write_str(".None"),
+            // These can't be enumerated, so any value will do as a witness
+            PatternConstructor::Char => writ.write_str("_"),
+            PatternConstructor::String => writ.write_str("_"),
+            PatternConstructor::Int => writ.write_str("_"),
+            PatternConstructor::TypeVariable => writ.write_str("_"),
             PatternConstructor::Some(inner) => {
-                writ.write_str("Some(")?;
+                writ.write_str(".Some(")?;
                 self.display_pattern_ctor(inner, writ)?;
                 writ.write_str(")")?;
                 Ok(())
@@ -562,6 +590,7 @@ impl TypedModule {
                 Ok(())
             }
             PatternConstructor::Enum { variant_name, inner } => {
+                writ.write_char('.')?;
                 writ.write_str(self.get_ident_str(*variant_name))?;
                 if let Some(payload) = inner.as_ref() {
                     writ.write_str("(")?;
@@ -570,6 +599,22 @@ impl TypedModule {
                 };
                 Ok(())
             }
+            PatternConstructor::Array { elements, has_more } => {
+                writ.write_str("[")?;
+                for (index, element) in elements.iter().enumerate() {
+                    if index > 0 {
+                        writ.write_str(", ")?;
+                    }
+                    self.display_pattern_ctor(element, writ)?;
+                }
+                if *has_more {
+                    if !elements.is_empty() {
+                        writ.write_str(", ")?;
+                    }
+                    writ.write_str("_, ..")?;
+                }
+                writ.write_str("]")
+            }
         }
     }
 
@@ -593,6 +638,7 @@ impl TypedModule {
             TypedPattern::Variable(var) => writ.write_str(self.get_ident_str(var.name)),
             TypedPattern::Wildcard(_) => writ.write_str("_"),
             TypedPattern::Enum(enum_pat) => {
+                writ.write_char('.')?;
                 writ.write_str(self.get_ident_str(enum_pat.variant_tag_name))?;
                 if let Some(payload) = enum_pat.payload.as_ref() {
                     writ.write_str("(")?;
@@ -617,6 +663,47 @@ impl TypedModule {
                 writ.write_str("}")?;
                 Ok(())
             }
+            TypedPattern::Or(or_pat) => {
+                for (index, alternative) in or_pat.alternatives.iter().enumerate() {
+                    if index > 0 {
+                        writ.write_str(" | ")?;
+                    }
+                    self.display_pattern(alternative, writ)?;
+                }
+                Ok(())
+            }
+            TypedPattern::Binding(binding_pat) => {
+                writ.write_str(self.get_ident_str(binding_pat.name))?;
+                writ.write_str(" @ ")?;
+                self.display_pattern(&binding_pat.inner, writ)
+            }
+            TypedPattern::Array(array_pat) => {
+                writ.write_str("[")?;
+                for (index, element_pat) in array_pat.elements.iter().enumerate() {
+                    if index > 0 {
+                        writ.write_str(", ")?;
+                    }
+                    self.display_pattern(element_pat, writ)?;
+                }
+                if let Some(rest) = array_pat.rest.as_ref() {
+                    if !array_pat.elements.is_empty() {
+                        writ.write_str(", ")?;
+                    }
+                    writ.write_str("..")?;
+                    if let TypedPattern::Variable(var) = rest.as_ref() {
+                        writ.write_str(self.get_ident_str(var.name))?;
+                    }
+                }
+                writ.write_str("]")
+            }
+            TypedPattern::StringPrefix(prefix_pat) => {
+                write!(writ, "\"{}\" ++ ", prefix_pat.prefix)?;
+                self.display_pattern(&prefix_pat.rest, writ)
+            }
+            TypedPattern::Range(range_pat) => {
+                let op = if range_pat.inclusive { "..=" } else { ".." };
+                write!(writ, "{}{}{}", range_pat.start, op, range_pat.end)
+            }
         }
     }
 
@@ -27,6 +27,7 @@ pub enum ScopeType {
     TypeDefn,
     AbilityDefn,
     AbilityImpl,
+    DeferBody,
 }
 
 impl ScopeType {
@@ -43,6 +44,7 @@ impl ScopeType {
             ScopeType::TypeDefn => "type_defn",
             ScopeType::AbilityDefn => "ability_defn",
             ScopeType::AbilityImpl => "ability_impl",
+            ScopeType::DeferBody => "defer",
         }
     }
 }
@@ -312,6 +314,73 @@ impl Scopes {
         }
     }
 
+    /// Finds the body of the innermost loop around `scope_id`, for `break` and `continue`.
+    /// Loops outside of the current function, or lambda, don't count
+    pub fn find_loop_scope(&self, scope_id: ScopeId) -> Option<ScopeId> {
+        let mut current = scope_id;
+        loop {
+            let scope = self.get_scope(current);
+            match scope.scope_type {
+                ScopeType::WhileBody => return Some(current),
+                ScopeType::FunctionScope => return None,
+                _ => current = scope.parent?,
+            }
+        }
+    }
+
+    /// Finds the body of a `defer` around `scope_id`, stopping at `outer_scope` if given,
+    /// and otherwise at the enclosing function. Control flow may not leave a defer body
+    pub fn find_defer_scope(
+        &self,
+        scope_id: ScopeId,
+        outer_scope: Option<ScopeId>,
+    ) -> Option<ScopeId> {
+        let mut current = scope_id;
+        while Some(current) != outer_scope {
+            let scope = self.get_scope(current);
+            match scope.scope_type {
+                ScopeType::DeferBody => return Some(current),
+                ScopeType::FunctionScope => return None,
+                _ => current = scope.parent?,
+            }
+        }
+        None
+    }
+
+    /// If a variable owned by `owner_scope` is referenced from `scope_id` across function
+    /// boundaries, returns every function scope that has to capture it, innermost first.
+    /// Variables that live outside of any function, like constants, are never captured
+    pub fn find_capturing_function_scopes(
+        &self,
+        scope_id: ScopeId,
+        owner_scope: ScopeId,
+    ) -> Vec<ScopeId> {
+        let mut capturing_scopes = Vec::new();
+        let mut current = scope_id;
+        while current != owner_scope {
+            let scope = self.get_scope(current);
+            if scope.scope_type == ScopeType::FunctionScope {
+                capturing_scopes.push(current);
+            }
+            let Some(parent) = scope.parent else {
+                return Vec::new();
+            };
+            current = parent;
+        }
+        if capturing_scopes.is_empty() {
+            return capturing_scopes;
+        }
+        let mut current = Some(owner_scope);
+        while let Some(scope_id) = current {
+            let scope = self.get_scope(scope_id);
+            if scope.scope_type == ScopeType::FunctionScope {
+                return capturing_scopes;
+            }
+            current = scope.parent;
+        }
+        Vec::new()
+    }
+
     pub fn traverse_namespace_chain(
         &self,
         scope_id: ScopeId,
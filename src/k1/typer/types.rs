@@ -79,17 +79,21 @@ pub const I16_TYPE_ID: TypeId = TypeId(5);
 pub const I32_TYPE_ID: TypeId = TypeId(6);
 pub const I64_TYPE_ID: TypeId = TypeId(7);
 
-pub const UNIT_TYPE_ID: TypeId = TypeId(8);
-pub const CHAR_TYPE_ID: TypeId = TypeId(9);
-pub const BOOL_TYPE_ID: TypeId = TypeId(10);
-pub const NEVER_TYPE_ID: TypeId = TypeId(11);
-pub const POINTER_TYPE_ID: TypeId = TypeId(12);
-pub const F32_TYPE_ID: TypeId = TypeId(13);
-pub const F64_TYPE_ID: TypeId = TypeId(14);
-
-pub const ARRAY_TYPE_ID: TypeId = TypeId(17);
-pub const STRING_TYPE_ID: TypeId = TypeId(18);
-pub const OPTIONAL_TYPE_ID: TypeId = TypeId(23);
+pub const ERROR_TYPE_ID: TypeId = TypeId(8);
+
+pub const UNIT_TYPE_ID: TypeId = TypeId(9);
+pub const CHAR_TYPE_ID: TypeId = TypeId(10);
+pub const BOOL_TYPE_ID: TypeId = TypeId(11);
+pub const NEVER_TYPE_ID: TypeId = TypeId(12);
+pub const POINTER_TYPE_ID: TypeId = TypeId(13);
+pub const F32_TYPE_ID: TypeId = TypeId(14);
+pub const F64_TYPE_ID: TypeId = TypeId(15);
+
+pub const ARRAY_TYPE_ID: TypeId = TypeId(18);
+pub const STRING_TYPE_ID: TypeId = TypeId(19);
+pub const OPTIONAL_TYPE_ID: TypeId = TypeId(24);
+pub const RANGE_TYPE_ID: TypeId = TypeId(31);
+pub const RESULT_TYPE_ID: TypeId = TypeId(37);
 
 #[derive(Debug, Clone)]
 pub struct ArrayType {
@@ -109,6 +113,17 @@ pub struct OptionalType {
     pub inner_type: TypeId,
 }
 
+#[derive(Debug, Clone)]
+pub struct RangeType {
+    pub element_type: TypeId,
+}
+
+#[derive(Debug, Clone)]
+pub struct ResultType {
+    pub ok_type: TypeId,
+    pub err_type: TypeId,
+}
+
 #[derive(Debug, Clone)]
 pub struct ReferenceType {
     pub inner_type: TypeId,
@@ -288,6 +303,9 @@ pub enum Type {
     Generic(GenericType),
     Function(FunctionType),
     RecursiveReference(RecursiveReference),
+    /// The type of an expression that failed to typecheck. It unifies with anything, so that
+    /// one mistake doesn't produce a cascade of errors from everything downstream of it
+    Error,
 }
 
 impl Type {
@@ -309,6 +327,7 @@ impl Type {
             Type::Generic(gen) => Some(gen.ast_id.into()),
             Type::Function(_fun) => None,
             Type::RecursiveReference(r) => Some(ParsedId::TypeDefn(r.parsed_id)),
+            Type::Error => None,
         }
     }
 
@@ -326,6 +345,37 @@ impl Type {
         }
     }
 
+    pub fn as_range_instance(&self) -> Option<RangeType> {
+        if let Type::Struct(s) = self {
+            s.generic_instance_info.as_ref().and_then(|spec_info| {
+                if spec_info.generic_parent == RANGE_TYPE_ID {
+                    Some(RangeType { element_type: spec_info.param_values[0] })
+                } else {
+                    None
+                }
+            })
+        } else {
+            None
+        }
+    }
+
+    pub fn as_result(&self) -> Option<ResultType> {
+        if let Type::Enum(e) = self {
+            e.generic_instance_info.as_ref().and_then(|spec_info| {
+                if spec_info.generic_parent == RESULT_TYPE_ID {
+                    Some(ResultType {
+                        ok_type: spec_info.param_values[0],
+                        err_type: spec_info.param_values[1],
+                    })
+                } else {
+                    None
+                }
+            })
+        } else {
+            None
+        }
+    }
+
     pub fn as_optional(&self) -> Option<OptionalType> {
         if let Type::Enum(e) = self {
             e.generic_instance_info.as_ref().and_then(|spec_info| {
@@ -534,6 +584,7 @@ impl Types {
             Type::Generic(_gen) => None,
             Type::Function(_) => None,
             Type::RecursiveReference(_) => None,
+            Type::Error => None,
         }
     }
 
@@ -555,6 +606,7 @@ impl Types {
             Type::Generic(gen) => Some(&gen.type_defn_info),
             Type::Function(f) => f.defn_info.as_ref(),
             Type::RecursiveReference(_) => None,
+            Type::Error => None,
         }
     }
 
@@ -640,6 +692,7 @@ impl Types {
                 return false;
             }
             Type::RecursiveReference(_rr) => false,
+            Type::Error => false,
         }
     }
 
@@ -681,6 +734,7 @@ impl Types {
             Type::Generic(_gen) => None,
             Type::Function(_fun) => None,
             Type::RecursiveReference(_) => None,
+            Type::Error => None,
         }
     }
 
@@ -749,11 +803,12 @@ impl Types {
             Type::Generic(_) => unreachable!(),
             Type::Function(_f) => unreachable!(),
             Type::RecursiveReference(_rr) => (),
+            Type::Error => (),
         }
     }
 
     fn type_id_eq(&self, type1: TypeId, type2: TypeId) -> bool {
-        self.type_eq(&self.get(type1), &self.get(type2))
+        type1 == type2 || self.type_eq(&self.get(type1), &self.get(type2))
     }
 
     fn type_eq(&self, type1: &Type, type2: &Type) -> bool {
@@ -805,8 +860,11 @@ impl Types {
             // We never really want to de-dupe this type as its inherently unique
             (Type::Generic(_g1), Type::Generic(_g2)) => false,
             (Type::Function(f1), Type::Function(f2)) => {
-                // I guess functions can totally share a FunctionType if they share
-                // the exact shape
+                // Anonymous function types, like those of lambdas and function values,
+                // share a FunctionType if they share the exact shape
+                if f1.defn_info.is_some() || f2.defn_info.is_some() {
+                    return false;
+                }
                 if self.type_id_eq(f1.return_type, f2.return_type) {
                     if f1.params.len() == f2.params.len() {
                         return f1
@@ -0,0 +1,48 @@
+//! With `--message-format=json`, every line the compiler writes to stderr must be a diagnostic
+//! that a tool can parse on its own
+
+use std::process::Command;
+
+fn compile_json(file: &str, extra_args: &[&str]) -> Vec<serde_json::Value> {
+    let output = Command::new(env!("CARGO_BIN_EXE_compiler"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .env_remove("RUST_LOG")
+        .args(["--message-format", "json"])
+        .args(extra_args)
+        .arg(file)
+        .output()
+        .expect("failed to run the compiler");
+    assert!(!output.status.success(), "{file} was expected to fail compilation");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    stderr
+        .lines()
+        .map(|line| {
+            serde_json::from_str::<serde_json::Value>(line)
+                .unwrap_or_else(|e| panic!("{file}: stderr line is not json ({e}): {line}"))
+        })
+        .collect()
+}
+
+fn severities(diagnostics: &[serde_json::Value], severity: &str) -> usize {
+    diagnostics.iter().filter(|d| d["severity"] == severity).count()
+}
+
+#[test]
+fn type_errors() {
+    let diagnostics = compile_json("test_src/typer_multiple_errors.k1", &[]);
+    assert_eq!(severities(&diagnostics, "error"), 10);
+}
+
+#[test]
+fn parse_errors() {
+    let diagnostics = compile_json("test_src/lex_error.k1", &[]);
+    assert_eq!(diagnostics[0]["message"], "Unterminated block comment");
+    assert_eq!(severities(&diagnostics, "error"), 1);
+}
+
+#[test]
+fn denied_warnings() {
+    let diagnostics = compile_json("test_src/lint_deny_warnings.k1", &["--deny-warnings"]);
+    assert_eq!(severities(&diagnostics, "error"), 0);
+    assert_eq!(severities(&diagnostics, "warning"), 2);
+}